@@ -0,0 +1,174 @@
+/*
+    Notes of the user at specific times of a recording.
+    They get stored in the metadata of the recording next to the events of the game (which they never change).
+*/
+
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::{helpers, recorder::data::Annotation};
+
+/// An annotation as it is sent by the UI when adding or editing one.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnnotationInput {
+    pub time: f64,
+    #[serde(default)]
+    pub end: Option<f64>,
+    pub text: String,
+    #[serde(default)]
+    pub category: Option<String>,
+}
+
+impl AnnotationInput {
+    fn validate(&self) -> Result<(), String> {
+        if !self.time.is_finite() || self.time < 0.0 {
+            return Err(format!("invalid annotation time {}", self.time));
+        }
+        if self.end.is_some_and(|end| !end.is_finite() || end < self.time) {
+            return Err("annotation ends before it starts".into());
+        }
+        if self.text.trim().is_empty() {
+            return Err("annotation text is empty".into());
+        }
+        Ok(())
+    }
+}
+
+/// The annotations of a recording sorted by time.
+pub fn list(recording: &Path) -> Vec<Annotation> {
+    helpers::read_metadata(recording)
+        .and_then(|metadata| metadata.get("annotations").cloned())
+        .and_then(|annotations| serde_json::from_value(annotations).ok())
+        .unwrap_or_default()
+}
+
+pub fn add(recording: &Path, input: AnnotationInput) -> Result<Annotation, String> {
+    input.validate()?;
+    modify(recording, |annotations| {
+        let id = annotations
+            .iter()
+            .map(|annotation| annotation.id + 1)
+            .max()
+            .unwrap_or(0);
+        let annotation = Annotation {
+            id,
+            time: input.time,
+            end: input.end,
+            text: input.text,
+            category: input.category,
+        };
+        annotations.push(annotation.clone());
+        Ok(annotation)
+    })
+}
+
+pub fn update(recording: &Path, id: u64, input: AnnotationInput) -> Result<Annotation, String> {
+    input.validate()?;
+    modify(recording, |annotations| {
+        let Some(annotation) = annotations.iter_mut().find(|annotation| annotation.id == id) else {
+            return Err(format!("annotation {id} doesn't exist"));
+        };
+        annotation.time = input.time;
+        annotation.end = input.end;
+        annotation.text = input.text;
+        annotation.category = input.category;
+        Ok(annotation.clone())
+    })
+}
+
+pub fn delete(recording: &Path, id: u64) -> Result<(), String> {
+    modify(recording, |annotations| {
+        let len = annotations.len();
+        annotations.retain(|annotation| annotation.id != id);
+        if annotations.len() == len {
+            return Err(format!("annotation {id} doesn't exist"));
+        }
+        Ok(())
+    })
+}
+
+/// Changes the annotations and writes them back to the metadata. Everything else in the metadata stays as it is.
+fn modify<T>(recording: &Path, f: impl FnOnce(&mut Vec<Annotation>) -> Result<T, String>) -> Result<T, String> {
+    helpers::update_metadata(recording, |metadata| {
+        let mut annotations: Vec<Annotation> = match metadata.get("annotations") {
+            Some(annotations) => serde_json::from_value(annotations.clone()).map_err(|e| e.to_string())?,
+            None => Vec::new(),
+        };
+        let result = f(&mut annotations)?;
+        annotations.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+        let annotations = serde_json::to_value(annotations).map_err(|e| e.to_string())?;
+        metadata.insert("annotations".into(), annotations);
+        Ok(result)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use serde_json::Value;
+
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("league_record_test_{name}_{}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn events(recording: &Path) -> String {
+        let metadata: Value = serde_json::from_slice(&fs::read(recording.with_extension("json")).unwrap()).unwrap();
+        serde_json::to_string(&metadata["events"]).unwrap()
+    }
+
+    fn input(time: f64, text: &str) -> AnnotationInput {
+        AnnotationInput {
+            time,
+            end: None,
+            text: text.into(),
+            category: None,
+        }
+    }
+
+    #[test]
+    fn never_changes_the_events() {
+        let dir = test_dir("annotations");
+        let recording = dir.join("game.mp4");
+        let metadata = r#"{"schemaVersion":3,"win":true,"events":[
+            {"name":"Kill","time":312.123456789,"victim":"Jinx"},{"name":"Dragon","time":600.0}
+        ]}"#;
+        fs::write(recording.with_extension("json"), metadata).unwrap();
+        let original = events(&recording);
+
+        let first = add(&recording, input(500.0, "first")).unwrap();
+        let second = add(&recording, input(100.0, "second")).unwrap();
+        let third = add(&recording, input(300.0, "third")).unwrap();
+        assert_eq!([first.id, second.id, third.id], [0, 1, 2]);
+        assert_eq!(events(&recording), original);
+
+        update(&recording, second.id, input(700.0, "moved")).unwrap();
+        assert_eq!(events(&recording), original);
+
+        delete(&recording, first.id).unwrap();
+        assert_eq!(events(&recording), original);
+        // the ids of deleted annotations don't collide with the remaining ones
+        let fourth = add(&recording, input(0.0, "fourth")).unwrap();
+        assert_eq!(fourth.id, 3);
+
+        let annotations = list(&recording);
+        let texts: Vec<(u64, &str)> = annotations.iter().map(|a| (a.id, a.text.as_str())).collect();
+        assert_eq!(texts, [(3, "fourth"), (2, "third"), (1, "moved")]);
+        assert_eq!(events(&recording), original);
+
+        assert!(delete(&recording, first.id).is_err());
+        assert!(update(&recording, first.id, input(1.0, "gone")).is_err());
+        assert!(add(&recording, input(-1.0, "negative")).is_err());
+        assert!(add(&recording, input(1.0, " ")).is_err());
+        assert_eq!(list(&recording).len(), 3);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -4,19 +4,23 @@
     return just a value
 */
 
-use std::{
-    cmp::Ordering,
-    fs::{metadata, remove_file, File},
-    io::BufReader,
-    path::PathBuf,
-};
+use std::path::PathBuf;
 
 use crate::{
-    helpers::{self, compare_time, get_recordings, show_window},
+    annotations::{self, AnnotationInput},
+    helpers::{self, show_window, MetadataError, ReelSegment},
+    layout,
+    library::{Library, RecordingPage, RecordingQuery},
+    recorder::{
+        data::{Annotation, GameData},
+        recovery::BrokenRecordings,
+    },
+    retention::{self, Deletion},
     state::{AssetPort, MarkerFlags, Settings, SettingsFile},
+    statistics::{self, Statistics, StatisticsQuery},
+    trash::{self, TrashItem},
 };
-use serde_json::Value;
-use tauri::{api::shell, AppHandle, Manager, State};
+use tauri::{api::shell, async_runtime, AppHandle, Manager, State};
 
 #[tauri::command]
 pub async fn show_app_window(app_handle: AppHandle) {
@@ -51,30 +55,38 @@ pub fn get_asset_port(port_state: State<'_, AssetPort>) -> u16 {
 }
 
 #[tauri::command]
-pub fn get_recordings_size(settings_state: State<'_, Settings>) -> f32 {
-    let mut size = 0;
-    for file in get_recordings(&settings_state.get_recordings_path()) {
-        if let Ok(metadata) = metadata(file) {
-            size += metadata.len();
-        }
-    }
-    size as f32 / 1_000_000_000.0 // in Gigabyte
+pub fn get_recordings_size(library: State<'_, Library>) -> f32 {
+    library.total_size() as f32 / 1_000_000_000.0 // in Gigabyte
 }
 
 #[tauri::command]
-pub fn get_recordings_list(settings_state: State<'_, Settings>) -> Vec<String> {
-    let mut recordings = get_recordings(&settings_state.get_recordings_path());
-    // sort by time created (index 0 is newest)
-    recordings.sort_by(|a, b| compare_time(a, b).unwrap_or(Ordering::Equal));
-    let mut ret = Vec::<String>::new();
-    for path in recordings {
-        if let Some(os_str_ref) = path.file_name() {
-            if let Ok(filename) = os_str_ref.to_os_string().into_string() {
-                ret.push(filename);
-            }
-        }
-    }
-    ret
+pub fn get_recordings_list(library: State<'_, Library>) -> Vec<String> {
+    // sorted by time created (index 0 is newest)
+    library.filenames()
+}
+
+#[tauri::command]
+pub fn query_recordings(query: RecordingQuery, library: State<'_, Library>) -> RecordingPage {
+    library.query(&query)
+}
+
+#[tauri::command]
+pub fn get_statistics(query: StatisticsQuery, library: State<'_, Library>) -> Statistics {
+    statistics::statistics(&library, &query)
+}
+
+#[tauri::command]
+pub fn export_statistics_csv(
+    query: StatisticsQuery,
+    library: State<'_, Library>,
+    state: State<'_, Settings>,
+) -> Result<String, String> {
+    statistics::export_csv(&library, &state.get_recordings_path(), &query)
+}
+
+#[tauri::command]
+pub fn save_replay_clip(app_handle: AppHandle) {
+    app_handle.trigger_global("save_replay_clip", None);
 }
 
 #[tauri::command]
@@ -89,31 +101,182 @@ pub fn open_recordings_folder(app_handle: AppHandle, state: State<'_, Settings>)
 #[tauri::command]
 pub fn delete_video(video: String, state: State<'_, Settings>) -> bool {
     // remove video
-    let mut path = state.get_recordings_path();
-    path.push(PathBuf::from(&video));
-    if remove_file(&path).is_err() {
-        // if video delete fails return and dont delete json file
+    let rec_folder = state.get_recordings_path();
+    let Some(path) = helpers::recording_path(&rec_folder, &video) else {
+        log::warn!("refusing to delete invalid recording '{video}'");
+        return false;
+    };
+    if helpers::is_protected(&path) {
+        log::warn!("refusing to delete protected recording {path:?}");
         return false;
     }
+    // deleted recordings can be restored from the trash
+    let deleted = trash::move_to_trash(&rec_folder, &path).is_ok();
+    if let (true, Some(folder)) = (deleted, path.parent()) {
+        helpers::remove_empty_folders(&rec_folder, folder);
+    }
+    deleted
+}
+
+#[tauri::command]
+pub async fn export_clip(video: String, start: f64, end: f64, state: State<'_, Settings>) -> Result<String, String> {
+    let rec_folder = state.get_recordings_path();
+    // copying a few GB can take a while
+    async_runtime::spawn_blocking(move || helpers::export_clip(&rec_folder, &video, start, end))
+        .await
+        .map_err(|e| e.to_string())?
+}
 
-    // remove json file if it exists
-    path.set_extension("json");
-    _ = remove_file(path);
-    true
+#[tauri::command]
+pub async fn create_highlight_reel(segments: Vec<ReelSegment>, state: State<'_, Settings>) -> Result<String, String> {
+    let rec_folder = state.get_recordings_path();
+    async_runtime::spawn_blocking(move || helpers::create_highlight_reel(&rec_folder, &segments))
+        .await
+        .map_err(|e| e.to_string())?
 }
 
 #[tauri::command]
-pub fn get_metadata(video: String, state: State<'_, Settings>) -> Value {
-    let mut path = state.get_recordings_path();
-    path.push(PathBuf::from(video));
-    path.set_extension("json");
-    let reader = match File::open(path) {
-        Ok(file) => BufReader::new(file),
-        Err(_) => return Value::Null,
+pub fn get_metadata(video: String, state: State<'_, Settings>) -> Result<GameData, MetadataError> {
+    let Some(path) = helpers::recording_path(&state.get_recordings_path(), &video) else {
+        log::warn!("refusing to read the metadata of invalid recording '{video}'");
+        return Err(MetadataError::InvalidRecording);
     };
+    // falls back to the metadata embedded in the video if the json file is missing
+    helpers::read_typed_metadata(&path).map_err(|e| {
+        log::warn!("unable to read metadata of {path:?}: {e}");
+        e
+    })
+}
+
+#[tauri::command]
+pub fn set_tags(video: String, tags: Vec<String>, state: State<'_, Settings>) -> Result<Vec<String>, String> {
+    helpers::set_tags(&valid_recording_path(&state, &video)?, &tags)
+}
+
+#[tauri::command]
+pub fn set_favorite(video: String, favorite: bool, state: State<'_, Settings>) -> Result<(), String> {
+    helpers::set_flag(&valid_recording_path(&state, &video)?, "favorite", favorite)
+}
+
+#[tauri::command]
+pub fn set_protected(video: String, protected: bool, state: State<'_, Settings>) -> Result<(), String> {
+    helpers::set_flag(&valid_recording_path(&state, &video)?, "protected", protected)
+}
+
+#[tauri::command]
+pub fn get_annotations(video: String, state: State<'_, Settings>) -> Vec<Annotation> {
+    match helpers::recording_path(&state.get_recordings_path(), &video) {
+        Some(path) => annotations::list(&path),
+        None => {
+            log::warn!("refusing to read the annotations of invalid recording '{video}'");
+            Vec::new()
+        }
+    }
+}
+
+#[tauri::command]
+pub fn add_annotation(
+    video: String,
+    annotation: AnnotationInput,
+    state: State<'_, Settings>,
+) -> Result<Annotation, String> {
+    annotations::add(&valid_recording_path(&state, &video)?, annotation)
+}
+
+#[tauri::command]
+pub fn update_annotation(
+    video: String,
+    id: u64,
+    annotation: AnnotationInput,
+    state: State<'_, Settings>,
+) -> Result<Annotation, String> {
+    annotations::update(&valid_recording_path(&state, &video)?, id, annotation)
+}
+
+#[tauri::command]
+pub fn delete_annotation(video: String, id: u64, state: State<'_, Settings>) -> Result<(), String> {
+    annotations::delete(&valid_recording_path(&state, &video)?, id)
+}
+
+#[tauri::command]
+pub async fn regenerate_metadata_files(state: State<'_, Settings>) -> Result<usize, String> {
+    let rec_folder = state.get_recordings_path();
+    async_runtime::spawn_blocking(move || helpers::regenerate_metadata_files(&rec_folder))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn migrate_library(state: State<'_, Settings>) -> Result<usize, String> {
+    let rec_folder = state.get_recordings_path();
+    async_runtime::spawn_blocking(move || helpers::migrate_library(&rec_folder))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn rebuild_library(app_handle: AppHandle, state: State<'_, Settings>) -> Result<(), String> {
+    let rec_folder = state.get_recordings_path();
+    async_runtime::spawn_blocking(move || app_handle.state::<Library>().rebuild(&rec_folder))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Moves all recordings into the folders of the current folder layout. Returns the number of moved recordings.
+#[tauri::command]
+pub async fn reorganize_library(app_handle: AppHandle, state: State<'_, Settings>) -> Result<usize, String> {
+    let rec_folder = state.get_recordings_path();
+    let folder_layout = state.get_folder_layout();
+    async_runtime::spawn_blocking(move || {
+        let count = layout::reorganize(&rec_folder, &folder_layout);
+        // the filewatcher events of removed folders don't tell which recordings were in them
+        app_handle.state::<Library>().sync(&rec_folder);
+        _ = app_handle.emit_all("reload_recordings", ());
+        count
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// Lists the recordings the retention settings would delete right now.
+#[tauri::command]
+pub async fn retention_dry_run(state: State<'_, Settings>) -> Result<Vec<Deletion>, String> {
+    let rec_folder = state.get_recordings_path();
+    let retention = state.get_retention();
+    async_runtime::spawn_blocking(move || retention::plan(&rec_folder, &retention))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn list_trash(state: State<'_, Settings>) -> Vec<TrashItem> {
+    trash::list(&state.get_recordings_path())
+}
 
-    match serde_json::from_reader::<BufReader<File>, Value>(reader) {
-        Ok(json) => json,
-        Err(_) => Value::Null,
+#[tauri::command]
+pub fn restore_from_trash(id: String, state: State<'_, Settings>) -> Result<String, String> {
+    let rec_folder = state.get_recordings_path();
+    let restored = trash::restore(&rec_folder, &id)?;
+    // into the folder of the current folder layout (if it changed since the recording got deleted)
+    let game_data = helpers::read_typed_metadata(&restored).unwrap_or_default();
+    let recording = layout::place(&rec_folder, &restored, &state.get_folder_layout(), &game_data);
+    if let (true, Some(folder)) = (recording != restored, restored.parent()) {
+        helpers::remove_empty_folders(&rec_folder, folder);
     }
+    helpers::recording_name(&rec_folder, &recording).ok_or_else(|| format!("unable to restore '{id}'"))
+}
+
+#[tauri::command]
+pub fn empty_trash(state: State<'_, Settings>) -> usize {
+    trash::empty(&state.get_recordings_path())
+}
+
+#[tauri::command]
+pub fn take_broken_recordings(broken_recordings: State<'_, BrokenRecordings>) -> Vec<String> {
+    broken_recordings.take()
+}
+
+/// the path of a recording in the recordings folder - commands that change a recording refuse anything else
+fn valid_recording_path(state: &Settings, video: &str) -> Result<PathBuf, String> {
+    helpers::recording_path(&state.get_recordings_path(), video).ok_or_else(|| format!("invalid recording '{video}'"))
 }
@@ -25,6 +25,8 @@ use tauri::{AppHandle, Manager};
 use tokio::{fs::File, io::AsyncSeekExt};
 use tokio_util::io::poll_read_buf;
 
+use crate::helpers;
+
 static BUFFER_SIZE: usize = 8192;
 
 pub fn start(app_handle: &AppHandle, folder: PathBuf, port: u16) {
@@ -90,7 +92,7 @@ impl Service<Request<Body>> for FileService {
 }
 
 #[inline]
-async fn response(req: Request<Body>, mut folder: PathBuf) -> Result<Response<Body>, String> {
+async fn response(req: Request<Body>, folder: PathBuf) -> Result<Response<Body>, String> {
     let headers = req.headers();
 
     // only allow connections from localhost
@@ -115,10 +117,13 @@ async fn response(req: Request<Body>, mut folder: PathBuf) -> Result<Response<Bo
         return response_from_statuscode(StatusCode::FORBIDDEN);
     };
 
-    folder.push(uri.as_ref());
-    log::info!("fileserver file requested: {folder:?}");
+    // the uri is the name of a recording (relative to the recordings folder) - never serve anything outside of it
+    let Some(path) = helpers::recording_path(&folder, &uri) else {
+        return response_from_statuscode(StatusCode::FORBIDDEN);
+    };
+    log::info!("fileserver file requested: {path:?}");
 
-    let Ok(file) = File::open(folder).await else {
+    let Ok(file) = File::open(path).await else {
         return response_from_statuscode(StatusCode::NOT_FOUND);
     };
     let file_size = match file.metadata().await {
@@ -1,24 +1,56 @@
-use std::path::Path;
+use std::{path::Path, thread};
 
-use notify::Watcher;
+use notify::{event::ModifyKind, EventKind, Watcher};
 use tauri::{AppHandle, Manager};
 
-use crate::state::FileWatcher;
+use crate::{helpers, library::Library, state::FileWatcher};
 
 pub fn replace_filewatcher(app_handle: &AppHandle, recordings_path: &Path) {
+    // bring the library index up to date with the (possibly new) recordings folder
+    thread::spawn({
+        let app_handle = app_handle.clone();
+        let recordings_path = recordings_path.to_path_buf();
+        move || {
+            app_handle.state::<Library>().sync(&recordings_path);
+            _ = app_handle.emit_all("reload_recordings", ());
+        }
+    });
+
     let watcher = notify::recommended_watcher({
         let app_handle = app_handle.clone();
+        let recordings_path = recordings_path.to_path_buf();
         move |res: notify::Result<notify::Event>| {
             log::info!("filewatcher event: {:?}", res);
 
-            // only trigger UI reload if one of the changed paths is a video (.mp4) file
             if let Ok(event) = res {
-                let contains_mp4_path = event
+                // ignore everything that isn't part of the library (staging folder, trash, clips, ...)
+                let paths: Vec<_> = event
                     .paths
                     .iter()
-                    .any(|p| p.extension().is_some_and(|ext| ext == "mp4"));
+                    .filter(|path| helpers::recording_name(&recordings_path, path).is_some())
+                    .collect();
+
+                // keep the library index up to date with the videos and their metadata files
+                let library = app_handle.state::<Library>();
+                for path in &paths {
+                    if path.extension().is_some_and(|ext| ext == "mp4" || ext == "json") {
+                        library.update(&recordings_path, path);
+                    }
+                }
+
+                // a folder that got moved or deleted doesn't get events for the recordings inside of it
+                let folder_removed = matches!(
+                    event.kind,
+                    EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_))
+                ) && paths.iter().any(|path| path.extension().is_none());
+                if folder_removed {
+                    library.sync(&recordings_path);
+                }
+
+                // only trigger UI reload if one of the changed paths is a video (.mp4) file or a folder
+                let contains_mp4_path = paths.iter().any(|p| p.extension().is_some_and(|ext| ext == "mp4"));
 
-                if contains_mp4_path {
+                if contains_mp4_path || folder_removed {
                     _ = app_handle.emit_all("reload_recordings", ());
                 }
             }
@@ -27,7 +59,8 @@ pub fn replace_filewatcher(app_handle: &AppHandle, recordings_path: &Path) {
 
     match watcher {
         Ok(mut watcher) => {
-            _ = watcher.watch(recordings_path, notify::RecursiveMode::NonRecursive);
+            // recursive for the subfolders of the folder layout
+            _ = watcher.watch(recordings_path, notify::RecursiveMode::Recursive);
 
             // store Watcher so it doesn't drop and stop watching
             // also drop old watcher
@@ -14,9 +14,10 @@ use crate::{
         add_log_plugin, check_updates, create_tray_menu, create_window, ensure_settings_exist, remove_log_plugin,
         save_window_state, sync_autostart,
     },
-    recorder,
+    library::Library,
+    recorder, retention,
     state::{FileWatcher, Settings, SettingsFile},
-    AssetPort,
+    trash, AssetPort,
 };
 
 pub fn create_system_tray() -> SystemTray {
@@ -75,6 +76,8 @@ pub fn system_tray_event_handler(app_handle: &AppHandle, event: SystemTrayEvent)
                             let recordings_path_changed = recordings_path != old_recordings_path;
 
                             if recordings_path_changed {
+                                filewatcher::replace_filewatcher(&app_handle, &recordings_path);
+
                                 // send stop fileserver signal
                                 app_handle.trigger_global("shutdown_fileserver", None);
                                 // when fileserver stopped restart with new folder on the same port as before
@@ -95,6 +98,7 @@ pub fn system_tray_event_handler(app_handle: &AppHandle, event: SystemTrayEvent)
                     }
                 });
             }
+            "clip" => app_handle.trigger_global("save_replay_clip", None),
             "open" => create_window(app_handle),
             "quit" => {
                 // close UI window
@@ -187,10 +191,24 @@ pub fn setup_handler(app: &mut App<Wry>) -> Result<(), Box<dyn Error>> {
     log::info!("video folder: {:?}", recordings_path);
     log::info!("fileserver port: {}", port);
 
+    app_handle.state::<Library>().open(&config_path.join("library.db"));
     filewatcher::replace_filewatcher(&app_handle, &recordings_path);
+    // finish the recordings of games that got interrupted last time before a new one can start
+    thread::spawn({
+        let app_handle = app_handle.clone();
+        let recordings_path = recordings_path.clone();
+        let folder_layout = settings.get_folder_layout();
+        move || {
+            recorder::recovery::recover_interrupted(&recordings_path, &folder_layout, |broken| {
+                recorder::report_broken_recording(&app_handle, broken)
+            });
+            recorder::start(&app_handle);
+        }
+    });
     // launch static-file-server as a replacement for the broken asset protocol
     fileserver::start(&app_handle, recordings_path, port);
-    recorder::start(&app_handle);
+    retention::start(&app_handle);
+    trash::start(&app_handle);
     Ok(())
 }
 
@@ -1,22 +1,38 @@
 use std::{
-    cmp::Ordering,
-    fs, io,
-    path::{Path, PathBuf},
+    fmt::{self, Display},
+    fs::{self, File},
+    io::{self, BufReader},
+    path::{Component, Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use log::LevelFilter;
 use reqwest::{blocking::Client, redirect::Policy, StatusCode};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
 use tauri::{api::version::compare, AppHandle, CustomMenuItem, Manager, SystemTrayMenu, SystemTrayMenuItem, Window};
 use tauri_plugin_autostart::ManagerExt;
 use tauri_plugin_log::LogTarget;
 
-use crate::state::{Settings, WindowState};
+use crate::{
+    mp4,
+    recorder::{
+        backend,
+        data::{GameData, SCHEMA_VERSION},
+        highlights::CLIPS_FOLDER,
+        migrations::{self, MigrationError},
+        recovery::{unique_path, BROKEN_FOLDER},
+        settings::Size,
+    },
+    state::{Settings, WindowState},
+};
 
 const GITHUB_LATEST: &str = "https://github.com/FFFFFFFXXXXXXX/league_record/releases/latest";
 
 pub fn create_tray_menu() -> SystemTrayMenu {
     SystemTrayMenu::new()
         .add_item(CustomMenuItem::new("rec", "Recording").disabled())
+        .add_item(CustomMenuItem::new("clip", "Save Clip").disabled())
         .add_native_item(SystemTrayMenuItem::Separator)
         .add_item(CustomMenuItem::new("settings", "Settings"))
         .add_item(CustomMenuItem::new("open", "Open"))
@@ -27,10 +43,19 @@ pub fn set_recording_tray_item(app_handle: &AppHandle, recording: bool) {
     let item = app_handle.tray_handle().get_item("rec");
     // set selected only updates the tray menu when open if the menu item is enabled
     _ = item.set_enabled(true);
+    if recording {
+        // reset the reason why the last game wasn't recorded
+        _ = item.set_title("Recording");
+    }
     _ = item.set_selected(recording);
     _ = item.set_enabled(false);
 }
 
+/// 'Save Clip' only does something while a game is recorded into the replay buffer
+pub fn set_clip_tray_item(app_handle: &AppHandle, enabled: bool) {
+    _ = app_handle.tray_handle().get_item("clip").set_enabled(enabled);
+}
+
 pub fn check_updates(app_handle: &AppHandle) {
     let config = app_handle.config();
     let version = config.package.version.as_ref().unwrap();
@@ -112,42 +137,479 @@ pub fn remove_log_plugin(app_handle: &AppHandle) {
 }
 
 pub fn get_recordings(rec_folder: &Path) -> Vec<PathBuf> {
-    // get all mp4 files in ~/Videos/%folder-name%
+    // get all mp4 files in ~/Videos/%folder-name% and its subfolders (see the folder layout)
     let mut recordings = Vec::<PathBuf>::new();
-    let Ok(read_dir) = rec_folder.read_dir() else {
-        return vec![];
+    let mut folders = vec![rec_folder.to_path_buf()];
+    while let Some(folder) = folders.pop() {
+        let Ok(read_dir) = folder.read_dir() else {
+            continue;
+        };
+
+        for entry in read_dir.flatten() {
+            if entry.file_name().to_str().is_none_or(is_ignored) {
+                continue;
+            }
+
+            let path = entry.path();
+            // doesn't follow symlinks so a link to a parent folder can't send the search in circles
+            if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+                folders.push(path);
+            } else if path.is_file() && path.extension().is_some_and(|ext| ext == "mp4") {
+                recordings.push(path);
+            }
+        }
+    }
+    recordings
+}
+
+/// The name the UI and the library use for a recording: its path relative to the recordings folder with '/' as
+/// separator. `None` if the path doesn't belong to the library (outside of the recordings folder, in the staging
+/// folder, the trash, a clips folder, ...).
+pub fn recording_name(rec_folder: &Path, recording: &Path) -> Option<String> {
+    let components = recording
+        .strip_prefix(rec_folder)
+        .ok()?
+        .components()
+        .map(|component| match component {
+            Component::Normal(name) => name.to_str().filter(|name| !is_ignored(name)),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    (!components.is_empty()).then(|| components.join("/"))
+}
+
+/// The path of the recording with the `name` (see `recording_name`).
+/// `None` if the name would point to anything outside of the recordings folder or to something that isn't a recording
+/// (e.g. the trash or the staging folder).
+pub fn recording_path(rec_folder: &Path, name: &str) -> Option<PathBuf> {
+    let mut path = rec_folder.to_path_buf();
+    for part in name.split('/') {
+        // only plain names - no '..', absolute paths, drive letters or other separators
+        let mut components = Path::new(part).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(part)), None) if !is_ignored(part.to_str()?) => path.push(part),
+            _ => return None,
+        }
+    }
+    Some(path)
+}
+
+/// hidden files and folders (e.g. the staging folder and the trash), the highlight clips and the recordings that
+/// couldn't be finished aren't recordings
+fn is_ignored(name: &str) -> bool {
+    name.starts_with('.') || name == CLIPS_FOLDER || name == BROKEN_FOLDER
+}
+
+/// Removes `folder` and its parents up to the recordings folder as long as they are empty.
+pub fn remove_empty_folders(rec_folder: &Path, folder: &Path) {
+    let mut folder = folder;
+    while folder != rec_folder && folder.starts_with(rec_folder) {
+        // only succeeds for empty folders
+        if fs::remove_dir(folder).is_err() {
+            break;
+        }
+        log::info!("removed empty folder {folder:?}");
+        let Some(parent) = folder.parent() else {
+            break;
+        };
+        folder = parent;
+    }
+}
+
+/// Cuts `[start, end]` out of a recording without re-encoding and returns the filename of the new recording.
+/// The metadata gets copied with only the events inside the range (shifted to the start of the new recording).
+pub fn export_clip(rec_folder: &Path, video: &str, start: f64, end: f64) -> Result<String, String> {
+    let Some(source) = recording_path(rec_folder, video) else {
+        return Err(format!("invalid video name '{video}'"));
+    };
+    let Some(stem) = source.file_stem().map(|stem| stem.to_string_lossy().into_owned()) else {
+        return Err(format!("invalid video name '{video}'"));
+    };
+    // next to the recording it is cut from - exporting the same range twice doesn't overwrite the first export
+    let destination = unique_path(
+        &source.with_file_name(format!("{stem}_{:.0}s-{:.0}s.mp4", start.max(0.0), end)),
+        Path::exists,
+    );
+    let Some(filename) = recording_name(rec_folder, &destination) else {
+        return Err(format!("invalid clip path {destination:?}"));
+    };
+
+    let range = mp4::trim(&source, &destination, start, end).map_err(|e| e.to_string())?;
+    log::info!(
+        "exported {:.2}s-{:.2}s of {source:?} to {destination:?}",
+        range.start,
+        range.end
+    );
+
+    let Some(Value::Object(mut metadata)) = read_metadata(&source) else {
+        return Ok(filename);
+    };
+    // events and annotations outside of the clip get dropped, the others are moved to their time in the clip
+    for key in ["events", "annotations"] {
+        if let Some(Value::Array(entries)) = metadata.get_mut(key) {
+            entries.retain_mut(|entry| match entry.get("time").and_then(Value::as_f64) {
+                Some(time) if (range.start..=range.end).contains(&time) => {
+                    entry["time"] = json!(time - range.start);
+                    if let Some(end) = entry.get("end").and_then(Value::as_f64) {
+                        entry["end"] = json!(end.min(range.end) - range.start);
+                    }
+                    true
+                }
+                _ => false,
+            });
+        }
+    }
+    // the clip is a new recording that isn't favorited or protected yet
+    metadata.remove("favorite");
+    metadata.remove("protected");
+    metadata.insert(
+        "source".into(),
+        json!({ "file": video, "start": range.start, "end": range.end }),
+    );
+
+    let result = write_metadata(&destination, &metadata);
+    log::info!("exported clip metadata saved: {result:?}");
+    Ok(filename)
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ReelSegment {
+    pub video: String,
+    pub start: f64,
+    pub end: f64,
+}
+
+/// Joins the segments (from one or more recordings) into a single recording and returns the filename of the new
+/// recording. Its metadata holds the events of all segments and a chapter for each segment.
+/// Segments only get re-encoded (with ffmpeg) if they can't be joined without (e.g. different resolutions).
+pub fn create_highlight_reel(rec_folder: &Path, segments: &[ReelSegment]) -> Result<String, String> {
+    let filename = format!("Highlights_{}.mp4", chrono::Local::now().format("%Y-%m-%d_%H-%M-%S"));
+    let destination = unique_path(&rec_folder.join(filename), Path::exists);
+    let Some(filename) = recording_name(rec_folder, &destination) else {
+        return Err(format!("invalid highlight reel path {destination:?}"));
+    };
+
+    let mut mp4_segments = Vec::with_capacity(segments.len());
+    for segment in segments {
+        let Some(source) = recording_path(rec_folder, &segment.video) else {
+            return Err(format!("invalid video name '{}'", segment.video));
+        };
+        mp4_segments.push(mp4::Segment {
+            source,
+            start: segment.start,
+            end: segment.end,
+        });
+    }
+
+    let ranges = match mp4::concat(&mp4_segments, &destination) {
+        Ok(ranges) => ranges,
+        Err(mp4::Mp4Error::Unsupported(e)) => {
+            log::info!("unable to join the segments without re-encoding ({e}) - re-encoding them instead");
+            reencode_highlight_reel(&mp4_segments, &destination)?
+        }
+        Err(e) => return Err(e.to_string()),
+    };
+    log::info!("created highlight reel {destination:?} from {} segments", ranges.len());
+
+    let mut events = Vec::new();
+    let mut chapters = Vec::new();
+    let mut reel_time = 0.0;
+    for ((segment, source), range) in segments.iter().zip(&mp4_segments).zip(ranges) {
+        let metadata = read_metadata(&source.source).unwrap_or(Value::Null);
+
+        if let Some(Value::Array(segment_events)) = metadata.get("events") {
+            for event in segment_events {
+                match event.get("time").and_then(Value::as_f64) {
+                    Some(time) if (range.start..=range.end).contains(&time) => {
+                        let mut event = event.clone();
+                        event["time"] = json!(time - range.start + reel_time);
+                        events.push(event);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let game_info = &metadata["gameInfo"];
+        let title = match (game_info["championName"].as_str(), game_info["gameMode"].as_str()) {
+            (Some(champion), Some(game_mode)) if !champion.is_empty() => format!("{champion} - {game_mode}"),
+            _ => segment.video.trim_end_matches(".mp4").to_string(),
+        };
+        let duration = range.end - range.start;
+        chapters.push(json!({
+            "title": title,
+            "start": reel_time,
+            "end": reel_time + duration,
+            "source": { "file": segment.video, "start": range.start, "end": range.end },
+        }));
+        reel_time += duration;
+    }
+
+    let metadata = json!({ "schemaVersion": SCHEMA_VERSION, "events": events, "chapters": chapters });
+    let result = write_metadata(&destination, &metadata);
+    log::info!("highlight reel metadata saved: {result:?}");
+    Ok(filename)
+}
+
+/// Joins the segments with ffmpeg - the video gets scaled to the size of the first segment.
+fn reencode_highlight_reel(segments: &[mp4::Segment], destination: &Path) -> Result<Vec<mp4::TrimRange>, String> {
+    let mut ranges = Vec::with_capacity(segments.len());
+    let mut size = None;
+    let mut audio = true;
+    for (i, segment) in segments.iter().enumerate() {
+        let info = mp4::media_info(&segment.source).map_err(|e| format!("unable to read segment {i}: {e}"))?;
+        let range = mp4::TrimRange {
+            start: segment.start.max(0.0),
+            end: segment.end.min(info.duration),
+        };
+        if range.start.is_nan() || range.end.is_nan() || range.end <= range.start {
+            return Err(format!(
+                "invalid range {}-{} of segment {i}",
+                segment.start, segment.end
+            ));
+        }
+        size.get_or_insert(Size::new(info.width, info.height));
+        audio &= info.has_audio;
+        ranges.push(range);
+    }
+    let Some(size) = size else {
+        return Err(String::from("no segments"));
+    };
+
+    let segments: Vec<mp4::Segment> = segments
+        .iter()
+        .zip(&ranges)
+        .map(|(segment, range)| mp4::Segment {
+            source: segment.source.clone(),
+            start: range.start,
+            end: range.end,
+        })
+        .collect();
+    // ffmpeg refuses to overwrite an existing file - only clean up after ffmpeg
+    let existed = destination.exists();
+    if let Err(e) = backend::reencode_concat(&segments, size, audio, destination) {
+        if !existed {
+            _ = fs::remove_file(destination);
+        }
+        return Err(e.to_string());
+    }
+    Ok(ranges)
+}
+
+/// Writes the metadata json file of a recording and embeds a copy in the recording itself.
+/// Only for new recordings (finished games and exports) - the json file is what gets read, so a copy that can't
+/// be embedded (e.g. into a fragmented recording) only gets logged.
+pub fn write_metadata(recording: &Path, metadata: &impl Serialize) -> Result<(), String> {
+    let json = serde_json::to_vec(metadata).map_err(|e| e.to_string())?;
+    fs::write(recording.with_extension("json"), &json).map_err(|e| e.to_string())?;
+    if let Err(e) = mp4::write_game_data(recording, &json) {
+        log::warn!("unable to embed metadata into {recording:?}: {e}");
+    }
+    Ok(())
+}
+
+/// Writes only the metadata json file of a recording.
+/// Rewriting the recording on every edit isn't worth it - the embedded copy is only a backup of the json file.
+pub fn write_metadata_file(recording: &Path, metadata: &impl Serialize) -> Result<(), String> {
+    let json = serde_json::to_vec(metadata).map_err(|e| e.to_string())?;
+    fs::write(recording.with_extension("json"), json).map_err(|e| e.to_string())
+}
+
+/// Changes the metadata of a recording and writes it back to the json file.
+/// Recordings without metadata start with an empty one.
+pub fn update_metadata<T>(
+    recording: &Path,
+    update: impl FnOnce(&mut Map<String, Value>) -> Result<T, String>,
+) -> Result<T, String> {
+    let mut metadata = match read_metadata(recording) {
+        Some(Value::Object(metadata)) => metadata,
+        Some(_) => return Err("invalid metadata".into()),
+        None => Map::from_iter([("schemaVersion".to_string(), Value::from(SCHEMA_VERSION))]),
+    };
+    let result = update(&mut metadata)?;
+    write_metadata_file(recording, &metadata)?;
+    Ok(result)
+}
+
+/// Replaces the tags of a recording. Returns the tags as they got saved (trimmed, sorted and without duplicates).
+pub fn set_tags(recording: &Path, tags: &[String]) -> Result<Vec<String>, String> {
+    let mut tags: Vec<String> = tags
+        .iter()
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    tags.sort();
+    tags.dedup();
+    update_metadata(recording, |metadata| {
+        metadata.insert("tags".into(), json!(tags));
+        Ok(())
+    })?;
+    Ok(tags)
+}
+
+/// Sets a flag (e.g. "favorite" or "protected") of a recording.
+pub fn set_flag(recording: &Path, flag: &str, value: bool) -> Result<(), String> {
+    update_metadata(recording, |metadata| {
+        metadata.insert(flag.into(), Value::Bool(value));
+        Ok(())
+    })
+}
+
+/// Protected recordings must not be deleted - neither by the user nor by any automatic cleanup.
+pub fn is_protected(recording: &Path) -> bool {
+    read_metadata(recording)
+        .and_then(|metadata| metadata.get("protected").and_then(Value::as_bool))
+        .unwrap_or(false)
+}
+
+/// The metadata of a recording from its json file or the copy embedded in the recording if the json file is missing.
+/// Metadata from older versions gets upgraded to the current schema (only in memory, the files stay untouched).
+pub fn read_metadata(recording: &Path) -> Option<Value> {
+    let mut metadata = read_stored_metadata(recording)?;
+    if let Err(e) = migrations::migrate(&mut metadata) {
+        log::warn!("unable to migrate metadata of {recording:?}: {e}");
+    }
+    Some(metadata)
+}
+
+/// Why the metadata of a recording couldn't be read.
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", content = "details", rename_all = "camelCase")]
+pub enum MetadataError {
+    /// there is no json file and no metadata embedded in the recording
+    Missing,
+    /// the path doesn't point to a recording in the recordings folder
+    InvalidRecording,
+    Corrupt(String),
+    /// written by a newer version of LeagueRecord
+    UnsupportedSchema(u64),
+    PermissionDenied,
+    Io(String),
+}
+
+impl Display for MetadataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetadataError::Missing => write!(f, "no metadata found"),
+            MetadataError::InvalidRecording => write!(f, "invalid recording"),
+            MetadataError::Corrupt(e) => write!(f, "corrupt metadata: {e}"),
+            MetadataError::UnsupportedSchema(version) => write!(f, "unsupported metadata version {version}"),
+            MetadataError::PermissionDenied => write!(f, "permission denied"),
+            MetadataError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+/// The metadata of a recording (see `read_metadata`) parsed into `GameData`.
+pub fn read_typed_metadata(recording: &Path) -> Result<GameData, MetadataError> {
+    let mut metadata = match read_metadata_file(recording) {
+        Ok(metadata) => metadata,
+        Err(e) => read_embedded_metadata(recording).ok_or(e)?,
     };
+    migrations::migrate(&mut metadata).map_err(|e| match e {
+        MigrationError::NewerVersion(version) => MetadataError::UnsupportedSchema(version),
+        MigrationError::NotAnObject => MetadataError::Corrupt(e.to_string()),
+    })?;
+    serde_json::from_value(metadata).map_err(|e| MetadataError::Corrupt(e.to_string()))
+}
+
+fn read_stored_metadata(recording: &Path) -> Option<Value> {
+    read_metadata_file(recording)
+        .ok()
+        .or_else(|| read_embedded_metadata(recording))
+}
+
+fn read_metadata_file(recording: &Path) -> Result<Value, MetadataError> {
+    let file = File::open(recording.with_extension("json")).map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => MetadataError::Missing,
+        io::ErrorKind::PermissionDenied => MetadataError::PermissionDenied,
+        _ => MetadataError::Io(e.to_string()),
+    })?;
+    serde_json::from_reader(BufReader::new(file)).map_err(|e| MetadataError::Corrupt(e.to_string()))
+}
 
-    for entry in read_dir.flatten() {
-        let path = entry.path();
+fn read_embedded_metadata(recording: &Path) -> Option<Value> {
+    let json = mp4::read_game_data(recording).ok()??;
+    serde_json::from_slice(&json).ok()
+}
 
-        if !path.is_file() {
+/// Recreates the missing (or broken) metadata json files from the metadata that is embedded in the recordings.
+/// Returns the number of json files that got written.
+pub fn regenerate_metadata_files(rec_folder: &Path) -> usize {
+    let mut count = 0;
+    for recording in get_recordings(rec_folder) {
+        if read_metadata_file(&recording).is_ok() {
             continue;
         }
 
-        // skip files that can't be renamed which means they are being written to - only works on windows
-        #[cfg(target_os = "windows")]
-        if std::fs::rename(&path, &path).is_err() {
+        let json_file = recording.with_extension("json");
+        match mp4::read_game_data(&recording) {
+            Ok(Some(json)) => match fs::write(&json_file, json) {
+                Ok(_) => count += 1,
+                Err(e) => log::warn!("unable to write {json_file:?}: {e}"),
+            },
+            Ok(None) => {}
+            Err(e) => log::warn!("unable to read embedded metadata of {recording:?}: {e}"),
+        }
+    }
+    log::info!("regenerated {count} metadata files");
+    count
+}
+
+/// Upgrades the metadata of all recordings from older versions to the current schema and writes it back to the
+/// json files. Returns the number of recordings that got migrated.
+pub fn migrate_library(rec_folder: &Path) -> usize {
+    let mut count = 0;
+    for recording in get_recordings(rec_folder) {
+        let Some(mut metadata) = read_stored_metadata(&recording) else {
             continue;
+        };
+
+        match migrations::migrate(&mut metadata) {
+            Ok(true) => match write_metadata_file(&recording, &metadata) {
+                Ok(_) => count += 1,
+                Err(e) => log::warn!("unable to write migrated metadata of {recording:?}: {e}"),
+            },
+            Ok(false) => {}
+            Err(e) => log::warn!("unable to migrate metadata of {recording:?}: {e}"),
         }
+    }
+    log::info!("migrated the metadata of {count} recordings");
+    count
+}
 
-        if let Some(ext) = path.extension() {
-            if ext == "mp4" {
-                recordings.push(path);
-            }
+/// Moves a recording and its metadata file.
+pub fn move_recording(recording: &Path, destination: &Path) -> io::Result<()> {
+    // if the video can't be moved keep the metadata where it is as well
+    fs::rename(recording, destination)?;
+    if let Err(e) = fs::rename(recording.with_extension("json"), destination.with_extension("json")) {
+        if e.kind() != io::ErrorKind::NotFound {
+            log::warn!("unable to move metadata of {recording:?}: {e}");
         }
     }
-    recordings
+    Ok(())
 }
 
-pub fn path_to_string(path: &PathBuf) -> String {
-    path.to_owned().into_os_string().into_string().expect("invalid path")
+/// Deletes a recording and its metadata file.
+pub fn delete_recording(recording: &Path) -> io::Result<()> {
+    // if the video can't be deleted keep the metadata as well
+    fs::remove_file(recording)?;
+    if let Err(e) = fs::remove_file(recording.with_extension("json")) {
+        if e.kind() != io::ErrorKind::NotFound {
+            log::warn!("unable to delete metadata of {recording:?}: {e}");
+        }
+    }
+    Ok(())
 }
 
-pub fn compare_time(a: &Path, b: &Path) -> io::Result<Ordering> {
-    let a_time = a.metadata()?.created()?;
-    let b_time = b.metadata()?.created()?;
-    Ok(a_time.cmp(&b_time).reverse())
+pub fn unix_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+pub fn path_to_string(path: &PathBuf) -> String {
+    path.to_owned().into_os_string().into_string().expect("invalid path")
 }
 
 pub fn show_window(window: &Window) {
@@ -212,3 +674,35 @@ pub fn ensure_settings_exist(settings_file: &PathBuf) -> bool {
     }
     true
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_recordings_inside_of_the_recordings_folder() {
+        let rec_folder = Path::new("recordings");
+        assert_eq!(
+            recording_path(rec_folder, "game.mp4"),
+            Some(rec_folder.join("game.mp4"))
+        );
+        assert_eq!(
+            recording_path(rec_folder, "2024/05/game.mp4"),
+            Some(rec_folder.join("2024/05/game.mp4"))
+        );
+
+        for name in ["", "../game.mp4", "2024/../../game.mp4", "/game.mp4", "2024//game.mp4"] {
+            assert_eq!(recording_path(rec_folder, name), None, "{name}");
+        }
+        // the trash, staged recordings, highlight clips and broken recordings
+        let ignored = [
+            String::from(".trash/game.mp4"),
+            String::from("2024/.game.mp4"),
+            format!("{CLIPS_FOLDER}/clip.mp4"),
+            format!("{BROKEN_FOLDER}/game.mp4"),
+        ];
+        for name in ignored {
+            assert_eq!(recording_path(rec_folder, &name), None, "{name}");
+        }
+    }
+}
@@ -0,0 +1,89 @@
+/*
+    Sorts the recordings into subfolders of the recordings folder by the folder layout setting (e.g. "{year}/{month}"
+    or "{champion}"). A recording gets moved into its folder as soon as its game is over, `reorganize` moves the
+    recordings that are already in the library into the folders of the current layout.
+*/
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local};
+
+use crate::{
+    helpers,
+    recorder::{
+        data::GameData,
+        filename::{fill_placeholders, sanitize},
+        highlights::CLIPS_FOLDER,
+        recovery::{unique_path, BROKEN_FOLDER},
+    },
+};
+
+/// The folder (relative to the recordings folder) the layout puts the recording of a game into.
+/// The date placeholders ({year}, {month}, {day}) are the date of the recording, all other placeholders are the
+/// same as in the filename format.
+pub fn folder(layout: &str, created: DateTime<Local>, game_data: &GameData) -> PathBuf {
+    let layout = layout
+        .replace("{year}", &created.format("%Y").to_string())
+        .replace("{month}", &created.format("%m").to_string())
+        .replace("{day}", &created.format("%d").to_string());
+    fill_placeholders(&layout, game_data)
+        .split(['/', '\\'])
+        // no '..' and no hidden folders - those get skipped when listing the recordings
+        .map(|name| name.trim().trim_start_matches('.'))
+        .filter(|name| !name.is_empty())
+        .map(|name| match sanitize(name) {
+            name if name == CLIPS_FOLDER || name == BROKEN_FOLDER => format!("{name}_"),
+            name => name,
+        })
+        .collect()
+}
+
+/// Moves the recording (and its metadata file) into the folder the layout puts it in.
+/// Returns the new path of the recording (or the old one if it stays where it is or can't be moved).
+pub fn place(rec_folder: &Path, recording: &Path, layout: &str, game_data: &GameData) -> PathBuf {
+    let target = rec_folder.join(folder(layout, created(recording), game_data));
+    if recording.parent() == Some(target.as_path()) {
+        return recording.to_path_buf();
+    }
+
+    if let Err(e) = fs::create_dir_all(&target) {
+        log::warn!("unable to create folder {target:?}: {e}");
+        return recording.to_path_buf();
+    }
+    let placed = unique_path(&target.join(recording.file_name().unwrap_or_default()), Path::exists);
+    if let Err(e) = helpers::move_recording(recording, &placed) {
+        log::warn!("unable to move {recording:?} to {placed:?}: {e}");
+        return recording.to_path_buf();
+    }
+    log::info!("moved {recording:?} to {placed:?}");
+    placed
+}
+
+/// Moves every recording into the folder of the layout and removes the folders that end up empty.
+/// Returns the number of recordings that got moved.
+pub fn reorganize(rec_folder: &Path, layout: &str) -> usize {
+    let mut count = 0;
+    for recording in helpers::get_recordings(rec_folder) {
+        // recordings without (readable) metadata end up in the 'Unknown' folders
+        let game_data = helpers::read_typed_metadata(&recording).unwrap_or_default();
+        if place(rec_folder, &recording, layout, &game_data) != recording {
+            count += 1;
+            if let Some(folder) = recording.parent() {
+                helpers::remove_empty_folders(rec_folder, folder);
+            }
+        }
+    }
+    log::info!("moved {count} recordings into the folder layout '{layout}'");
+    count
+}
+
+/// when the recording was created (not every platform / filesystem records the creation time)
+fn created(recording: &Path) -> DateTime<Local> {
+    fs::metadata(recording)
+        .and_then(|metadata| metadata.created().or_else(|_| metadata.modified()))
+        .map(DateTime::from)
+        .unwrap_or_else(|_| Local::now())
+}
@@ -0,0 +1,695 @@
+/*
+    Persistent index of the recordings (file info and parsed metadata) in a SQLite database in the app config dir.
+    It gets synced with the recordings folder when the filewatcher is (re)started and updated on every filewatcher
+    event, so listing the recordings doesn't have to scan the whole folder and read every metadata file.
+    Everything in the index can be rebuilt from the recordings folder.
+*/
+
+use std::{collections::HashSet, fs, path::Path, sync::Mutex};
+
+use rusqlite::{params, params_from_iter, types::Value as SqlValue, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    helpers::{self, unix_millis},
+    mp4,
+    recorder::data::{GameData, GameInfo, Stats},
+};
+
+/// Increase when the layout of the tables changes - the index gets rebuilt from the recordings folder then.
+const INDEX_VERSION: i32 = 5;
+
+/// (kills + assists) / deaths - with at least 1 death to avoid dividing by 0
+const KDA: &str = "(kills + assists) * 1.0 / MAX(deaths, 1)";
+
+#[derive(Default)]
+pub struct Library(Mutex<Option<Connection>>);
+
+/// Filters, sorting and pagination for `Library::query`. Every filter that is not set matches all recordings.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RecordingQuery {
+    /// case insensitive
+    pub champion: Option<String>,
+    pub win: Option<bool>,
+    /// case insensitive
+    pub game_mode: Option<String>,
+    /// unix timestamp in milliseconds
+    pub from: Option<i64>,
+    /// unix timestamp in milliseconds
+    pub to: Option<i64>,
+    pub min_kda: Option<f64>,
+    /// case insensitive
+    pub tag: Option<String>,
+    pub favorite: Option<bool>,
+    pub protected: Option<bool>,
+    pub sort: RecordingSort,
+    pub ascending: bool,
+    pub offset: u32,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RecordingSort {
+    #[default]
+    Date,
+    Size,
+    Champion,
+    Kills,
+    Deaths,
+    Assists,
+    Kda,
+}
+
+impl RecordingSort {
+    fn column(self) -> &'static str {
+        match self {
+            RecordingSort::Date => "created",
+            RecordingSort::Size => "size",
+            RecordingSort::Champion => "champion",
+            RecordingSort::Kills => "kills",
+            RecordingSort::Deaths => "deaths",
+            RecordingSort::Assists => "assists",
+            RecordingSort::Kda => KDA,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingPage {
+    /// number of recordings that match the filters (without pagination)
+    pub total: u64,
+    pub recordings: Vec<RecordingEntry>,
+}
+
+/// A recorded game with the values the statistics are calculated from.
+#[derive(Debug)]
+pub struct GameRecord {
+    pub filename: String,
+    /// unix timestamp in milliseconds
+    pub created: i64,
+    pub champion: String,
+    pub game_mode: String,
+    pub win: Option<bool>,
+    /// `None` if the post game stats never arrived
+    pub stats: Option<GameStats>,
+    /// length of the recording in seconds
+    pub duration: Option<f64>,
+}
+
+#[derive(Debug)]
+pub struct GameStats {
+    pub kills: u64,
+    pub deaths: u64,
+    pub assists: u64,
+    /// lane minions and neutral minions killed
+    pub creep_score: u64,
+    pub vision_score: f64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordingEntry {
+    /// relative to the recordings folder (see `helpers::recording_name`)
+    pub filename: String,
+    /// in bytes
+    pub size: u64,
+    /// unix timestamp in milliseconds
+    pub created: i64,
+    pub win: Option<bool>,
+    /// `None` if the recording has no (readable) metadata
+    pub game_info: Option<GameInfo>,
+    pub stats: Option<Stats>,
+    pub tags: Vec<String>,
+    pub favorite: bool,
+    pub protected: bool,
+}
+
+impl RecordingEntry {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let game_data = row
+            .get::<_, Option<String>>(3)?
+            .and_then(|json| serde_json::from_str::<GameData>(&json).ok());
+        Ok(Self {
+            filename: row.get(0)?,
+            size: row.get::<_, i64>(1)? as u64,
+            created: row.get(2)?,
+            win: game_data.as_ref().and_then(|game_data| game_data.win),
+            game_info: game_data.as_ref().map(|game_data| game_data.game_info.clone()),
+            stats: game_data.as_ref().map(|game_data| game_data.stats.clone()),
+            tags: game_data
+                .as_ref()
+                .map(|game_data| game_data.tags.clone())
+                .unwrap_or_default(),
+            favorite: game_data.as_ref().is_some_and(|game_data| game_data.favorite),
+            protected: game_data.is_some_and(|game_data| game_data.protected),
+        })
+    }
+}
+
+impl Library {
+    /// Opens (or creates) the index database. Falls back to a temporary in-memory index if that fails.
+    pub fn open(&self, db_path: &Path) {
+        let db = match open_db(Some(db_path)) {
+            Ok(db) => db,
+            Err(e) => {
+                log::error!("unable to open library index {db_path:?}: {e}");
+                match open_db(None) {
+                    Ok(db) => db,
+                    Err(e) => {
+                        log::error!("unable to create temporary library index: {e}");
+                        return;
+                    }
+                }
+            }
+        };
+        *self.0.lock().unwrap() = Some(db);
+    }
+
+    /// Brings the index up to date with the recordings folder.
+    /// Only recordings that changed since they were indexed get their metadata read again.
+    pub fn sync(&self, rec_folder: &Path) {
+        self.with_db(|db| {
+            let tx = db.transaction()?;
+
+            let folder = rec_folder.to_string_lossy();
+            let indexed_folder: Option<String> = tx
+                .query_row("SELECT value FROM info WHERE key = 'folder'", [], |row| row.get(0))
+                .optional()?;
+            if indexed_folder.as_deref() != Some(&folder) {
+                tx.execute("DELETE FROM recordings", [])?;
+                tx.execute(
+                    "INSERT OR REPLACE INTO info (key, value) VALUES ('folder', ?1)",
+                    [&folder],
+                )?;
+            }
+
+            let mut on_disk = HashSet::new();
+            for recording in helpers::get_recordings(rec_folder) {
+                if let Some(filename) = helpers::recording_name(rec_folder, &recording) {
+                    index_recording(&tx, &filename, &recording)?;
+                    on_disk.insert(filename);
+                }
+            }
+
+            let indexed = {
+                let mut stmt = tx.prepare("SELECT filename FROM recordings")?;
+                let filenames = stmt.query_map([], |row| row.get::<_, String>(0))?;
+                filenames.collect::<Result<Vec<_>, _>>()?
+            };
+            for filename in indexed.iter().filter(|filename| !on_disk.contains(*filename)) {
+                tx.execute("DELETE FROM recordings WHERE filename = ?1", [filename])?;
+            }
+
+            tx.commit()?;
+            log::info!("library index synced ({} recordings)", on_disk.len());
+            Ok(())
+        });
+    }
+
+    /// Throws away the whole index and indexes every recording again.
+    pub fn rebuild(&self, rec_folder: &Path) {
+        self.with_db(|db| db.execute("DELETE FROM recordings", []).map(|_| ()));
+        self.sync(rec_folder);
+    }
+
+    /// Updates the entry of the recording that `changed` (the video or its metadata file) belongs to.
+    pub fn update(&self, rec_folder: &Path, changed: &Path) {
+        let recording = changed.with_extension("mp4");
+        let Some(filename) = helpers::recording_name(rec_folder, &recording) else {
+            return;
+        };
+        self.with_db(|db| index_recording(db, &filename, &recording));
+    }
+
+    /// The recorded games (without clips and highlight reels) between `from` and `to` (oldest first).
+    pub fn games(&self, from: Option<i64>, to: Option<i64>) -> Vec<GameRecord> {
+        self.with_db(|db| {
+            let mut stmt = db.prepare(
+                "SELECT filename, created, champion, game_mode, win, kills, deaths, assists, creep_score,
+                    vision_score, duration
+                FROM recordings
+                WHERE clip = 0 AND champion IS NOT NULL
+                    AND created >= IFNULL(?1, created) AND created <= IFNULL(?2, created)
+                ORDER BY created ASC",
+            )?;
+            let games = stmt.query_map(params![from, to], |row| {
+                // the stat columns are either all set or all NULL
+                let stats = match row.get::<_, Option<i64>>(5)? {
+                    Some(kills) => Some(GameStats {
+                        kills: kills as u64,
+                        deaths: row.get::<_, i64>(6)? as u64,
+                        assists: row.get::<_, i64>(7)? as u64,
+                        creep_score: row.get::<_, i64>(8)? as u64,
+                        vision_score: row.get(9)?,
+                    }),
+                    None => None,
+                };
+                Ok(GameRecord {
+                    filename: row.get(0)?,
+                    created: row.get(1)?,
+                    champion: row.get(2)?,
+                    game_mode: row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+                    win: row.get(4)?,
+                    stats,
+                    duration: row.get(10)?,
+                })
+            })?;
+            games.collect()
+        })
+    }
+
+    /// The filenames of all recordings (index 0 is the newest).
+    pub fn filenames(&self) -> Vec<String> {
+        self.with_db(|db| {
+            let mut stmt = db.prepare("SELECT filename FROM recordings ORDER BY created DESC")?;
+            let filenames = stmt.query_map([], |row| row.get(0))?;
+            filenames.collect()
+        })
+    }
+
+    /// The recordings that match the filters of the query.
+    pub fn query(&self, query: &RecordingQuery) -> RecordingPage {
+        let mut conditions = Vec::new();
+        let mut values = Vec::<SqlValue>::new();
+        if let Some(champion) = &query.champion {
+            conditions.push("champion = ? COLLATE NOCASE".to_string());
+            values.push(champion.clone().into());
+        }
+        if let Some(win) = query.win {
+            conditions.push("win = ?".to_string());
+            values.push(win.into());
+        }
+        if let Some(game_mode) = &query.game_mode {
+            conditions.push("game_mode = ? COLLATE NOCASE".to_string());
+            values.push(game_mode.clone().into());
+        }
+        if let Some(from) = query.from {
+            conditions.push("created >= ?".to_string());
+            values.push(from.into());
+        }
+        if let Some(to) = query.to {
+            conditions.push("created <= ?".to_string());
+            values.push(to.into());
+        }
+        if let Some(min_kda) = query.min_kda {
+            conditions.push(format!("{KDA} >= ?"));
+            values.push(min_kda.into());
+        }
+        if let Some(tag) = &query.tag {
+            conditions.push("EXISTS (SELECT 1 FROM json_each(tags) WHERE value = ? COLLATE NOCASE)".to_string());
+            values.push(tag.clone().into());
+        }
+        if let Some(favorite) = query.favorite {
+            conditions.push("favorite = ?".to_string());
+            values.push(favorite.into());
+        }
+        if let Some(protected) = query.protected {
+            conditions.push("protected = ?".to_string());
+            values.push(protected.into());
+        }
+        let filter = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+
+        self.with_db(|db| {
+            let total: i64 = db.query_row(
+                &format!("SELECT COUNT(*) FROM recordings {filter}"),
+                params_from_iter(&values),
+                |row| row.get(0),
+            )?;
+
+            let direction = if query.ascending { "ASC" } else { "DESC" };
+            let sql = format!(
+                "SELECT filename, size, created, game_data FROM recordings {filter}
+                ORDER BY {} {direction}, created DESC LIMIT ? OFFSET ?",
+                query.sort.column()
+            );
+            // a negative LIMIT means no limit
+            values.push(query.limit.map_or(-1, i64::from).into());
+            values.push(i64::from(query.offset).into());
+
+            let mut stmt = db.prepare(&sql)?;
+            let recordings = stmt.query_map(params_from_iter(&values), RecordingEntry::from_row)?;
+            Ok(RecordingPage {
+                total: total as u64,
+                recordings: recordings.collect::<Result<_, _>>()?,
+            })
+        })
+    }
+
+    /// The size of all recordings in bytes.
+    pub fn total_size(&self) -> u64 {
+        // TOTAL() always returns a float
+        let size: f64 = self.with_db(|db| db.query_row("SELECT TOTAL(size) FROM recordings", [], |row| row.get(0)));
+        size as u64
+    }
+
+    fn with_db<T: Default>(&self, f: impl FnOnce(&mut Connection) -> rusqlite::Result<T>) -> T {
+        let mut db = self.0.lock().unwrap();
+        let Some(db) = db.as_mut() else {
+            return T::default();
+        };
+        f(db).unwrap_or_else(|e| {
+            log::warn!("library index error: {e}");
+            T::default()
+        })
+    }
+}
+
+/// Opens the database at `path` (or in memory if there is no path) and makes sure the tables are up to date.
+fn open_db(path: Option<&Path>) -> rusqlite::Result<Connection> {
+    let db = match path {
+        Some(path) => Connection::open(path)?,
+        None => Connection::open_in_memory()?,
+    };
+
+    let version: i32 = db.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    if version != INDEX_VERSION {
+        db.execute_batch("DROP TABLE IF EXISTS recordings; DROP TABLE IF EXISTS info;")?;
+    }
+    db.execute_batch(
+        "CREATE TABLE IF NOT EXISTS recordings (
+            filename TEXT PRIMARY KEY NOT NULL,
+            size INTEGER NOT NULL,
+            created INTEGER NOT NULL,
+            modified INTEGER NOT NULL,
+            metadata_modified INTEGER,
+            game_data TEXT,
+            champion TEXT,
+            game_mode TEXT,
+            win INTEGER,
+            kills INTEGER,
+            deaths INTEGER,
+            assists INTEGER,
+            creep_score INTEGER,
+            vision_score REAL,
+            duration REAL,
+            clip INTEGER NOT NULL,
+            tags TEXT NOT NULL,
+            favorite INTEGER NOT NULL,
+            protected INTEGER NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS recordings_created ON recordings (created);
+        CREATE INDEX IF NOT EXISTS recordings_champion ON recordings (champion COLLATE NOCASE);
+        CREATE TABLE IF NOT EXISTS info (
+            key TEXT PRIMARY KEY NOT NULL,
+            value TEXT NOT NULL
+        );",
+    )?;
+    db.pragma_update(None, "user_version", INDEX_VERSION)?;
+    Ok(db)
+}
+
+/// Adds or updates the entry of a recording or removes it if the recording doesn't exist anymore.
+fn index_recording(db: &Connection, filename: &str, recording: &Path) -> rusqlite::Result<()> {
+    let Ok(file) = fs::metadata(recording) else {
+        db.execute("DELETE FROM recordings WHERE filename = ?1", [filename])?;
+        return Ok(());
+    };
+
+    let size = file.len() as i64;
+    let modified = file.modified().map(unix_millis).unwrap_or_default();
+    // not every platform / filesystem records the creation time
+    let created = file.created().map(unix_millis).unwrap_or(modified);
+    let metadata_modified = fs::metadata(recording.with_extension("json"))
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .map(unix_millis);
+
+    let indexed = db
+        .query_row(
+            "SELECT size, modified, metadata_modified FROM recordings WHERE filename = ?1",
+            [filename],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, Option<i64>>(2)?,
+                ))
+            },
+        )
+        .optional()?;
+    if indexed == Some((size, modified, metadata_modified)) {
+        return Ok(());
+    }
+
+    let metadata = helpers::read_metadata(recording);
+    // clips, exported parts of recordings and highlight reels carry the metadata of the games they are cut from
+    let clip = metadata.as_ref().is_some_and(|metadata| {
+        ["trigger", "source", "chapters"]
+            .iter()
+            .any(|key| metadata.get(key).is_some())
+    });
+    let game_data = metadata.and_then(|metadata| serde_json::from_value::<GameData>(metadata).ok());
+    let json = game_data
+        .as_ref()
+        .and_then(|game_data| serde_json::to_string(game_data).ok());
+    // the columns that can be filtered and sorted by
+    let game_info = game_data.as_ref().map(|game_data| &game_data.game_info);
+    let champion = game_info
+        .map(|info| &info.champion_name)
+        .filter(|name| !name.is_empty());
+    let game_mode = game_info.map(|info| &info.game_mode).filter(|mode| !mode.is_empty());
+    let win = game_data.as_ref().and_then(|game_data| game_data.win);
+    // NULL if there are no post game stats, so these games don't count as 0/0/0 (and don't match a minimum KDA)
+    let stats = game_data.as_ref().and_then(GameData::collected_stats);
+    let kills = stats.map(|stats| stats.kills as i64);
+    let deaths = stats.map(|stats| stats.deaths as i64);
+    let assists = stats.map(|stats| stats.assists as i64);
+    let creep_score = stats.map(|stats| (stats.minions_killed + stats.neutral_minions_killed) as i64);
+    let vision_score = stats.map(|stats| stats.ward_score);
+    // a recording that is still in progress has no duration yet
+    let duration = mp4::duration(recording).ok();
+    // json array
+    let tags = game_data
+        .as_ref()
+        .and_then(|game_data| serde_json::to_string(&game_data.tags).ok())
+        .unwrap_or_else(|| "[]".into());
+    let favorite = game_data.as_ref().is_some_and(|game_data| game_data.favorite);
+    let protected = game_data.as_ref().is_some_and(|game_data| game_data.protected);
+
+    db.execute(
+        "INSERT OR REPLACE INTO recordings (
+            filename, size, created, modified, metadata_modified, game_data,
+            champion, game_mode, win, kills, deaths, assists, creep_score, vision_score, duration, clip,
+            tags, favorite, protected
+        )
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)",
+        params![
+            filename,
+            size,
+            created,
+            modified,
+            metadata_modified,
+            json,
+            champion,
+            game_mode,
+            win,
+            kills,
+            deaths,
+            assists,
+            creep_score,
+            vision_score,
+            duration,
+            clip,
+            tags,
+            favorite,
+            protected
+        ],
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestGame {
+        filename: &'static str,
+        created: i64,
+        champion: &'static str,
+        game_mode: &'static str,
+        win: Option<bool>,
+        /// kills, deaths, assists
+        kda: Option<(i64, i64, i64)>,
+    }
+
+    fn library(games: &[TestGame]) -> Library {
+        let db = open_db(None).unwrap();
+        for game in games {
+            let (kills, deaths, assists) = match game.kda {
+                Some((kills, deaths, assists)) => (Some(kills), Some(deaths), Some(assists)),
+                None => (None, None, None),
+            };
+            db.execute(
+                "INSERT INTO recordings (
+                    filename, size, created, modified, champion, game_mode, win, kills, deaths, assists, clip, tags,
+                    favorite, protected
+                )
+                VALUES (?1, ?2, ?3, ?3, ?4, ?5, ?6, ?7, ?8, ?9, 0, '[]', 0, 0)",
+                params![
+                    game.filename,
+                    game.created * 10,
+                    game.created,
+                    game.champion,
+                    game.game_mode,
+                    game.win,
+                    kills,
+                    deaths,
+                    assists
+                ],
+            )
+            .unwrap();
+        }
+        Library(Mutex::new(Some(db)))
+    }
+
+    fn test_library() -> Library {
+        library(&[
+            TestGame {
+                filename: "a.mp4",
+                created: 100,
+                champion: "Ahri",
+                game_mode: "CLASSIC",
+                win: Some(true),
+                kda: Some((10, 2, 6)),
+            },
+            TestGame {
+                filename: "b.mp4",
+                created: 200,
+                champion: "Jinx",
+                game_mode: "ARAM",
+                win: Some(false),
+                kda: Some((3, 8, 5)),
+            },
+            TestGame {
+                filename: "c.mp4",
+                created: 300,
+                champion: "Ahri",
+                game_mode: "ARAM",
+                win: Some(false),
+                kda: Some((4, 0, 1)),
+            },
+            // interrupted game without post game stats
+            TestGame {
+                filename: "d.mp4",
+                created: 400,
+                champion: "Ahri",
+                game_mode: "CLASSIC",
+                win: None,
+                kda: None,
+            },
+        ])
+    }
+
+    fn filenames(page: &RecordingPage) -> Vec<&str> {
+        page.recordings
+            .iter()
+            .map(|recording| recording.filename.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn filters_recordings() {
+        let library = test_library();
+        let query = |query: RecordingQuery| filenames(&library.query(&query)).join(",");
+
+        assert_eq!(query(RecordingQuery::default()), "d.mp4,c.mp4,b.mp4,a.mp4");
+        let champion = Some(String::from("ahri"));
+        assert_eq!(
+            query(RecordingQuery {
+                champion: champion.clone(),
+                ..Default::default()
+            }),
+            "d.mp4,c.mp4,a.mp4"
+        );
+        assert_eq!(
+            query(RecordingQuery {
+                champion,
+                win: Some(false),
+                ..Default::default()
+            }),
+            "c.mp4"
+        );
+        assert_eq!(
+            query(RecordingQuery {
+                game_mode: Some(String::from("aram")),
+                ..Default::default()
+            }),
+            "c.mp4,b.mp4"
+        );
+        assert_eq!(
+            query(RecordingQuery {
+                from: Some(200),
+                to: Some(300),
+                ..Default::default()
+            }),
+            "c.mp4,b.mp4"
+        );
+        // games without stats don't have a KDA
+        assert_eq!(
+            query(RecordingQuery {
+                min_kda: Some(5.0),
+                ..Default::default()
+            }),
+            "c.mp4,a.mp4"
+        );
+        assert_eq!(
+            query(RecordingQuery {
+                min_kda: Some(0.0),
+                ..Default::default()
+            }),
+            "c.mp4,b.mp4,a.mp4"
+        );
+    }
+
+    #[test]
+    fn sorts_recordings() {
+        let library = test_library();
+        let query = |sort: RecordingSort, ascending: bool| {
+            let page = library.query(&RecordingQuery {
+                sort,
+                ascending,
+                min_kda: Some(0.0),
+                ..Default::default()
+            });
+            filenames(&page).join(",")
+        };
+
+        assert_eq!(query(RecordingSort::Date, true), "a.mp4,b.mp4,c.mp4");
+        assert_eq!(query(RecordingSort::Date, false), "c.mp4,b.mp4,a.mp4");
+        assert_eq!(query(RecordingSort::Kills, false), "a.mp4,c.mp4,b.mp4");
+        assert_eq!(query(RecordingSort::Deaths, true), "c.mp4,a.mp4,b.mp4");
+        assert_eq!(query(RecordingSort::Kda, false), "a.mp4,c.mp4,b.mp4");
+        assert_eq!(query(RecordingSort::Size, true), "a.mp4,b.mp4,c.mp4");
+        // equal champions are sorted by date (newest first)
+        assert_eq!(query(RecordingSort::Champion, true), "c.mp4,a.mp4,b.mp4");
+    }
+
+    #[test]
+    fn paginates_recordings() {
+        let library = test_library();
+        let page = |offset: u32, limit: Option<u32>| {
+            library.query(&RecordingQuery {
+                ascending: true,
+                offset,
+                limit,
+                ..Default::default()
+            })
+        };
+
+        let first = page(0, Some(3));
+        assert_eq!((first.total, filenames(&first)), (4, vec!["a.mp4", "b.mp4", "c.mp4"]));
+        let last = page(3, Some(3));
+        assert_eq!((last.total, filenames(&last)), (4, vec!["d.mp4"]));
+        let rest = page(1, None);
+        assert_eq!(filenames(&rest), ["b.mp4", "c.mp4", "d.mp4"]);
+        let outside = page(10, Some(3));
+        assert_eq!((outside.total, outside.recordings.len()), (4, 0));
+    }
+}
@@ -5,15 +5,24 @@
 
 use commands::*;
 use handlers::*;
+use library::Library;
+use recorder::recovery::BrokenRecordings;
 use state::*;
 
+mod annotations;
 mod commands;
 mod fileserver;
 mod filewatcher;
 mod handlers;
 mod helpers;
+mod layout;
+mod library;
+mod mp4;
 mod recorder;
+mod retention;
 mod state;
+mod statistics;
+mod trash;
 
 fn main() {
     // Only check if this is the only instance of LeagueRecord if the check succeeds (= true|false).
@@ -40,6 +49,8 @@ fn main() {
         .manage(SettingsFile::default())
         .manage(Settings::default())
         .manage(FileWatcher::default())
+        .manage(Library::default())
+        .manage(BrokenRecordings::default())
         .invoke_handler(tauri::generate_handler![
             show_app_window,
             get_default_marker_flags,
@@ -48,9 +59,31 @@ fn main() {
             get_asset_port,
             get_recordings_size,
             get_recordings_list,
+            query_recordings,
+            get_statistics,
+            export_statistics_csv,
+            save_replay_clip,
             open_recordings_folder,
             delete_video,
-            get_metadata
+            export_clip,
+            create_highlight_reel,
+            get_metadata,
+            set_tags,
+            set_favorite,
+            set_protected,
+            get_annotations,
+            add_annotation,
+            update_annotation,
+            delete_annotation,
+            regenerate_metadata_files,
+            migrate_library,
+            rebuild_library,
+            reorganize_library,
+            retention_dry_run,
+            list_trash,
+            restore_from_trash,
+            empty_trash,
+            take_broken_recordings
         ])
         .system_tray(create_system_tray())
         .on_system_tray_event(system_tray_event_handler)
@@ -0,0 +1,65 @@
+/*
+    Chapters in the Nero format (moov/udta/chpl).
+    Supported by ffmpeg based players (mpv, ...), VLC and most video editors.
+*/
+
+use std::path::Path;
+
+use super::{full_box_header, update_moov, Mp4Box, Mp4Error};
+
+/// chpl stores its times in 100ns units
+const CHPL_TIMESCALE: f64 = 10_000_000.0;
+/// chpl stores the number of chapters in a single byte
+const MAX_CHAPTERS: usize = 255;
+
+#[derive(Debug, Clone)]
+pub struct Chapter {
+    /// start of the chapter in seconds
+    pub start: f64,
+    pub title: String,
+}
+
+/// Writes the chapters into the MP4 file (replacing chapters that already exist).
+pub fn write_chapters(path: &Path, chapters: &[Chapter]) -> Result<(), Mp4Error> {
+    let mut chapters = chapters.to_vec();
+    chapters.sort_by(|a, b| a.start.total_cmp(&b.start));
+    if chapters.len() > MAX_CHAPTERS {
+        log::warn!("only writing the first {MAX_CHAPTERS} of {} chapters", chapters.len());
+        chapters.truncate(MAX_CHAPTERS);
+    }
+
+    update_moov(path, |moov| {
+        let udta = moov.container_mut(b"udta")?;
+        remove_chapters(udta);
+        if !chapters.is_empty() {
+            udta.children_mut().push(chapter_list(&chapters));
+        }
+        Ok(())
+    })
+}
+
+/// remove the chapters from a udta box (e.g. when they don't match the video anymore)
+pub fn remove_chapters(udta: &mut Mp4Box) {
+    udta.children_mut().retain(|child| !child.is(b"chpl"));
+}
+
+fn chapter_list(chapters: &[Chapter]) -> Mp4Box {
+    let mut data = Vec::new();
+    full_box_header(&mut data, 1, 0);
+    // reserved
+    data.extend_from_slice(&[0; 4]);
+    data.push(chapters.len() as u8);
+    for chapter in chapters {
+        let start = (chapter.start.max(0.0) * CHPL_TIMESCALE).round() as u64;
+        data.extend_from_slice(&start.to_be_bytes());
+
+        // the title length is a single byte as well
+        let mut title_len = chapter.title.len().min(255);
+        while !chapter.title.is_char_boundary(title_len) {
+            title_len -= 1;
+        }
+        data.push(title_len as u8);
+        data.extend_from_slice(&chapter.title.as_bytes()[..title_len]);
+    }
+    Mp4Box::new_data(b"chpl", data)
+}
@@ -0,0 +1,43 @@
+/*
+    The game metadata (the same json as in the sidecar file) embedded in a custom box in moov/udta.
+    Players ignore unknown udta boxes, so the recording stays playable everywhere.
+*/
+
+use std::{fs::File, path::Path};
+
+use super::{read_box_payload, top_level_boxes, update_moov, FourCC, Mp4Box, Mp4Error};
+
+/// LeagueRecord game data
+const GAME_DATA_BOX: &FourCC = b"lrgd";
+
+/// Embeds the metadata into the MP4 file (replacing metadata that already exists).
+pub fn write_game_data(path: &Path, metadata: &[u8]) -> Result<(), Mp4Error> {
+    update_moov(path, |moov| {
+        let udta = moov.container_mut(b"udta")?;
+        remove_game_data(udta);
+        udta.children_mut()
+            .push(Mp4Box::new_data(GAME_DATA_BOX, metadata.to_vec()));
+        Ok(())
+    })
+}
+
+/// The embedded metadata of the MP4 file if there is any.
+pub fn read_game_data(path: &Path) -> Result<Option<Vec<u8>>, Mp4Error> {
+    let mut file = File::open(path)?;
+    let boxes = top_level_boxes(&mut file)?;
+    let Some(location) = boxes.iter().find(|location| &location.kind == b"moov") else {
+        return Ok(None);
+    };
+
+    let mut moov = Mp4Box::parse(*b"moov", &read_box_payload(&mut file, location)?)?;
+    if moov.child(b"udta").is_none() {
+        return Ok(None);
+    }
+    let udta = moov.container_mut(b"udta")?;
+    Ok(udta.child(GAME_DATA_BOX).map(|game_data| game_data.data().to_vec()))
+}
+
+/// remove the metadata from a udta box (e.g. when it doesn't belong to the video anymore)
+pub fn remove_game_data(udta: &mut Mp4Box) {
+    udta.children_mut().retain(|child| !child.is(GAME_DATA_BOX));
+}
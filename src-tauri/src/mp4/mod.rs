@@ -0,0 +1,436 @@
+/*
+    Minimal MP4 (ISO BMFF) box reading and writing.
+    Only the boxes that are needed to restructure a recording get parsed - everything else is copied as is.
+    The media data (mdat) is never loaded into memory.
+*/
+
+use std::{
+    error::Error,
+    fmt::{self, Display},
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+mod chapters;
+mod metadata;
+mod trim;
+
+pub use chapters::{write_chapters, Chapter};
+pub use metadata::{read_game_data, write_game_data};
+pub use trim::{concat, defragment, media_info, trim, Segment, TrimRange};
+
+pub type FourCC = [u8; 4];
+
+/// boxes that only contain other boxes and that need to be looked into
+const CONTAINERS: [&FourCC; 6] = [b"moov", b"trak", b"edts", b"mdia", b"minf", b"stbl"];
+
+#[derive(Debug)]
+pub enum Mp4Error {
+    Io(io::Error),
+    /// the file is not a valid MP4 file
+    Invalid(String),
+    /// valid MP4 file but uses something that isn't supported (e.g. fragmented MP4)
+    Unsupported(String),
+}
+
+impl Display for Mp4Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mp4Error::Io(e) => write!(f, "io error: {e}"),
+            Mp4Error::Invalid(msg) => write!(f, "invalid mp4: {msg}"),
+            Mp4Error::Unsupported(msg) => write!(f, "unsupported mp4: {msg}"),
+        }
+    }
+}
+
+impl Error for Mp4Error {}
+
+impl From<io::Error> for Mp4Error {
+    fn from(e: io::Error) -> Self {
+        Mp4Error::Io(e)
+    }
+}
+
+pub fn invalid<T>(msg: impl Into<String>) -> Result<T, Mp4Error> {
+    Err(Mp4Error::Invalid(msg.into()))
+}
+
+/// position of a top level box in the file
+#[derive(Debug, Clone, Copy)]
+pub struct BoxLocation {
+    pub kind: FourCC,
+    pub offset: u64,
+    pub header_len: u64,
+    /// size including the header
+    pub size: u64,
+}
+
+impl BoxLocation {
+    pub fn payload_offset(&self) -> u64 {
+        self.offset + self.header_len
+    }
+
+    pub fn payload_len(&self) -> u64 {
+        self.size - self.header_len
+    }
+}
+
+/// list the top level boxes of a file without reading their contents
+pub fn top_level_boxes(file: &mut File) -> Result<Vec<BoxLocation>, Mp4Error> {
+    let (boxes, cut_off) = scan_top_level_boxes(file)?;
+    if let Some(location) = cut_off {
+        return invalid(format!(
+            "box '{}' at {} has an invalid size",
+            fourcc_str(&location.kind),
+            location.offset
+        ));
+    }
+    Ok(boxes)
+}
+
+/// Like `top_level_boxes` but doesn't fail on a box that doesn't fit into the file (e.g. the last box of a recording
+/// that got interrupted while it was written). Returns the complete boxes and the first broken box.
+pub fn scan_top_level_boxes(file: &mut File) -> Result<(Vec<BoxLocation>, Option<BoxLocation>), Mp4Error> {
+    let file_len = file.metadata()?.len();
+    let mut boxes = Vec::new();
+    let mut offset = 0;
+    while offset + 8 <= file_len {
+        file.seek(SeekFrom::Start(offset))?;
+        let mut header = [0; 8];
+        file.read_exact(&mut header)?;
+        let kind = [header[4], header[5], header[6], header[7]];
+        let (size, header_len) = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+            0 => (file_len - offset, 8),
+            1 if offset + 16 > file_len => (0, 16),
+            1 => {
+                let mut largesize = [0; 8];
+                file.read_exact(&mut largesize)?;
+                (u64::from_be_bytes(largesize), 16)
+            }
+            size => (u64::from(size), 8),
+        };
+        let location = BoxLocation { kind, offset, header_len, size };
+        if size < header_len || offset + size > file_len {
+            return Ok((boxes, Some(location)));
+        }
+
+        boxes.push(location);
+        offset += size;
+    }
+    Ok((boxes, None))
+}
+
+/// The duration of a finished MP4 file in seconds (from the movie header).
+pub fn duration(path: &Path) -> Result<f64, Mp4Error> {
+    let mut file = File::open(path)?;
+    let boxes = top_level_boxes(&mut file)?;
+    let Some(location) = boxes.iter().find(|location| &location.kind == b"moov") else {
+        return invalid("no moov box");
+    };
+    let moov = Mp4Box::parse(*b"moov", &read_box_payload(&mut file, location)?)?;
+
+    let mut mvhd = ByteReader::new(moov.require(&[b"mvhd"])?.data());
+    let (version, _) = mvhd.full_box_header()?;
+    // skip creation and modification time
+    let (timescale, duration) = if version == 1 {
+        mvhd.skip(16)?;
+        (mvhd.u32()?, mvhd.u64()?)
+    } else {
+        mvhd.skip(8)?;
+        (mvhd.u32()?, u64::from(mvhd.u32()?))
+    };
+    if timescale == 0 {
+        return invalid("timescale is 0");
+    }
+    Ok(duration as f64 / f64::from(timescale))
+}
+
+pub fn read_box_payload(file: &mut File, location: &BoxLocation) -> Result<Vec<u8>, Mp4Error> {
+    let len = usize::try_from(location.payload_len()).or_else(|_| invalid("box too large"))?;
+    let mut payload = vec![0; len];
+    file.seek(SeekFrom::Start(location.payload_offset()))?;
+    file.read_exact(&mut payload)?;
+    Ok(payload)
+}
+
+/// Changes the moov box of a finished MP4 file - the media data stays where it is.
+/// The new moov box always gets appended and only then the old one is turned into a free box, so there is a valid moov
+/// box in the file at any time (even if the app crashes or the disk is full while it gets written).
+pub fn update_moov(path: &Path, update: impl FnOnce(&mut Mp4Box) -> Result<(), Mp4Error>) -> Result<(), Mp4Error> {
+    let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+    let boxes = top_level_boxes(&mut file)?;
+    let Some(location) = boxes.iter().find(|location| &location.kind == b"moov").copied() else {
+        return invalid("missing moov box (the recording probably didn't finish)");
+    };
+    if boxes.iter().any(|location| &location.kind == b"moof") {
+        return Err(Mp4Error::Unsupported(String::from("fragmented mp4")));
+    }
+
+    let mut moov = Mp4Box::parse(*b"moov", &read_box_payload(&mut file, &location)?)?;
+    update(&mut moov)?;
+    let moov = moov.to_bytes();
+
+    // the old moov box keeps its size - a size of 0 ("until the end of the file") would swallow the new one though
+    let (free_offset, free_header) = match location.header_len {
+        8 => {
+            let size = u32::try_from(location.size).or_else(|_| invalid("moov box too large"))?;
+            (location.offset, [size.to_be_bytes(), *b"free"].concat())
+        }
+        _ => (location.offset + 4, b"free".to_vec()),
+    };
+
+    let file_len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(file_len))?;
+    if let Err(e) = file.write_all(&moov).and_then(|_| file.sync_data()) {
+        // don't leave a broken box at the end of the file
+        _ = file.set_len(file_len);
+        return Err(e.into());
+    }
+
+    file.seek(SeekFrom::Start(free_offset))?;
+    file.write_all(&free_header)?;
+    file.flush()?;
+    Ok(())
+}
+
+pub fn fourcc_str(kind: &FourCC) -> String {
+    String::from_utf8_lossy(kind).into_owned()
+}
+
+/// a box that is held in memory
+#[derive(Debug, Clone)]
+pub struct Mp4Box {
+    pub kind: FourCC,
+    pub content: BoxContent,
+}
+
+#[derive(Debug, Clone)]
+pub enum BoxContent {
+    Children(Vec<Mp4Box>),
+    Data(Vec<u8>),
+}
+
+impl Mp4Box {
+    pub fn new_data(kind: &FourCC, data: Vec<u8>) -> Self {
+        Self {
+            kind: *kind,
+            content: BoxContent::Data(data),
+        }
+    }
+
+    pub fn new_container(kind: &FourCC, children: Vec<Mp4Box>) -> Self {
+        Self {
+            kind: *kind,
+            content: BoxContent::Children(children),
+        }
+    }
+
+    /// parse a box payload - containers get parsed recursively
+    pub fn parse(kind: FourCC, payload: &[u8]) -> Result<Self, Mp4Error> {
+        let content = if CONTAINERS.contains(&&kind) {
+            BoxContent::Children(parse_boxes(payload)?)
+        } else {
+            BoxContent::Data(payload.to_vec())
+        };
+        Ok(Self { kind, content })
+    }
+
+    pub fn is(&self, kind: &FourCC) -> bool {
+        &self.kind == kind
+    }
+
+    pub fn children(&self) -> &[Mp4Box] {
+        match &self.content {
+            BoxContent::Children(children) => children,
+            BoxContent::Data(_) => &[],
+        }
+    }
+
+    /// panics if the box is not a container
+    pub fn children_mut(&mut self) -> &mut Vec<Mp4Box> {
+        match &mut self.content {
+            BoxContent::Children(children) => children,
+            BoxContent::Data(_) => panic!("box '{}' is not a container", fourcc_str(&self.kind)),
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        match &self.content {
+            BoxContent::Data(data) => data,
+            BoxContent::Children(_) => &[],
+        }
+    }
+
+    pub fn data_mut(&mut self) -> Option<&mut Vec<u8>> {
+        match &mut self.content {
+            BoxContent::Data(data) => Some(data),
+            BoxContent::Children(_) => None,
+        }
+    }
+
+    pub fn child(&self, kind: &FourCC) -> Option<&Mp4Box> {
+        self.children().iter().find(|child| child.is(kind))
+    }
+
+    pub fn child_mut(&mut self, kind: &FourCC) -> Option<&mut Mp4Box> {
+        match &mut self.content {
+            BoxContent::Children(children) => children.iter_mut().find(|child| child.is(kind)),
+            BoxContent::Data(_) => None,
+        }
+    }
+
+    /// the child container with this type - a child that hasn't been parsed yet (e.g. udta) gets parsed and a
+    /// missing one gets created
+    pub fn container_mut(&mut self, kind: &FourCC) -> Result<&mut Mp4Box, Mp4Error> {
+        let children = self.children_mut();
+        let index = match children.iter().position(|child| child.is(kind)) {
+            Some(index) => index,
+            None => {
+                children.push(Mp4Box::new_container(kind, Vec::new()));
+                children.len() - 1
+            }
+        };
+        let child = &mut children[index];
+        if let BoxContent::Data(data) = &child.content {
+            child.content = BoxContent::Children(parse_boxes(data)?);
+        }
+        Ok(child)
+    }
+
+    /// follow a path of box types, e.g. `[b"mdia", b"minf", b"stbl"]`
+    pub fn descend(&self, path: &[&FourCC]) -> Option<&Mp4Box> {
+        path.iter().try_fold(self, |current, kind| current.child(kind))
+    }
+
+    pub fn descend_mut(&mut self, path: &[&FourCC]) -> Option<&mut Mp4Box> {
+        path.iter().try_fold(self, |current, kind| current.child_mut(kind))
+    }
+
+    /// the required box with this path or an error
+    pub fn require(&self, path: &[&FourCC]) -> Result<&Mp4Box, Mp4Error> {
+        self.descend(path).ok_or_else(|| {
+            let path: Vec<String> = path.iter().map(|kind| fourcc_str(kind)).collect();
+            Mp4Error::Invalid(format!("missing box '{}'", path.join("/")))
+        })
+    }
+
+    pub fn write(&self, out: &mut Vec<u8>) {
+        let start = out.len();
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&self.kind);
+        match &self.content {
+            BoxContent::Data(data) => out.extend_from_slice(data),
+            BoxContent::Children(children) => children.iter().for_each(|child| child.write(out)),
+        }
+        let size = out.len() - start;
+        match u32::try_from(size) {
+            Ok(size) => out[start..start + 4].copy_from_slice(&size.to_be_bytes()),
+            Err(_) => {
+                // switch to a 64 bit size
+                out[start..start + 4].copy_from_slice(&1u32.to_be_bytes());
+                let largesize = (size + 8) as u64;
+                out.splice(start + 8..start + 8, largesize.to_be_bytes());
+            }
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write(&mut out);
+        out
+    }
+}
+
+pub fn parse_boxes(mut data: &[u8]) -> Result<Vec<Mp4Box>, Mp4Error> {
+    let mut boxes = Vec::new();
+    while data.len() >= 8 {
+        let mut reader = ByteReader::new(data);
+        let size = reader.u32()?;
+        let kind = reader.fourcc()?;
+        let (size, header_len) = match size {
+            0 => (data.len(), 8),
+            1 => {
+                let largesize = usize::try_from(reader.u64()?).or_else(|_| invalid("box too large"))?;
+                (largesize, 16)
+            }
+            size => (size as usize, 8),
+        };
+        if size < header_len || size > data.len() {
+            return invalid(format!("box '{}' has an invalid size", fourcc_str(&kind)));
+        }
+
+        boxes.push(Mp4Box::parse(kind, &data[header_len..size])?);
+        data = &data[size..];
+    }
+    Ok(boxes)
+}
+
+/// big endian reader for box payloads
+pub struct ByteReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], Mp4Error> {
+        let Some(bytes) = self.data.get(self.pos..self.pos + len) else {
+            return invalid("unexpected end of box");
+        };
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub fn skip(&mut self, len: usize) -> Result<(), Mp4Error> {
+        self.bytes(len).map(|_| ())
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Mp4Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u32(&mut self) -> Result<u32, Mp4Error> {
+        Ok(u32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn i32(&mut self) -> Result<i32, Mp4Error> {
+        Ok(i32::from_be_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, Mp4Error> {
+        Ok(u64::from_be_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    pub fn i64(&mut self) -> Result<i64, Mp4Error> {
+        Ok(i64::from_be_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    pub fn fourcc(&mut self) -> Result<FourCC, Mp4Error> {
+        Ok(self.bytes(4)?.try_into().unwrap())
+    }
+
+    /// version and flags of a full box
+    pub fn full_box_header(&mut self) -> Result<(u8, u32), Mp4Error> {
+        let version = self.u8()?;
+        let flags = self.bytes(3)?;
+        Ok((version, u32::from_be_bytes([0, flags[0], flags[1], flags[2]])))
+    }
+
+    /// a table entry count that has to fit into the rest of the box
+    pub fn entry_count(&mut self, entry_len: usize) -> Result<usize, Mp4Error> {
+        let count = self.u32()? as usize;
+        if count.saturating_mul(entry_len) > self.data.len() - self.pos {
+            return invalid("table entry count larger than the box");
+        }
+        Ok(count)
+    }
+}
+
+pub fn full_box_header(out: &mut Vec<u8>, version: u8, flags: u32) {
+    out.push(version);
+    out.extend_from_slice(&flags.to_be_bytes()[1..]);
+}
@@ -0,0 +1,1751 @@
+/*
+    Lossless trimming and concatenation of MP4 files.
+    The sample tables of every track get rebuilt for the selected ranges and only the samples in those ranges
+    get copied into the new mdat. Nothing gets re-encoded, so each cut starts at the keyframe before `start`
+    and ends at the keyframe after `end`.
+    Fragmented MP4 files (the samples are described by moof boxes instead of the moov box) are read as well, so
+    copying all of their samples turns them into a regular MP4 file (`defragment`).
+*/
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use super::{
+    chapters::remove_chapters, full_box_header, invalid, metadata::remove_game_data, parse_boxes, read_box_payload,
+    top_level_boxes, BoxLocation, ByteReader, FourCC, Mp4Box, Mp4Error,
+};
+
+/// sample tables that get rebuilt - everything else in the stbl box is kept
+const SAMPLE_TABLES: [&[u8; 4]; 12] = [
+    b"stts", b"ctts", b"stss", b"stsz", b"stz2", b"stsc", b"stco", b"co64", b"sdtp", b"sbgp", b"cslg", b"stps",
+];
+
+/// the range that actually got cut from the source in seconds
+#[derive(Debug, Clone, Copy)]
+pub struct TrimRange {
+    pub start: f64,
+    pub end: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    offset: u64,
+    size: u32,
+    dts: u64,
+    duration: u32,
+    /// composition time offset
+    cto: i64,
+    sync: bool,
+    description: u32,
+    /// index of the segment (source file) the sample gets copied from
+    segment: usize,
+}
+
+impl Sample {
+    fn pts(&self) -> i64 {
+        self.dts as i64 + self.cto
+    }
+}
+
+struct Track {
+    id: u32,
+    timescale: u32,
+    handler: FourCC,
+    is_video: bool,
+    /// contents of the stsd box (codec parameters)
+    sample_descriptions: Vec<u8>,
+    /// gets added to the pts of a sample to get its presentation time (from the edit list)
+    presentation_offset: i64,
+    has_sync_table: bool,
+    samples: Vec<Sample>,
+}
+
+/// the samples `first..end` of a track that end up in the new file
+#[derive(Clone, Copy)]
+struct Selection {
+    first: usize,
+    end: usize,
+    /// start of the presentation in the media timeline of the new track
+    media_time: i64,
+    /// time before the first sample gets presented (in media timescale)
+    delay: u64,
+}
+
+struct Chunk {
+    /// offset relative to the start of the mdat payload
+    offset: u64,
+    samples: u32,
+    description: u32,
+}
+
+/// what is needed to join recordings by re-encoding them when they can't be joined losslessly
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MediaInfo {
+    pub width: u32,
+    pub height: u32,
+    pub has_audio: bool,
+    /// in seconds
+    pub duration: f64,
+}
+
+/// a range of a recording that ends up in the output
+#[derive(Debug, Clone)]
+pub struct Segment {
+    pub source: PathBuf,
+    pub start: f64,
+    pub end: f64,
+}
+
+/// a parsed source file - only the moov box is held in memory
+struct Source {
+    file: File,
+    ftyp: Mp4Box,
+    moov: Mp4Box,
+    movie_timescale: u32,
+    tracks: Vec<Track>,
+}
+
+/// the sample defaults of a track in a fragmented file (from trex and tfhd)
+#[derive(Debug, Clone, Copy, Default)]
+struct FragmentDefaults {
+    description: u32,
+    duration: u32,
+    size: u32,
+    flags: u32,
+}
+
+/// the samples of one output track
+struct OutputTrack {
+    timescale: u32,
+    has_sync_table: bool,
+    samples: Vec<Sample>,
+    media_time: i64,
+    delay: u64,
+}
+
+/// Copies the range `[start, end]` (in seconds) of `source` into a new MP4 file at `destination`.
+/// Fails if `destination` already exists.
+pub fn trim(source: &Path, destination: &Path, start: f64, end: f64) -> Result<TrimRange, Mp4Error> {
+    let segment = Segment {
+        source: source.to_path_buf(),
+        start,
+        end,
+    };
+    concat(&[segment], destination).map(|ranges| ranges[0])
+}
+
+/// Joins the segments into a new MP4 file at `destination` and returns the ranges that actually got cut.
+/// All sources need the same tracks with the same codec parameters since nothing gets re-encoded.
+/// Fails if `destination` already exists.
+pub fn concat(segments: &[Segment], destination: &Path) -> Result<Vec<TrimRange>, Mp4Error> {
+    if segments.is_empty() {
+        return invalid("no segments");
+    }
+
+    let mut sources = Vec::with_capacity(segments.len());
+    let mut selections = Vec::with_capacity(segments.len());
+    let mut ranges = Vec::with_capacity(segments.len());
+    for (i, segment) in segments.iter().enumerate() {
+        let (start, end) = (segment.start, segment.end);
+        if start.is_nan() || end.is_nan() || end <= start {
+            return invalid(format!("invalid range {start}-{end} of segment {i}"));
+        }
+
+        let source = Source::open(&segment.source)?;
+        if let Some(first) = sources.first() {
+            if !source.is_compatible(first) {
+                return Err(Mp4Error::Unsupported(format!(
+                    "segment {i} has different tracks or codec parameters than the first segment"
+                )));
+            }
+        }
+        let (selection, range) = source.select(start.max(0.0), end)?;
+
+        sources.push(source);
+        selections.push(selection);
+        ranges.push(range);
+    }
+
+    create_output(destination, |file| write_output(&sources, &selections, &ranges, file)).map(|_| ranges)
+}
+
+/// Copies all samples of the fragmented MP4 file `source` into a regular MP4 file at `destination`.
+/// Fails if `destination` already exists.
+pub fn defragment(source: &Path, destination: &Path) -> Result<(), Mp4Error> {
+    let mut source = Source::open(source)?;
+    // the brands of a fragmented file (e.g. isml for ismv) don't describe the new file
+    let mut ftyp = b"isom".to_vec();
+    ftyp.extend_from_slice(&512u32.to_be_bytes());
+    ftyp.extend_from_slice(b"isomiso2avc1mp41");
+    source.ftyp = Mp4Box::new_data(b"ftyp", ftyp);
+
+    if source.tracks.iter().all(|track| track.samples.is_empty()) {
+        return invalid("no samples");
+    }
+
+    let mut duration: f64 = 0.0;
+    let selections = source
+        .tracks
+        .iter()
+        .map(|track| {
+            duration = duration.max(track.time_to_seconds(track.presentation_end()));
+            (!track.samples.is_empty()).then(|| Selection {
+                first: 0,
+                end: track.samples.len(),
+                // keep the edit list of the source
+                media_time: (-track.presentation_offset).max(0),
+                delay: track.presentation_offset.max(0) as u64,
+            })
+        })
+        .collect();
+    let range = TrimRange { start: 0.0, end: duration };
+    create_output(destination, |file| {
+        write_output(&[source], &[selections], &[range], file)
+    })
+}
+
+/// never overwrite an existing file - and only remove the file again if it was created here
+fn create_output(destination: &Path, write: impl FnOnce(File) -> Result<(), Mp4Error>) -> Result<(), Mp4Error> {
+    let file = OpenOptions::new().write(true).create_new(true).open(destination)?;
+    let result = write(file);
+    if result.is_err() {
+        _ = fs::remove_file(destination);
+    }
+    result
+}
+
+/// The size of the video track, whether there is an audio track and how long the longest track is.
+pub fn media_info(path: &Path) -> Result<MediaInfo, Mp4Error> {
+    let source = Source::open(path)?;
+    let traks = source.moov.children().iter().filter(|child| child.is(b"trak"));
+
+    let mut size = None;
+    let mut has_audio = false;
+    let mut duration: f64 = 0.0;
+    for (trak, track) in traks.zip(&source.tracks) {
+        if track.is_video && size.is_none() {
+            size = Some(read_track_size(trak.require(&[b"tkhd"])?.data())?);
+        }
+        has_audio |= &track.handler == b"soun";
+        let track_duration: u64 = track.samples.iter().map(|sample| u64::from(sample.duration)).sum();
+        duration = duration.max(track_duration as f64 / f64::from(track.timescale));
+    }
+
+    let Some((width, height)) = size else {
+        return invalid("no video track");
+    };
+    Ok(MediaInfo {
+        width,
+        height,
+        has_audio,
+        duration,
+    })
+}
+
+impl Source {
+    fn open(path: &Path) -> Result<Self, Mp4Error> {
+        let mut file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let boxes = top_level_boxes(&mut file)?;
+        let Some(ftyp) = boxes.iter().find(|location| &location.kind == b"ftyp") else {
+            return invalid("missing ftyp box");
+        };
+        let Some(moov) = boxes.iter().find(|location| &location.kind == b"moov") else {
+            return invalid("missing moov box (the recording probably didn't finish)");
+        };
+        let ftyp = Mp4Box::new_data(b"ftyp", read_box_payload(&mut file, ftyp)?);
+        let mut moov = Mp4Box::parse(*b"moov", &read_box_payload(&mut file, moov)?)?;
+
+        let movie_timescale = read_timescale(moov.require(&[b"mvhd"])?)?;
+        let mut tracks = moov
+            .children()
+            .iter()
+            .filter(|child| child.is(b"trak"))
+            .map(|trak| Track::parse(trak, movie_timescale, file_len))
+            .collect::<Result<Vec<_>, _>>()?;
+        if tracks.is_empty() {
+            return invalid("no tracks");
+        }
+
+        // the samples of a fragmented file get appended to the (usually empty) sample tables of the moov box
+        let fragments: Vec<&BoxLocation> = boxes.iter().filter(|location| &location.kind == b"moof").collect();
+        if !fragments.is_empty() || moov.child(b"mvex").is_some() {
+            let defaults = match moov.child(b"mvex") {
+                Some(mvex) => parse_boxes(mvex.data())?
+                    .iter()
+                    .filter(|child| child.is(b"trex"))
+                    .map(|trex| parse_track_extends(trex.data()))
+                    .collect::<Result<Vec<_>, _>>()?,
+                None => Vec::new(),
+            };
+            for moof in fragments {
+                let payload = read_box_payload(&mut file, moof)?;
+                parse_fragment(&payload, moof.offset, &defaults, &mut tracks, file_len)?;
+            }
+            for track in &mut tracks {
+                track.has_sync_table |= track.samples.iter().any(|sample| !sample.sync);
+            }
+            // the output has all samples in the moov box
+            moov.children_mut().retain(|child| !child.is(b"mvex"));
+        }
+
+        Ok(Self {
+            file,
+            ftyp,
+            moov,
+            movie_timescale,
+            tracks,
+        })
+    }
+
+    fn is_compatible(&self, other: &Source) -> bool {
+        self.tracks.len() == other.tracks.len()
+            && self.tracks.iter().zip(&other.tracks).all(|(a, b)| {
+                a.handler == b.handler && a.timescale == b.timescale && a.sample_descriptions == b.sample_descriptions
+            })
+    }
+
+    /// select the samples of every track - the video track decides where the range starts and ends
+    fn select(&self, start: f64, end: f64) -> Result<(Vec<Option<Selection>>, TrimRange), Mp4Error> {
+        let reference = self.tracks.iter().position(|track| track.is_video).unwrap_or(0);
+        let (reference_selection, range) = self.tracks[reference].select_keyframes(start, end)?;
+        let selections = self
+            .tracks
+            .iter()
+            .enumerate()
+            .map(|(i, track)| {
+                if i == reference {
+                    Some(reference_selection)
+                } else {
+                    track.select_range(range)
+                }
+            })
+            .collect();
+        Ok((selections, range))
+    }
+}
+
+impl Track {
+    fn parse(trak: &Mp4Box, movie_timescale: u32, file_len: u64) -> Result<Self, Mp4Error> {
+        let id = read_track_id(trak.require(&[b"tkhd"])?.data())?;
+        let timescale = read_timescale(trak.require(&[b"mdia", b"mdhd"])?)?;
+        let hdlr = trak.require(&[b"mdia", b"hdlr"])?.data();
+        let Some(handler) = hdlr.get(8..12).map(|handler| FourCC::try_from(handler).unwrap()) else {
+            return invalid("hdlr box too short");
+        };
+
+        let presentation_offset = match trak.descend(&[b"edts", b"elst"]) {
+            Some(elst) => parse_edit_list(elst.data(), timescale, movie_timescale)?,
+            None => 0,
+        };
+
+        let stbl = trak.require(&[b"mdia", b"minf", b"stbl"])?;
+        let sample_descriptions = stbl.require(&[b"stsd"])?.data().to_vec();
+        let has_sync_table = stbl.child(b"stss").is_some();
+        let samples = parse_samples(stbl, file_len)?;
+
+        Ok(Self {
+            id,
+            timescale,
+            handler,
+            is_video: &handler == b"vide",
+            sample_descriptions,
+            presentation_offset,
+            has_sync_table,
+            samples,
+        })
+    }
+
+    fn presentation_time(&self, sample: &Sample) -> i64 {
+        sample.pts() + self.presentation_offset
+    }
+
+    fn presentation_end(&self) -> i64 {
+        let end = self
+            .samples
+            .iter()
+            .map(|sample| sample.pts() + i64::from(sample.duration))
+            .max();
+        end.unwrap_or(0) + self.presentation_offset
+    }
+
+    fn time_to_seconds(&self, time: i64) -> f64 {
+        time as f64 / f64::from(self.timescale)
+    }
+
+    fn seconds_to_time(&self, seconds: f64) -> i64 {
+        (seconds * f64::from(self.timescale)).round() as i64
+    }
+
+    /// select the samples from the keyframe before `start` up to the keyframe after `end`
+    fn select_keyframes(&self, start: f64, end: f64) -> Result<(Selection, TrimRange), Mp4Error> {
+        let (start_time, end_time) = (self.seconds_to_time(start), self.seconds_to_time(end));
+        if start_time >= self.presentation_end() {
+            return invalid("range is outside of the recording");
+        }
+        let keyframes: Vec<usize> = (0..self.samples.len()).filter(|&i| self.samples[i].sync).collect();
+        let Some(&first_keyframe) = keyframes.first() else {
+            return invalid("no keyframes");
+        };
+
+        let first = keyframes
+            .iter()
+            .copied()
+            .take_while(|&i| self.presentation_time(&self.samples[i]) <= start_time)
+            .last()
+            .unwrap_or(first_keyframe);
+        let end = keyframes
+            .iter()
+            .copied()
+            .find(|&i| i > first && self.presentation_time(&self.samples[i]) >= end_time)
+            .unwrap_or(self.samples.len());
+
+        let range_start = self.presentation_time(&self.samples[first]);
+        let range_end = match self.samples.get(end) {
+            Some(sample) => self.presentation_time(sample),
+            None => self.presentation_end(),
+        };
+        if range_end <= range_start || range_start >= end_time {
+            return invalid("range is outside of the recording");
+        }
+
+        let selection = Selection {
+            first,
+            end,
+            // the first keyframe gets presented right at the start
+            media_time: self.samples[first].cto.max(0),
+            delay: 0,
+        };
+        let range = TrimRange {
+            start: self.time_to_seconds(range_start).max(0.0),
+            end: self.time_to_seconds(range_end),
+        };
+        Ok((selection, range))
+    }
+
+    /// select the samples that cover `range` (e.g. audio samples for the range of the video track)
+    fn select_range(&self, range: TrimRange) -> Option<Selection> {
+        let start_dts = self.seconds_to_time(range.start) - self.presentation_offset;
+        let end_dts = self.seconds_to_time(range.end) - self.presentation_offset;
+
+        let first = (0..self.samples.len())
+            .take_while(|&i| self.samples[i].dts as i64 <= start_dts)
+            .filter(|&i| self.samples[i].sync)
+            .last()
+            .unwrap_or(0);
+        let end = (first..self.samples.len())
+            .find(|&i| self.samples[i].dts as i64 >= end_dts)
+            .unwrap_or(self.samples.len());
+        if end <= first {
+            return None;
+        }
+
+        let first_sample = &self.samples[first];
+        let media_time = start_dts - first_sample.dts as i64 + first_sample.cto;
+        Some(Selection {
+            first,
+            end,
+            media_time: media_time.max(0),
+            // the track starts after the range starts
+            delay: (-media_time).max(0) as u64,
+        })
+    }
+}
+
+fn read_timescale(header: &Mp4Box) -> Result<u32, Mp4Error> {
+    // mvhd and mdhd have the same layout up to the duration
+    let data = header.data();
+    let offset = if data.first() == Some(&1) { 20 } else { 12 };
+    let timescale = ByteReader::new(data.get(offset..).unwrap_or_default()).u32()?;
+    if timescale == 0 {
+        return invalid("timescale is 0");
+    }
+    Ok(timescale)
+}
+
+/// write the duration of a mvhd, mdhd or tkhd box
+fn set_duration(header: &mut Mp4Box, duration: u64) -> Result<(), Mp4Error> {
+    let (v0_offset, v1_offset) = if header.is(b"tkhd") { (20, 28) } else { (16, 24) };
+    let Some(data) = header.data_mut() else {
+        return invalid("header box is a container");
+    };
+    let bytes = if data.first() == Some(&1) {
+        data.get_mut(v1_offset..v1_offset + 8)
+            .map(|bytes| (bytes, duration.to_be_bytes().to_vec()))
+    } else {
+        let duration = u32::try_from(duration).unwrap_or(u32::MAX);
+        data.get_mut(v0_offset..v0_offset + 4)
+            .map(|bytes| (bytes, duration.to_be_bytes().to_vec()))
+    };
+    match bytes {
+        Some((bytes, duration)) => {
+            bytes.copy_from_slice(&duration);
+            Ok(())
+        }
+        None => invalid("header box too short"),
+    }
+}
+
+/// width and height from the tkhd box (16.16 fixed point numbers at the end of the box)
+fn read_track_size(tkhd: &[u8]) -> Result<(u32, u32), Mp4Error> {
+    let mut reader = ByteReader::new(tkhd);
+    let (version, _) = reader.full_box_header()?;
+    // times, track id, duration, reserved, layer, alternate group, volume and the matrix
+    reader.skip(if version == 1 { 84 } else { 72 })?;
+    Ok((reader.u32()? >> 16, reader.u32()? >> 16))
+}
+
+fn read_track_id(tkhd: &[u8]) -> Result<u32, Mp4Error> {
+    let mut reader = ByteReader::new(tkhd);
+    let (version, _) = reader.full_box_header()?;
+    // creation and modification time
+    reader.skip(if version == 1 { 16 } else { 8 })?;
+    reader.u32()
+}
+
+fn parse_edit_list(data: &[u8], timescale: u32, movie_timescale: u32) -> Result<i64, Mp4Error> {
+    let mut reader = ByteReader::new(data);
+    let (version, _) = reader.full_box_header()?;
+    let count = reader.entry_count(if version == 1 { 20 } else { 12 })?;
+
+    let mut delay = 0;
+    for _ in 0..count {
+        let (segment_duration, media_time) = if version == 1 {
+            (reader.u64()?, reader.i64()?)
+        } else {
+            (u64::from(reader.u32()?), i64::from(reader.i32()?))
+        };
+        reader.skip(4)?;
+
+        // empty edit
+        if media_time == -1 {
+            delay += rescale(segment_duration, movie_timescale, timescale) as i64;
+        } else {
+            return Ok(delay - media_time);
+        }
+    }
+    Ok(delay)
+}
+
+/// `file_len` is the length of the whole file - the samples have to fit into it
+fn parse_samples(stbl: &Mp4Box, file_len: u64) -> Result<Vec<Sample>, Mp4Error> {
+    if stbl.child(b"stsz").is_none() && stbl.child(b"stz2").is_some() {
+        return Err(Mp4Error::Unsupported("compact sample sizes (stz2)".into()));
+    }
+
+    let mut reader = ByteReader::new(stbl.require(&[b"stsz"])?.data());
+    reader.full_box_header()?;
+    let sample_size = reader.u32()?;
+    let sizes = if sample_size == 0 {
+        let count = reader.entry_count(4)?;
+        (0..count).map(|_| reader.u32()).collect::<Result<Vec<_>, _>>()?
+    } else {
+        // with a constant sample size there are no entries the count could be checked against
+        let count = reader.u32()?;
+        if u64::from(count) * u64::from(sample_size) > file_len {
+            return invalid("stsz sample count larger than the file");
+        }
+        vec![sample_size; count as usize]
+    };
+
+    let mut reader = ByteReader::new(stbl.require(&[b"stts"])?.data());
+    reader.full_box_header()?;
+    let mut durations = Vec::with_capacity(sizes.len());
+    for _ in 0..reader.entry_count(8)? {
+        let (count, delta) = (reader.u32()?, reader.u32()?);
+        let count = (count as usize).min(sizes.len() - durations.len());
+        durations.resize(durations.len() + count, delta);
+    }
+    if durations.len() != sizes.len() {
+        return invalid("stts and stsz sample counts don't match");
+    }
+
+    let mut ctos = Vec::new();
+    if let Some(ctts) = stbl.child(b"ctts") {
+        let mut reader = ByteReader::new(ctts.data());
+        let (version, _) = reader.full_box_header()?;
+        for _ in 0..reader.entry_count(8)? {
+            let count = reader.u32()?;
+            let cto = if version == 1 {
+                i64::from(reader.i32()?)
+            } else {
+                i64::from(reader.u32()?)
+            };
+            let count = (count as usize).min(sizes.len() - ctos.len());
+            ctos.resize(ctos.len() + count, cto);
+        }
+    }
+
+    let mut sync = vec![stbl.child(b"stss").is_none(); sizes.len()];
+    if let Some(stss) = stbl.child(b"stss") {
+        let mut reader = ByteReader::new(stss.data());
+        reader.full_box_header()?;
+        for _ in 0..reader.entry_count(4)? {
+            if let Some(sample) = (reader.u32()? as usize).checked_sub(1).and_then(|i| sync.get_mut(i)) {
+                *sample = true;
+            }
+        }
+    }
+
+    let chunk_offsets = if let Some(stco) = stbl.child(b"stco") {
+        let mut reader = ByteReader::new(stco.data());
+        reader.full_box_header()?;
+        let count = reader.entry_count(4)?;
+        (0..count)
+            .map(|_| reader.u32().map(u64::from))
+            .collect::<Result<Vec<_>, _>>()?
+    } else {
+        let mut reader = ByteReader::new(stbl.require(&[b"co64"])?.data());
+        reader.full_box_header()?;
+        let count = reader.entry_count(8)?;
+        (0..count).map(|_| reader.u64()).collect::<Result<Vec<_>, _>>()?
+    };
+
+    let mut reader = ByteReader::new(stbl.require(&[b"stsc"])?.data());
+    reader.full_box_header()?;
+    let stsc = (0..reader.entry_count(12)?)
+        .map(|_| Ok((reader.u32()?, reader.u32()?, reader.u32()?)))
+        .collect::<Result<Vec<_>, Mp4Error>>()?;
+
+    let mut samples = Vec::with_capacity(sizes.len());
+    let mut dts = 0;
+    for (i, &(first_chunk, samples_per_chunk, description)) in stsc.iter().enumerate() {
+        let last_chunk = stsc
+            .get(i + 1)
+            .map_or(chunk_offsets.len(), |next| (next.0 as usize).saturating_sub(1));
+        if first_chunk == 0 {
+            return invalid("stsc chunk numbers start at 1");
+        }
+
+        for chunk in first_chunk as usize..=last_chunk {
+            let Some(&chunk_offset) = chunk_offsets.get(chunk - 1) else {
+                return invalid("stsc references a chunk that doesn't exist");
+            };
+            let mut offset = chunk_offset;
+            for _ in 0..samples_per_chunk {
+                let i = samples.len();
+                if i == sizes.len() {
+                    return invalid("chunks contain more samples than stsz");
+                }
+                samples.push(Sample {
+                    offset,
+                    size: sizes[i],
+                    dts,
+                    duration: durations[i],
+                    cto: ctos.get(i).copied().unwrap_or(0),
+                    sync: sync[i],
+                    description,
+                    segment: 0,
+                });
+                offset += u64::from(sizes[i]);
+                dts += u64::from(durations[i]);
+            }
+        }
+    }
+    if samples.len() != sizes.len() {
+        return invalid("chunks contain less samples than stsz");
+    }
+
+    Ok(samples)
+}
+
+/// the track id and sample defaults from a trex box
+fn parse_track_extends(trex: &[u8]) -> Result<(u32, FragmentDefaults), Mp4Error> {
+    let mut reader = ByteReader::new(trex);
+    reader.full_box_header()?;
+    let id = reader.u32()?;
+    let defaults = FragmentDefaults {
+        description: reader.u32()?,
+        duration: reader.u32()?,
+        size: reader.u32()?,
+        flags: reader.u32()?,
+    };
+    Ok((id, defaults))
+}
+
+/// Appends the samples of a movie fragment (the payload of a moof box at `moof_offset`) to the tracks.
+fn parse_fragment(
+    moof: &[u8],
+    moof_offset: u64,
+    defaults: &[(u32, FragmentDefaults)],
+    tracks: &mut [Track],
+    file_len: u64,
+) -> Result<(), Mp4Error> {
+    // without an explicit base offset the data of a track fragment follows the data of the previous one
+    let mut data_end = moof_offset;
+    for traf in parse_boxes(moof)?.iter().filter(|child| child.is(b"traf")) {
+        let traf = parse_boxes(traf.data())?;
+        let Some(tfhd) = traf.iter().find(|child| child.is(b"tfhd")) else {
+            return invalid("missing box 'traf/tfhd'");
+        };
+        let mut reader = ByteReader::new(tfhd.data());
+        let (_, flags) = reader.full_box_header()?;
+        let id = reader.u32()?;
+        let Some(track) = tracks.iter_mut().find(|track| track.id == id) else {
+            return invalid(format!("fragment of track {id} that doesn't exist"));
+        };
+        let mut defaults = defaults
+            .iter()
+            .find(|(track_id, _)| *track_id == id)
+            .map_or_else(FragmentDefaults::default, |&(_, defaults)| defaults);
+        let base = if flags & 0x01 != 0 {
+            reader.u64()?
+        } else if flags & 0x02_0000 != 0 {
+            // default-base-is-moof
+            moof_offset
+        } else {
+            data_end
+        };
+        if flags & 0x02 != 0 {
+            defaults.description = reader.u32()?;
+        }
+        if flags & 0x08 != 0 {
+            defaults.duration = reader.u32()?;
+        }
+        if flags & 0x10 != 0 {
+            defaults.size = reader.u32()?;
+        }
+        if flags & 0x20 != 0 {
+            defaults.flags = reader.u32()?;
+        }
+
+        // fragments without a decode time continue where the previous one ended
+        let mut dts = match traf.iter().find(|child| child.is(b"tfdt")) {
+            Some(tfdt) => {
+                let mut reader = ByteReader::new(tfdt.data());
+                match reader.full_box_header()? {
+                    (1, _) => reader.u64()?,
+                    _ => u64::from(reader.u32()?),
+                }
+            }
+            None => track
+                .samples
+                .last()
+                .map_or(0, |last| last.dts + u64::from(last.duration)),
+        };
+        let mut offset = base;
+        for trun in traf.iter().filter(|child| child.is(b"trun")) {
+            let mut reader = ByteReader::new(trun.data());
+            let (version, flags) = reader.full_box_header()?;
+            let entry_len = [0x100, 0x200, 0x400, 0x800]
+                .iter()
+                .filter(|&&flag| flags & flag != 0)
+                .count()
+                * 4;
+            let count = reader.entry_count(entry_len)?;
+            // without entries there is nothing the count could be checked against
+            if entry_len == 0 && count as u64 * u64::from(defaults.size.max(1)) > file_len {
+                return invalid("trun sample count larger than the file");
+            }
+            if flags & 0x01 != 0 {
+                let data_offset = i64::from(reader.i32()?);
+                let Some(data_start) = base.checked_add_signed(data_offset) else {
+                    return invalid("trun data offset outside of the file");
+                };
+                offset = data_start;
+            }
+            let first_flags = if flags & 0x04 != 0 { Some(reader.u32()?) } else { None };
+
+            track.samples.reserve(count);
+            for i in 0..count {
+                let duration = if flags & 0x100 != 0 {
+                    reader.u32()?
+                } else {
+                    defaults.duration
+                };
+                let size = if flags & 0x200 != 0 {
+                    reader.u32()?
+                } else {
+                    defaults.size
+                };
+                let sample_flags = if flags & 0x400 != 0 {
+                    reader.u32()?
+                } else {
+                    first_flags.filter(|_| i == 0).unwrap_or(defaults.flags)
+                };
+                let cto = match flags & 0x800 != 0 {
+                    true if version == 0 => i64::from(reader.u32()?),
+                    true => i64::from(reader.i32()?),
+                    false => 0,
+                };
+                if offset + u64::from(size) > file_len {
+                    return invalid("sample data is outside of the file");
+                }
+
+                track.samples.push(Sample {
+                    offset,
+                    size,
+                    dts,
+                    duration,
+                    cto,
+                    // sample_is_non_sync_sample
+                    sync: sample_flags & 0x01_0000 == 0,
+                    description: defaults.description,
+                    segment: 0,
+                });
+                offset += u64::from(size);
+                dts += u64::from(duration);
+            }
+        }
+        data_end = offset;
+    }
+    Ok(())
+}
+
+fn write_output(
+    sources: &[Source],
+    selections: &[Vec<Option<Selection>>],
+    ranges: &[TrimRange],
+    destination: File,
+) -> Result<(), Mp4Error> {
+    let template = &sources[0];
+    // where each segment starts in the output in seconds
+    let segment_starts: Vec<f64> = ranges
+        .iter()
+        .scan(0.0, |time, range| {
+            let start = *time;
+            *time += range.end - range.start;
+            Some(start)
+        })
+        .collect();
+
+    let tracks: Vec<Option<OutputTrack>> = (0..template.tracks.len())
+        .map(|t| join_track(sources, selections, &segment_starts, t))
+        .collect();
+
+    // order the samples like in the source files so the interleaving of the tracks stays the same
+    let mut samples: Vec<(usize, &Sample)> = tracks
+        .iter()
+        .enumerate()
+        .filter_map(|(t, track)| track.as_ref().map(|track| (t, track)))
+        .flat_map(|(t, track)| track.samples.iter().map(move |sample| (t, sample)))
+        .collect();
+    samples.sort_by_key(|(t, sample)| (sample.segment, sample.offset, *t));
+
+    // every run of samples of the same track becomes a chunk
+    let mut chunks: Vec<Vec<Chunk>> = tracks.iter().map(|_| Vec::new()).collect();
+    let mut last_track = None;
+    let mut mdat_len = 0;
+    for &(t, sample) in &samples {
+        match chunks[t].last_mut() {
+            Some(chunk) if last_track == Some(t) && chunk.description == sample.description => chunk.samples += 1,
+            _ => chunks[t].push(Chunk {
+                offset: mdat_len,
+                samples: 1,
+                description: sample.description,
+            }),
+        }
+        last_track = Some(t);
+        mdat_len += u64::from(sample.size);
+    }
+
+    // rebuild the traks
+    let mut moov = template.moov.clone();
+    // the chapters and metadata of the source don't match the new timeline
+    if moov.child(b"udta").is_some() {
+        if let Ok(udta) = moov.container_mut(b"udta") {
+            remove_chapters(udta);
+            remove_game_data(udta);
+        }
+    }
+    let mut movie_duration = 0;
+    let mut trak_index = 0;
+    let mut children = Vec::new();
+    for mut child in std::mem::take(moov.children_mut()) {
+        if child.is(b"trak") {
+            let track = &tracks[trak_index];
+            trak_index += 1;
+            // tracks without samples in any segment get dropped
+            if let Some(track) = track {
+                let duration = rewrite_trak(&mut child, track, template.movie_timescale)?;
+                movie_duration = movie_duration.max(duration);
+                children.push(child);
+            }
+        } else {
+            children.push(child);
+        }
+    }
+    *moov.children_mut() = children;
+    if let Some(mvhd) = moov.child_mut(b"mvhd") {
+        set_duration(mvhd, movie_duration)?;
+    }
+
+    // the size of moov only depends on the kind of chunk offset box
+    let ftyp = template.ftyp.to_bytes();
+    let mdat_header_len = if mdat_len + 8 > u64::from(u32::MAX) { 16 } else { 8 };
+    let mut co64 = false;
+    set_chunk_offsets(&mut moov, &chunks, 0, co64);
+    let mut mdat_start = (ftyp.len() + moov.to_bytes().len()) as u64 + mdat_header_len;
+    if mdat_start + mdat_len > u64::from(u32::MAX) {
+        co64 = true;
+        set_chunk_offsets(&mut moov, &chunks, 0, co64);
+        mdat_start = (ftyp.len() + moov.to_bytes().len()) as u64 + mdat_header_len;
+    }
+    set_chunk_offsets(&mut moov, &chunks, mdat_start, co64);
+
+    let mut writer = BufWriter::new(destination);
+    writer.write_all(&ftyp)?;
+    writer.write_all(&moov.to_bytes())?;
+    if mdat_header_len == 16 {
+        writer.write_all(&1u32.to_be_bytes())?;
+        writer.write_all(b"mdat")?;
+        writer.write_all(&(mdat_len + 16).to_be_bytes())?;
+    } else {
+        writer.write_all(&(mdat_len as u32 + 8).to_be_bytes())?;
+        writer.write_all(b"mdat")?;
+    }
+
+    // copy the sample data in as few reads as possible
+    let mut readers: Vec<BufReader<&File>> = sources.iter().map(|source| BufReader::new(&source.file)).collect();
+    let mut run: Option<(usize, u64, u64)> = None;
+    for (_, sample) in samples {
+        match &mut run {
+            Some((segment, offset, len)) if *segment == sample.segment && *offset + *len == sample.offset => {
+                *len += u64::from(sample.size)
+            }
+            _ => {
+                if let Some((segment, offset, len)) = run {
+                    copy_range(&mut readers[segment], &mut writer, offset, len)?;
+                }
+                run = Some((sample.segment, sample.offset, u64::from(sample.size)));
+            }
+        }
+    }
+    if let Some((segment, offset, len)) = run {
+        copy_range(&mut readers[segment], &mut writer, offset, len)?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// put the selected samples of track `t` of all segments after each other
+fn join_track(
+    sources: &[Source],
+    selections: &[Vec<Option<Selection>>],
+    segment_starts: &[f64],
+    t: usize,
+) -> Option<OutputTrack> {
+    let mut output: Option<OutputTrack> = None;
+    for (segment, source) in sources.iter().enumerate() {
+        let (track, Some(selection)) = (&source.tracks[t], selections[segment][t]) else {
+            continue;
+        };
+        let segment_start = track.seconds_to_time(segment_starts[segment]);
+        let samples = &track.samples[selection.first..selection.end];
+
+        let Some(output) = &mut output else {
+            // the first segment with this track defines the edit list
+            output = Some(OutputTrack {
+                timescale: track.timescale,
+                has_sync_table: track.has_sync_table,
+                samples: rebase(samples, segment, 0),
+                media_time: selection.media_time,
+                delay: segment_start as u64 + selection.delay,
+            });
+            continue;
+        };
+
+        // place the samples so they get presented at the start of the segment
+        let start =
+            segment_start + selection.delay as i64 - selection.media_time + output.media_time - output.delay as i64;
+        // the previous segment can end with a (audio) sample that overlaps the start of this one
+        while output.samples.len() > 1 && output.samples.last().is_some_and(|last| last.dts as i64 >= start) {
+            output.samples.pop();
+        }
+        let Some(last) = output.samples.last_mut() else {
+            continue;
+        };
+        let start = start.max(last.dts as i64 + 1) as u64;
+        // the last sample of the previous segment lasts until the new segment starts
+        last.duration = u32::try_from(start - last.dts).unwrap_or(u32::MAX);
+
+        output.has_sync_table |= track.has_sync_table;
+        output.samples.extend(rebase(samples, segment, start));
+    }
+    output
+}
+
+/// move the samples to start at `start` in the output
+fn rebase(samples: &[Sample], segment: usize, start: u64) -> Vec<Sample> {
+    let first_dts = samples[0].dts;
+    samples
+        .iter()
+        .map(|sample| Sample {
+            dts: sample.dts - first_dts + start,
+            segment,
+            ..*sample
+        })
+        .collect()
+}
+
+fn copy_range(reader: &mut BufReader<&File>, writer: &mut impl Write, offset: u64, len: u64) -> Result<(), Mp4Error> {
+    reader.seek(SeekFrom::Start(offset))?;
+    if io::copy(&mut reader.take(len), writer)? != len {
+        return invalid("sample data is outside of the file");
+    }
+    Ok(())
+}
+
+/// rebuild the sample tables, durations and edit list of a trak and return its duration in the movie timescale
+fn rewrite_trak(trak: &mut Mp4Box, track: &OutputTrack, movie_timescale: u32) -> Result<u64, Mp4Error> {
+    let samples = &track.samples;
+    let media_duration: u64 = samples.iter().map(|sample| u64::from(sample.duration)).sum();
+    let presentation_end = samples
+        .iter()
+        .map(|sample| sample.pts() + i64::from(sample.duration))
+        .max()
+        .unwrap_or(0);
+    let presentation_duration = (presentation_end - track.media_time).max(0) as u64;
+    let delay = rescale(track.delay, track.timescale, movie_timescale);
+    let segment_duration = rescale(presentation_duration, track.timescale, movie_timescale);
+
+    let Some(mdhd) = trak.descend_mut(&[b"mdia", b"mdhd"]) else {
+        return invalid("missing box 'mdia/mdhd'");
+    };
+    set_duration(mdhd, media_duration)?;
+    let Some(tkhd) = trak.child_mut(b"tkhd") else {
+        return invalid("missing box 'tkhd'");
+    };
+    set_duration(tkhd, delay + segment_duration)?;
+
+    // replace the edit list
+    let children = trak.children_mut();
+    children.retain(|child| !child.is(b"edts"));
+    let position = children.iter().position(|child| child.is(b"tkhd")).map_or(0, |i| i + 1);
+    let mut edits = Vec::new();
+    if delay > 0 {
+        edits.push((delay, -1));
+    }
+    edits.push((segment_duration, track.media_time));
+    children.insert(position, Mp4Box::new_container(b"edts", vec![edit_list(&edits)]));
+
+    let Some(stbl) = trak.descend_mut(&[b"mdia", b"minf", b"stbl"]) else {
+        return invalid("missing box 'mdia/minf/stbl'");
+    };
+    let old = std::mem::take(stbl.children_mut());
+    let (stsd, others): (Vec<Mp4Box>, Vec<Mp4Box>) = old
+        .into_iter()
+        .filter(|child| !SAMPLE_TABLES.contains(&&child.kind))
+        .partition(|child| child.is(b"stsd"));
+
+    let tables = stbl.children_mut();
+    tables.extend(stsd);
+    tables.push(time_to_sample(samples));
+    if samples.iter().any(|sample| sample.cto != 0) {
+        tables.push(composition_offsets(samples));
+    }
+    if track.has_sync_table {
+        tables.push(sync_samples(samples));
+    }
+    tables.push(sample_sizes(samples));
+    tables.extend(others);
+
+    Ok(delay + segment_duration)
+}
+
+/// (re)write stsc and stco/co64 with the chunk offsets relative to `mdat_start`
+fn set_chunk_offsets(moov: &mut Mp4Box, chunks: &[Vec<Chunk>], mdat_start: u64, co64: bool) {
+    let traks = moov.children_mut().iter_mut().filter(|child| child.is(b"trak"));
+    // dropped traks have no chunks
+    let chunks = chunks.iter().filter(|chunks| !chunks.is_empty());
+    for (trak, chunks) in traks.zip(chunks) {
+        let Some(stbl) = trak.descend_mut(&[b"mdia", b"minf", b"stbl"]) else {
+            continue;
+        };
+        let tables = stbl.children_mut();
+        tables.retain(|child| !child.is(b"stsc") && !child.is(b"stco") && !child.is(b"co64"));
+        tables.push(sample_to_chunk(chunks));
+        tables.push(chunk_offsets(chunks, mdat_start, co64));
+    }
+}
+
+fn run_lengths<T: PartialEq + Copy>(values: impl Iterator<Item = T>) -> Vec<(u32, T)> {
+    let mut runs: Vec<(u32, T)> = Vec::new();
+    for value in values {
+        match runs.last_mut() {
+            Some((count, last)) if *last == value => *count += 1,
+            _ => runs.push((1, value)),
+        }
+    }
+    runs
+}
+
+fn time_to_sample(samples: &[Sample]) -> Mp4Box {
+    let runs = run_lengths(samples.iter().map(|sample| sample.duration));
+    let mut data = Vec::with_capacity(8 + runs.len() * 8);
+    full_box_header(&mut data, 0, 0);
+    data.extend_from_slice(&(runs.len() as u32).to_be_bytes());
+    for (count, duration) in runs {
+        data.extend_from_slice(&count.to_be_bytes());
+        data.extend_from_slice(&duration.to_be_bytes());
+    }
+    Mp4Box::new_data(b"stts", data)
+}
+
+fn composition_offsets(samples: &[Sample]) -> Mp4Box {
+    let runs = run_lengths(samples.iter().map(|sample| sample.cto));
+    let version = u8::from(runs.iter().any(|&(_, cto)| cto < 0));
+    let mut data = Vec::with_capacity(8 + runs.len() * 8);
+    full_box_header(&mut data, version, 0);
+    data.extend_from_slice(&(runs.len() as u32).to_be_bytes());
+    for (count, cto) in runs {
+        data.extend_from_slice(&count.to_be_bytes());
+        // the offsets come from a 32 bit table so they always fit
+        data.extend_from_slice(&(cto as u32).to_be_bytes());
+    }
+    Mp4Box::new_data(b"ctts", data)
+}
+
+fn sync_samples(samples: &[Sample]) -> Mp4Box {
+    let sync: Vec<u32> = (0..samples.len())
+        .filter(|&i| samples[i].sync)
+        .map(|i| i as u32 + 1)
+        .collect();
+    let mut data = Vec::with_capacity(8 + sync.len() * 4);
+    full_box_header(&mut data, 0, 0);
+    data.extend_from_slice(&(sync.len() as u32).to_be_bytes());
+    sync.iter().for_each(|i| data.extend_from_slice(&i.to_be_bytes()));
+    Mp4Box::new_data(b"stss", data)
+}
+
+fn sample_sizes(samples: &[Sample]) -> Mp4Box {
+    let mut data = Vec::new();
+    full_box_header(&mut data, 0, 0);
+    let first_size = samples[0].size;
+    if samples.iter().all(|sample| sample.size == first_size) {
+        data.extend_from_slice(&first_size.to_be_bytes());
+        data.extend_from_slice(&(samples.len() as u32).to_be_bytes());
+    } else {
+        data.extend_from_slice(&0u32.to_be_bytes());
+        data.extend_from_slice(&(samples.len() as u32).to_be_bytes());
+        samples
+            .iter()
+            .for_each(|sample| data.extend_from_slice(&sample.size.to_be_bytes()));
+    }
+    Mp4Box::new_data(b"stsz", data)
+}
+
+fn sample_to_chunk(chunks: &[Chunk]) -> Mp4Box {
+    let mut entries: Vec<(u32, u32, u32)> = Vec::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        if entries.last().map(|&(_, samples, description)| (samples, description))
+            != Some((chunk.samples, chunk.description))
+        {
+            entries.push((i as u32 + 1, chunk.samples, chunk.description));
+        }
+    }
+
+    let mut data = Vec::with_capacity(8 + entries.len() * 12);
+    full_box_header(&mut data, 0, 0);
+    data.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+    for (first_chunk, samples, description) in entries {
+        data.extend_from_slice(&first_chunk.to_be_bytes());
+        data.extend_from_slice(&samples.to_be_bytes());
+        data.extend_from_slice(&description.to_be_bytes());
+    }
+    Mp4Box::new_data(b"stsc", data)
+}
+
+fn chunk_offsets(chunks: &[Chunk], mdat_start: u64, co64: bool) -> Mp4Box {
+    let mut data = Vec::with_capacity(8 + chunks.len() * 8);
+    full_box_header(&mut data, 0, 0);
+    data.extend_from_slice(&(chunks.len() as u32).to_be_bytes());
+    for chunk in chunks {
+        let offset = mdat_start + chunk.offset;
+        if co64 {
+            data.extend_from_slice(&offset.to_be_bytes());
+        } else {
+            data.extend_from_slice(&(offset as u32).to_be_bytes());
+        }
+    }
+    Mp4Box::new_data(if co64 { b"co64" } else { b"stco" }, data)
+}
+
+fn edit_list(edits: &[(u64, i64)]) -> Mp4Box {
+    let version = u8::from(
+        edits
+            .iter()
+            .any(|&(duration, media_time)| duration > u64::from(u32::MAX) || media_time > i64::from(i32::MAX)),
+    );
+    let mut data = Vec::new();
+    full_box_header(&mut data, version, 0);
+    data.extend_from_slice(&(edits.len() as u32).to_be_bytes());
+    for &(duration, media_time) in edits {
+        if version == 1 {
+            data.extend_from_slice(&duration.to_be_bytes());
+            data.extend_from_slice(&media_time.to_be_bytes());
+        } else {
+            data.extend_from_slice(&(duration as u32).to_be_bytes());
+            data.extend_from_slice(&(media_time as i32).to_be_bytes());
+        }
+        // media rate 1.0
+        data.extend_from_slice(&[0, 1, 0, 0]);
+    }
+    Mp4Box::new_data(b"elst", data)
+}
+
+fn rescale(value: u64, from: u32, to: u32) -> u64 {
+    (u128::from(value) * u128::from(to) / u128::from(from)) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::mp4::{read_game_data, write_chapters, write_game_data, Chapter};
+
+    /// 1s per video sample with a keyframe every 3s, 0.5s per audio sample
+    const SECONDS: usize = 10;
+    const AUDIO_SAMPLE_SIZE: u32 = 4;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("league_record_test_{name}_{}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn u32s(values: &[u32]) -> Vec<u8> {
+        values.iter().flat_map(|value| value.to_be_bytes()).collect()
+    }
+
+    fn full_box(kind: &FourCC, body: &[u8]) -> Mp4Box {
+        full_box_with_flags(kind, 0, 0, body)
+    }
+
+    fn full_box_with_flags(kind: &FourCC, version: u8, flags: u32, body: &[u8]) -> Mp4Box {
+        let mut data = Vec::new();
+        full_box_header(&mut data, version, flags);
+        data.extend_from_slice(body);
+        Mp4Box::new_data(kind, data)
+    }
+
+    /// the content of sample `i` of track `t` - every byte tells where it came from
+    fn sample_data(t: usize, i: usize) -> Vec<u8> {
+        match t {
+            0 => vec![i as u8; 10 + i],
+            _ => vec![100 + i as u8; AUDIO_SAMPLE_SIZE as usize],
+        }
+    }
+
+    struct TrackFixture {
+        handler: &'static FourCC,
+        /// width and height in the track header
+        size: (u32, u32),
+        sample_duration: u32,
+        samples: usize,
+        /// stsz after the full box header
+        sizes: Vec<u8>,
+        keyframes: Option<Vec<u32>>,
+        samples_per_chunk: u32,
+        chunk_offsets: Vec<u64>,
+    }
+
+    fn trak(track: &TrackFixture, co64: bool) -> Mp4Box {
+        let samples = track.samples as u32;
+        let mut hdlr = u32s(&[0]);
+        hdlr.extend_from_slice(track.handler);
+        hdlr.extend_from_slice(&[0; 13]);
+
+        let mut stbl = vec![
+            full_box(b"stsd", &u32s(&[0])),
+            full_box(b"stts", &u32s(&[1, samples, track.sample_duration])),
+            full_box(b"stsz", &track.sizes),
+            full_box(b"stsc", &u32s(&[1, 1, track.samples_per_chunk, 1])),
+        ];
+        if let Some(keyframes) = &track.keyframes {
+            let mut stss = u32s(&[keyframes.len() as u32]);
+            stss.extend(u32s(keyframes));
+            stbl.push(full_box(b"stss", &stss));
+        }
+        let mut offsets = u32s(&[track.chunk_offsets.len() as u32]);
+        for &offset in &track.chunk_offsets {
+            match co64 {
+                true => offsets.extend_from_slice(&offset.to_be_bytes()),
+                false => offsets.extend_from_slice(&(offset as u32).to_be_bytes()),
+            }
+        }
+        stbl.push(full_box(if co64 { b"co64" } else { b"stco" }, &offsets));
+
+        Mp4Box::new_container(
+            b"trak",
+            vec![
+                full_box(
+                    b"tkhd",
+                    &[[0; 72].as_slice(), &u32s(&[track.size.0 << 16, track.size.1 << 16])].concat(),
+                ),
+                Mp4Box::new_container(
+                    b"mdia",
+                    vec![
+                        full_box(b"mdhd", &u32s(&[0, 0, 1000, samples * track.sample_duration, 0])),
+                        full_box(b"hdlr", &hdlr),
+                        Mp4Box::new_container(b"minf", vec![Mp4Box::new_container(b"stbl", stbl)]),
+                    ],
+                ),
+            ],
+        )
+    }
+
+    /// a 1080p recording with a video and an audio track that are interleaved in chunks of 1s
+    fn write_fixture(path: &Path, co64: bool, moov_after_mdat: bool) {
+        write_fixture_with_audio(path, co64, moov_after_mdat, true);
+    }
+
+    /// without audio the audio samples stay in the mdat but there is no track for them
+    fn write_fixture_with_audio(path: &Path, co64: bool, moov_after_mdat: bool, audio: bool) {
+        let mut ftyp_data = b"isom".to_vec();
+        ftyp_data.extend(u32s(&[512]));
+        ftyp_data.extend_from_slice(b"isomiso2mp41");
+        let ftyp = Mp4Box::new_data(b"ftyp", ftyp_data).to_bytes();
+
+        let mut mdat = Vec::new();
+        let (mut video_chunks, mut audio_chunks) = (Vec::new(), Vec::new());
+        for second in 0..SECONDS {
+            video_chunks.push(mdat.len() as u64);
+            mdat.extend(sample_data(0, second));
+            audio_chunks.push(mdat.len() as u64);
+            mdat.extend(sample_data(1, 2 * second));
+            mdat.extend(sample_data(1, 2 * second + 1));
+        }
+
+        let moov = |mdat_start: u64| {
+            let video = TrackFixture {
+                handler: b"vide",
+                size: (1920, 1080),
+                sample_duration: 1000,
+                samples: SECONDS,
+                sizes: u32s(
+                    &[
+                        [0, SECONDS as u32].as_slice(),
+                        &(0..SECONDS as u32).map(|i| 10 + i).collect::<Vec<_>>(),
+                    ]
+                    .concat(),
+                ),
+                keyframes: Some(vec![1, 4, 7, 10]),
+                samples_per_chunk: 1,
+                chunk_offsets: video_chunks.iter().map(|offset| offset + mdat_start).collect(),
+            };
+            let audio_track = TrackFixture {
+                handler: b"soun",
+                size: (0, 0),
+                sample_duration: 500,
+                samples: 2 * SECONDS,
+                sizes: u32s(&[AUDIO_SAMPLE_SIZE, 2 * SECONDS as u32]),
+                keyframes: None,
+                samples_per_chunk: 2,
+                chunk_offsets: audio_chunks.iter().map(|offset| offset + mdat_start).collect(),
+            };
+            let mut moov = vec![
+                full_box(b"mvhd", &u32s(&[0, 0, 1000, SECONDS as u32 * 1000])),
+                trak(&video, co64),
+            ];
+            if audio {
+                moov.push(trak(&audio_track, co64));
+            }
+            Mp4Box::new_container(b"moov", moov).to_bytes()
+        };
+
+        let moov_len = moov(0).len() as u64;
+        let mdat_start = match moov_after_mdat {
+            true => ftyp.len() as u64 + 8,
+            false => ftyp.len() as u64 + moov_len + 8,
+        };
+        let moov = moov(mdat_start);
+        let mdat = Mp4Box::new_data(b"mdat", mdat).to_bytes();
+
+        let mut file = ftyp;
+        if moov_after_mdat {
+            file.extend(mdat);
+            file.extend(moov);
+        } else {
+            file.extend(moov);
+            file.extend(mdat);
+        }
+        fs::write(path, file).unwrap();
+    }
+
+    /// the fixture as a fragmented file with a fragment for every keyframe interval (like libobs writes it)
+    fn write_fragmented_fixture(path: &Path) {
+        let mut ftyp_data = b"isml".to_vec();
+        ftyp_data.extend(u32s(&[1]));
+        ftyp_data.extend_from_slice(b"piffiso2");
+        let mut file = Mp4Box::new_data(b"ftyp", ftyp_data).to_bytes();
+
+        // the tracks have no samples in the moov box
+        let empty_trak = |id: u32, handler: &'static FourCC, size: (u32, u32), sample_duration: u32| {
+            let track = TrackFixture {
+                handler,
+                size,
+                sample_duration,
+                samples: 0,
+                sizes: u32s(&[0, 0]),
+                keyframes: None,
+                samples_per_chunk: 1,
+                chunk_offsets: Vec::new(),
+            };
+            let mut trak = trak(&track, false);
+            let tkhd = trak.child_mut(b"tkhd").unwrap().data_mut().unwrap();
+            tkhd[12..16].copy_from_slice(&id.to_be_bytes());
+            trak
+        };
+        let mvex = Mp4Box::new_container(
+            b"mvex",
+            vec![
+                // video samples are no keyframes by default, audio samples have a constant size
+                full_box(b"trex", &u32s(&[1, 1, 1000, 0, 0x01_0000])),
+                full_box(b"trex", &u32s(&[2, 1, 500, AUDIO_SAMPLE_SIZE, 0])),
+            ],
+        );
+        let moov = Mp4Box::new_container(
+            b"moov",
+            vec![
+                full_box(b"mvhd", &u32s(&[0, 0, 1000, 0])),
+                empty_trak(1, b"vide", (1920, 1080), 1000),
+                empty_trak(2, b"soun", (0, 0), 500),
+                mvex,
+            ],
+        );
+        file.extend(moov.to_bytes());
+
+        for (first, end) in [(0, 3), (3, 6), (6, 9), (9, SECONDS)] {
+            let moof = |data_offset: u32| {
+                let sizes: Vec<u32> = (first..end).map(|i| sample_data(0, i).len() as u32).collect();
+                let video = Mp4Box::new_container(
+                    b"traf",
+                    vec![
+                        // default-base-is-moof
+                        full_box_with_flags(b"tfhd", 0, 0x02_0000, &u32s(&[1])),
+                        full_box_with_flags(b"tfdt", 1, 0, &(first as u64 * 1000).to_be_bytes()),
+                        // data offset, first sample flags (keyframe) and sample sizes
+                        full_box_with_flags(
+                            b"trun",
+                            0,
+                            0x01 | 0x04 | 0x200,
+                            &[u32s(&[sizes.len() as u32, data_offset, 0]), u32s(&sizes)].concat(),
+                        ),
+                    ],
+                );
+                // the audio data follows the video data and the samples continue where the last fragment ended
+                let audio = Mp4Box::new_container(
+                    b"traf",
+                    vec![
+                        full_box(b"tfhd", &u32s(&[2])),
+                        full_box(b"trun", &u32s(&[2 * (end - first) as u32])),
+                    ],
+                );
+                Mp4Box::new_container(b"moof", vec![video, audio]).to_bytes()
+            };
+            let moof_len = moof(0).len() as u32;
+            file.extend(moof(moof_len + 8));
+
+            let mut mdat = Vec::new();
+            (first..end).for_each(|i| mdat.extend(sample_data(0, i)));
+            (2 * first..2 * end).for_each(|i| mdat.extend(sample_data(1, i)));
+            file.extend(Mp4Box::new_data(b"mdat", mdat).to_bytes());
+        }
+        fs::write(path, file).unwrap();
+    }
+
+    /// the samples of a track are the samples `indices` of track `t` of the fixture
+    fn assert_samples(path: &Path, track: &Track, t: usize, indices: impl IntoIterator<Item = usize>) {
+        let file = fs::read(path).unwrap();
+        let indices: Vec<usize> = indices.into_iter().collect();
+        assert_eq!(track.samples.len(), indices.len(), "sample count of track {t}");
+        for (i, (sample, index)) in track.samples.iter().zip(indices).enumerate() {
+            let offset = sample.offset as usize;
+            assert_eq!(
+                file[offset..offset + sample.size as usize],
+                sample_data(t, index),
+                "sample {i} of track {t}"
+            );
+        }
+    }
+
+    #[test]
+    fn trims_to_the_surrounding_keyframes() {
+        let dir = test_dir("mp4_trim");
+        for (co64, moov_after_mdat) in [(false, false), (true, false), (false, true), (true, true)] {
+            let source = dir.join(format!("source_{co64}_{moov_after_mdat}.mp4"));
+            let destination = dir.join(format!("clip_{co64}_{moov_after_mdat}.mp4"));
+            write_fixture(&source, co64, moov_after_mdat);
+
+            // keyframes at 3s and 6s
+            let range = trim(&source, &destination, 3.5, 5.5).unwrap();
+            assert_eq!((range.start, range.end), (3.0, 6.0));
+
+            let clip = Source::open(&destination).unwrap();
+            assert_eq!(clip.movie_timescale, 1000);
+
+            let video = &clip.tracks[0];
+            assert!(video.is_video);
+            assert_eq!(video.samples.iter().map(|s| s.size).collect::<Vec<_>>(), [13, 14, 15]);
+            assert_eq!(video.samples.iter().map(|s| s.dts).collect::<Vec<_>>(), [0, 1000, 2000]);
+            assert_eq!(
+                video.samples.iter().map(|s| s.sync).collect::<Vec<_>>(),
+                [true, false, false]
+            );
+            assert_samples(&destination, video, 0, 3..6);
+
+            let audio = &clip.tracks[1];
+            assert_eq!(audio.samples.len(), 6);
+            assert!(audio
+                .samples
+                .iter()
+                .all(|s| s.size == AUDIO_SAMPLE_SIZE && s.duration == 500));
+            assert_samples(&destination, audio, 1, 6..12);
+
+            // the clip always has its moov in front and 32 bit chunk offsets
+            let stbl = clip.moov.descend(&[b"trak", b"mdia", b"minf", b"stbl"]).unwrap();
+            assert!(stbl.child(b"stco").is_some() && stbl.child(b"co64").is_none());
+            let mut file = File::open(&destination).unwrap();
+            let kinds: Vec<FourCC> = top_level_boxes(&mut file).unwrap().iter().map(|b| b.kind).collect();
+            assert_eq!(kinds, [*b"ftyp", *b"moov", *b"mdat"]);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn trims_until_the_end() {
+        let dir = test_dir("mp4_trim_end");
+        let (source, destination) = (dir.join("source.mp4"), dir.join("clip.mp4"));
+        write_fixture(&source, false, false);
+
+        let range = trim(&source, &destination, 9.5, 20.0).unwrap();
+        assert_eq!((range.start, range.end), (9.0, 10.0));
+        let clip = Source::open(&destination).unwrap();
+        assert_eq!(clip.tracks[0].samples.len(), 1);
+        assert_samples(&destination, &clip.tracks[0], 0, 9..10);
+        assert_samples(&destination, &clip.tracks[1], 1, 18..20);
+
+        assert!(trim(&source, &dir.join("outside.mp4"), 15.0, 20.0).is_err());
+        assert!(!dir.join("outside.mp4").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn never_overwrites_the_destination() {
+        let dir = test_dir("mp4_trim_existing");
+        let (source, destination) = (dir.join("source.mp4"), dir.join("clip.mp4"));
+        write_fixture(&source, false, false);
+        fs::write(&destination, "existing").unwrap();
+
+        assert!(matches!(trim(&source, &destination, 0.0, 5.0), Err(Mp4Error::Io(_))));
+        assert_eq!(fs::read_to_string(&destination).unwrap(), "existing");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn joins_segments_of_different_recordings() {
+        let dir = test_dir("mp4_concat");
+        let (first, second, reel) = (dir.join("first.mp4"), dir.join("second.mp4"), dir.join("reel.mp4"));
+        write_fixture(&first, false, false);
+        write_fixture(&second, true, true);
+
+        let segments = [
+            Segment {
+                source: first,
+                start: 1.0,
+                end: 2.0,
+            },
+            Segment {
+                source: second,
+                start: 6.5,
+                end: 8.0,
+            },
+        ];
+        let ranges = concat(&segments, &reel).unwrap();
+        let ranges: Vec<(f64, f64)> = ranges.iter().map(|range| (range.start, range.end)).collect();
+        assert_eq!(ranges, [(0.0, 3.0), (6.0, 9.0)]);
+
+        // the second segment continues right where the first one ends
+        let reel_source = Source::open(&reel).unwrap();
+        let video = &reel_source.tracks[0];
+        assert_eq!(
+            video.samples.iter().map(|s| s.dts).collect::<Vec<_>>(),
+            [0, 1000, 2000, 3000, 4000, 5000]
+        );
+        assert_eq!(
+            video.samples.iter().map(|s| s.sync).collect::<Vec<_>>(),
+            [true, false, false, true, false, false]
+        );
+        assert_samples(&reel, video, 0, (0..3).chain(6..9));
+        assert_samples(&reel, &reel_source.tracks[1], 1, (0..6).chain(12..18));
+
+        let info = media_info(&reel).unwrap();
+        assert_eq!(info.duration, 6.0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn refuses_to_join_different_tracks() {
+        let dir = test_dir("mp4_concat_incompatible");
+        let (with_audio, without_audio) = (dir.join("audio.mp4"), dir.join("no_audio.mp4"));
+        write_fixture(&with_audio, false, false);
+        write_fixture_with_audio(&without_audio, false, false, false);
+
+        let segment = |source: &Path| Segment {
+            source: source.to_path_buf(),
+            start: 0.0,
+            end: 5.0,
+        };
+        let reel = dir.join("reel.mp4");
+        let result = concat(&[segment(&with_audio), segment(&without_audio)], &reel);
+        assert!(matches!(result, Err(Mp4Error::Unsupported(_))));
+        assert!(!reel.exists());
+
+        assert!(matches!(concat(&[], &reel), Err(Mp4Error::Invalid(_))));
+        let mut backwards = segment(&with_audio);
+        backwards.start = 6.0;
+        assert!(matches!(concat(&[backwards], &reel), Err(Mp4Error::Invalid(_))));
+        assert!(!reel.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reads_the_media_info() {
+        let dir = test_dir("mp4_media_info");
+        let (with_audio, without_audio) = (dir.join("audio.mp4"), dir.join("no_audio.mp4"));
+        write_fixture(&with_audio, false, false);
+        write_fixture_with_audio(&without_audio, true, true, false);
+
+        let info = media_info(&with_audio).unwrap();
+        assert_eq!(
+            info,
+            MediaInfo {
+                width: 1920,
+                height: 1080,
+                has_audio: true,
+                duration: 10.0
+            }
+        );
+        let info = media_info(&without_audio).unwrap();
+        assert!(!info.has_audio && info.width == 1920 && info.height == 1080);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn stays_valid_after_writing_chapters_and_metadata() {
+        let dir = test_dir("mp4_update_moov");
+        for moov_after_mdat in [false, true] {
+            let recording = dir.join(format!("recording_{moov_after_mdat}.mp4"));
+            write_fixture(&recording, false, moov_after_mdat);
+            let original = fs::read(&recording).unwrap();
+            let mut file = File::open(&recording).unwrap();
+            let old_moov = top_level_boxes(&mut file)
+                .unwrap()
+                .into_iter()
+                .find(|b| &b.kind == b"moov")
+                .unwrap();
+
+            let chapter = |start: f64, title: &str| Chapter { start, title: title.into() };
+            write_chapters(&recording, &[chapter(0.0, "Start"), chapter(5.0, "Kill")]).unwrap();
+            write_game_data(&recording, br#"{"win":true}"#).unwrap();
+            write_chapters(&recording, &[chapter(2.0, "Dragon")]).unwrap();
+
+            // everything before the new moov box is untouched except the old moov box which is now a free box
+            let updated = fs::read(&recording).unwrap();
+            let mut expected = original.clone();
+            expected[old_moov.offset as usize + 4..old_moov.offset as usize + 8].copy_from_slice(b"free");
+            assert_eq!(updated[..original.len()], expected);
+
+            let mut file = File::open(&recording).unwrap();
+            let boxes = top_level_boxes(&mut file).unwrap();
+            assert_eq!(boxes.iter().filter(|b| &b.kind == b"moov").count(), 1);
+            assert_eq!(&boxes.last().unwrap().kind, b"moov");
+
+            let source = Source::open(&recording).unwrap();
+            assert_samples(&recording, &source.tracks[0], 0, 0..SECONDS);
+            assert_samples(&recording, &source.tracks[1], 1, 0..2 * SECONDS);
+            assert_eq!(read_game_data(&recording).unwrap().unwrap(), br#"{"win":true}"#);
+            let mut moov = source.moov.clone();
+            let chapters = moov.container_mut(b"udta").unwrap().children();
+            assert_eq!(chapters.iter().filter(|child| child.is(b"chpl")).count(), 1);
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn defragments_recordings() {
+        let dir = test_dir("mp4_defragment");
+        let (fragmented, recording) = (dir.join("fragmented.ismv"), dir.join("recording.mp4"));
+        write_fragmented_fixture(&fragmented);
+
+        defragment(&fragmented, &recording).unwrap();
+        assert!(matches!(defragment(&fragmented, &recording), Err(Mp4Error::Io(_))));
+
+        let mut file = File::open(&recording).unwrap();
+        let kinds: Vec<FourCC> = top_level_boxes(&mut file).unwrap().iter().map(|b| b.kind).collect();
+        assert_eq!(kinds, [*b"ftyp", *b"moov", *b"mdat"]);
+
+        let source = Source::open(&recording).unwrap();
+        assert!(source.moov.child(b"mvex").is_none());
+        let (video, audio) = (&source.tracks[0], &source.tracks[1]);
+        assert_samples(&recording, video, 0, 0..SECONDS);
+        assert_samples(&recording, audio, 1, 0..2 * SECONDS);
+        let keyframes: Vec<usize> = (0..SECONDS).filter(|&i| video.samples[i].sync).collect();
+        assert_eq!(keyframes, [0, 3, 6, 9]);
+        assert!(audio.samples.iter().enumerate().all(|(i, s)| s.dts == i as u64 * 500));
+        assert_eq!(media_info(&recording).unwrap().duration, SECONDS as f64);
+
+        // the recording can be edited like any other recording
+        write_game_data(&recording, br#"{"win":true}"#).unwrap();
+        assert_eq!(read_game_data(&recording).unwrap().unwrap(), br#"{"win":true}"#);
+
+        // fragmented recordings can be trimmed directly as well
+        let clip = dir.join("clip.mp4");
+        let range = trim(&fragmented, &clip, 3.5, 5.5).unwrap();
+        assert_eq!((range.start, range.end), (3.0, 6.0));
+        assert_samples(&clip, &Source::open(&clip).unwrap().tracks[0], 0, 3..6);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn writes_64_bit_chunk_offsets() {
+        let dir = test_dir("mp4_co64");
+        let source = dir.join("source.mp4");
+        write_fixture(&source, false, false);
+        let mut moov = Source::open(&source).unwrap().moov;
+
+        let chunks = |count: u64, samples: u32| {
+            (0..count)
+                .map(|i| Chunk {
+                    offset: i * 100,
+                    samples,
+                    description: 1,
+                })
+                .collect::<Vec<_>>()
+        };
+        let mdat_start = u64::from(u32::MAX) + 1;
+        set_chunk_offsets(&mut moov, &[chunks(10, 1), chunks(10, 2)], mdat_start, true);
+
+        let stbl = moov.descend(&[b"trak", b"mdia", b"minf", b"stbl"]).unwrap();
+        assert!(stbl.child(b"co64").is_some() && stbl.child(b"stco").is_none());
+        let samples = parse_samples(stbl, u64::MAX).unwrap();
+        let offsets: Vec<u64> = samples.iter().map(|sample| sample.offset).collect();
+        assert_eq!(offsets, (0..10).map(|i| mdat_start + i * 100).collect::<Vec<_>>());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_sample_counts_that_dont_fit_into_the_file() {
+        let stbl = Mp4Box::new_container(
+            b"stbl",
+            vec![
+                full_box(b"stsz", &u32s(&[1, u32::MAX])),
+                full_box(b"stts", &u32s(&[1, u32::MAX, 1])),
+                full_box(b"stsc", &u32s(&[1, 1, 1, 1])),
+                full_box(b"stco", &u32s(&[1, 0])),
+            ],
+        );
+        assert!(matches!(parse_samples(&stbl, 1000), Err(Mp4Error::Invalid(_))));
+
+        // more entries than the box is long
+        let stbl = Mp4Box::new_container(b"stbl", vec![full_box(b"stsz", &u32s(&[0, u32::MAX, 1]))]);
+        assert!(matches!(parse_samples(&stbl, u64::MAX), Err(Mp4Error::Invalid(_))));
+    }
+}
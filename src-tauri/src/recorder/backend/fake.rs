@@ -0,0 +1,224 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use super::{BackendError, RecordingBackend};
+
+/// Doesn't capture anything - just writes a placeholder file to the output path.
+/// Allows driving the whole recording lifecycle on machines without libobs or a LoL client.
+pub struct FakeBackend {
+    output_path: PathBuf,
+    replay_buffer_length: Option<u32>,
+    recording_start: Option<Instant>,
+}
+
+impl FakeBackend {
+    pub fn new(output_path: PathBuf, replay_buffer_length: Option<u32>) -> Self {
+        Self {
+            output_path,
+            replay_buffer_length,
+            recording_start: None,
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording_start.is_some()
+    }
+}
+
+impl RecordingBackend for FakeBackend {
+    fn name(&self) -> &'static str {
+        "fake"
+    }
+
+    fn start_recording(&mut self) -> Result<(), BackendError> {
+        if self.is_recording() {
+            return Err(BackendError("already recording".into()));
+        }
+
+        // in replay buffer mode the output file never gets written
+        if self.replay_buffer_length.is_none() {
+            fs::write(&self.output_path, "LeagueRecord placeholder recording\n")
+                .map_err(|e| BackendError(format!("unable to create placeholder file: {e}")))?;
+        }
+        self.recording_start = Some(Instant::now());
+        Ok(())
+    }
+
+    fn stop_recording(&mut self) -> Result<(), BackendError> {
+        let Some(recording_start) = self.recording_start.take() else {
+            return Err(BackendError("not recording".into()));
+        };
+
+        if self.replay_buffer_length.is_some() {
+            return Ok(());
+        }
+
+        let contents = format!(
+            "LeagueRecord placeholder recording\nduration: {}s\n",
+            recording_start.elapsed().as_secs_f64()
+        );
+        fs::write(&self.output_path, contents)
+            .map_err(|e| BackendError(format!("unable to finalize placeholder file: {e}")))
+    }
+
+    fn shutdown(mut self: Box<Self>) -> Result<(), BackendError> {
+        if self.is_recording() {
+            self.stop_recording()?;
+        }
+        Ok(())
+    }
+
+    fn replay_buffer_active(&self) -> bool {
+        self.replay_buffer_length.is_some()
+    }
+
+    fn save_replay_buffer(&mut self, path: &Path) -> Result<(), BackendError> {
+        let (Some(length), Some(recording_start)) = (self.replay_buffer_length, self.recording_start) else {
+            return Err(BackendError("replay buffer is not running".into()));
+        };
+
+        let duration = recording_start.elapsed().as_secs_f64().min(f64::from(length));
+        fs::write(path, format!("LeagueRecord placeholder clip\nduration: {duration}s\n"))
+            .map_err(|e| BackendError(format!("unable to write placeholder clip: {e}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use super::*;
+    use crate::recorder::lifecycle::{
+        PostGamePhase, RecorderCommand, RecorderEvent, RecorderState, RecorderStateMachine,
+    };
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("league_record_test_{name}_{}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Executes the commands of the state machine like the recorder thread does.
+    /// The parts of the ingame task (starting and stopping the recorder) are done by the test itself.
+    struct Driver {
+        machine: RecorderStateMachine,
+        output_path: PathBuf,
+        replay_buffer_length: Option<u32>,
+        backend: Option<Box<dyn RecordingBackend>>,
+        tray_recording: bool,
+    }
+
+    impl Driver {
+        fn new(output_path: PathBuf, replay_buffer_length: Option<u32>) -> Self {
+            Self {
+                machine: RecorderStateMachine::new(),
+                output_path,
+                replay_buffer_length,
+                backend: None,
+                tray_recording: false,
+            }
+        }
+
+        fn dispatch(&mut self, event: RecorderEvent) {
+            for command in self.machine.handle(event) {
+                match command {
+                    RecorderCommand::StartSession => {
+                        let backend = FakeBackend::new(self.output_path.clone(), self.replay_buffer_length);
+                        self.backend = Some(Box::new(backend));
+                    }
+                    RecorderCommand::SetRecordingTray(recording) => self.tray_recording = recording,
+                    RecorderCommand::AbortTask => self.backend = None,
+                    _ => {}
+                }
+            }
+        }
+
+        fn backend(&mut self) -> &mut Box<dyn RecordingBackend> {
+            self.backend.as_mut().expect("no session")
+        }
+
+        /// what the ingame task does when the game starts
+        fn start_game(&mut self) {
+            self.backend().start_recording().unwrap();
+            self.dispatch(RecorderEvent::GameStarted);
+        }
+
+        /// what the ingame task does when the game is over
+        fn finish_game(&mut self) {
+            self.backend().stop_recording().unwrap();
+            self.dispatch(RecorderEvent::RecordingStopped);
+            self.backend.take().unwrap().shutdown().unwrap();
+            self.dispatch(RecorderEvent::TaskFinished);
+        }
+    }
+
+    #[test]
+    fn records_a_whole_game() {
+        let dir = test_dir("fake_backend_game");
+        let recording = dir.join("game.mp4");
+        let mut driver = Driver::new(recording.clone(), None);
+
+        driver.dispatch(RecorderEvent::WindowGone);
+        assert_eq!(driver.machine.state(), RecorderState::Idle);
+        assert!(driver.backend.is_none());
+
+        driver.dispatch(RecorderEvent::WindowFound);
+        assert_eq!(driver.machine.state(), RecorderState::WaitingForGame);
+        assert!(driver.backend.is_some());
+        assert!(!recording.exists());
+
+        driver.start_game();
+        assert_eq!(driver.machine.state(), RecorderState::Recording);
+        assert!(driver.tray_recording);
+        assert!(driver.backend().start_recording().is_err());
+        assert!(recording.exists());
+
+        // the window stays open until the player leaves the post game lobby
+        driver.dispatch(RecorderEvent::WindowFound);
+        driver.finish_game();
+        assert_eq!(driver.machine.state(), RecorderState::PostGame(PostGamePhase::TaskDone));
+        assert!(!driver.tray_recording);
+
+        driver.dispatch(RecorderEvent::WindowGone);
+        assert_eq!(driver.machine.state(), RecorderState::Idle);
+
+        let contents = fs::read_to_string(&recording).unwrap();
+        assert!(contents.starts_with("LeagueRecord placeholder recording\nduration: "));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn saves_clips_from_the_replay_buffer() {
+        let dir = test_dir("fake_backend_replay");
+        let recording = dir.join("game.mp4");
+        let clip = dir.join("game_clip1.mp4");
+        let mut driver = Driver::new(recording.clone(), Some(30));
+
+        driver.dispatch(RecorderEvent::WindowFound);
+        assert!(driver.backend().save_replay_buffer(&clip).is_err());
+        driver.start_game();
+        assert!(driver.backend().replay_buffer_active());
+        driver.backend().save_replay_buffer(&clip).unwrap();
+
+        // the LoL window closes before the ingame task is done
+        driver.dispatch(RecorderEvent::WindowGone);
+        assert_eq!(
+            driver.machine.state(),
+            RecorderState::PostGame(PostGamePhase::WaitingForStats)
+        );
+        driver.finish_game();
+        assert_eq!(driver.machine.state(), RecorderState::Idle);
+        assert!(!driver.tray_recording);
+
+        // only the clip gets written in replay buffer mode
+        assert!(!recording.exists());
+        assert!(fs::read_to_string(&clip)
+            .unwrap()
+            .starts_with("LeagueRecord placeholder clip\n"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,659 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Child, Command, ExitStatus, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{BackendError, BackendSettings, RecordingBackend};
+use crate::{
+    mp4::Segment,
+    recorder::settings::{AudioSource, Framerate, Size},
+};
+
+const FFMPEG: &str = "ffmpeg";
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+/// length of a single replay buffer segment in seconds
+const SEGMENT_LENGTH: u32 = 2;
+
+/// Where ffmpeg grabs the video from
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FfmpegSource {
+    /// the X11 display from $DISPLAY (also works for games running through Wine/Proton)
+    #[default]
+    X11grab,
+    /// DRM/KMS framebuffer capture (requires CAP_SYS_ADMIN)
+    Kmsgrab,
+    /// synthetic test pattern and test tone - doesn't need a display or audio server
+    Testsrc,
+}
+
+/// Records by running `ffmpeg` as a child process.
+/// The recording is stopped by sending 'q' to ffmpeg's stdin so the mp4 gets finalized properly.
+pub struct FfmpegBackend {
+    args: Vec<String>,
+    debug_log: bool,
+    child: Option<Child>,
+    exit_status: Option<ExitStatus>,
+    replay_buffer: Option<ReplayBuffer>,
+}
+
+/// The replay buffer is a ring of short MPEG-TS segments that ffmpeg keeps overwriting.
+/// Saving a clip concatenates the newest segments into an mp4.
+struct ReplayBuffer {
+    dir: PathBuf,
+    length: u32,
+}
+
+impl FfmpegBackend {
+    pub fn new(settings: BackendSettings, source: FfmpegSource) -> Result<Self, BackendError> {
+        // make sure ffmpeg is actually available before the game starts
+        let version = Command::new(FFMPEG)
+            .arg("-version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        match version {
+            Ok(status) if status.success() => {}
+            Ok(status) => return Err(BackendError(format!("'{FFMPEG} -version' failed: {status}"))),
+            Err(e) => return Err(BackendError(format!("unable to run {FFMPEG}: {e}"))),
+        }
+
+        let replay_buffer = settings.replay_buffer_length.map(|length| ReplayBuffer {
+            dir: std::env::temp_dir().join(format!("league_record_replay_buffer_{}", std::process::id())),
+            length,
+        });
+
+        let args = ffmpeg_args(&settings, source, replay_buffer.as_ref())?;
+        log::info!("ffmpeg arguments: {args:?}");
+
+        Ok(Self {
+            args,
+            debug_log: settings.debug_log,
+            child: None,
+            exit_status: None,
+            replay_buffer,
+        })
+    }
+
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    /// exit status of the last ffmpeg process that was stopped
+    pub fn exit_status(&self) -> Option<ExitStatus> {
+        self.exit_status
+    }
+}
+
+impl RecordingBackend for FfmpegBackend {
+    fn name(&self) -> &'static str {
+        "ffmpeg"
+    }
+
+    fn start_recording(&mut self) -> Result<(), BackendError> {
+        if self.child.is_some() {
+            return Err(BackendError("already recording".into()));
+        }
+
+        if let Some(replay_buffer) = &self.replay_buffer {
+            // start with an empty buffer
+            _ = fs::remove_dir_all(&replay_buffer.dir);
+            fs::create_dir_all(&replay_buffer.dir)
+                .map_err(|e| BackendError(format!("unable to create replay buffer directory: {e}")))?;
+        }
+
+        let (stdout, stderr) = if self.debug_log {
+            (Stdio::inherit(), Stdio::inherit())
+        } else {
+            (Stdio::null(), Stdio::null())
+        };
+        let child = Command::new(FFMPEG)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(stdout)
+            .stderr(stderr)
+            .spawn()
+            .map_err(|e| BackendError(format!("unable to spawn {FFMPEG}: {e}")))?;
+        log::info!("ffmpeg started (pid {})", child.id());
+
+        self.exit_status = None;
+        self.child = Some(child);
+        Ok(())
+    }
+
+    fn stop_recording(&mut self) -> Result<(), BackendError> {
+        let Some(mut child) = self.child.take() else {
+            return Err(BackendError("not recording".into()));
+        };
+
+        // 'q' makes ffmpeg stop reading the input and finish writing the output file
+        if let Some(mut stdin) = child.stdin.take() {
+            if let Err(e) = stdin.write_all(b"q").and_then(|_| stdin.flush()) {
+                log::warn!("unable to send 'q' to ffmpeg: {e}");
+            }
+        }
+
+        let status = wait_with_timeout(&mut child, STOP_TIMEOUT)?;
+        log::info!("ffmpeg exited: {status}");
+        self.exit_status = Some(status);
+
+        if let Some(replay_buffer) = &self.replay_buffer {
+            _ = fs::remove_dir_all(&replay_buffer.dir);
+        }
+
+        if status.success() {
+            Ok(())
+        } else {
+            Err(BackendError(format!("ffmpeg exited with {status}")))
+        }
+    }
+
+    fn shutdown(mut self: Box<Self>) -> Result<(), BackendError> {
+        if self.child.is_some() {
+            self.stop_recording()?;
+        }
+        Ok(())
+    }
+
+    fn replay_buffer_active(&self) -> bool {
+        self.replay_buffer.is_some()
+    }
+
+    fn save_replay_buffer(&mut self, path: &Path) -> Result<(), BackendError> {
+        let Some(replay_buffer) = &self.replay_buffer else {
+            return Err(BackendError("replay buffer is not enabled".into()));
+        };
+        if self.child.is_none() {
+            return Err(BackendError("replay buffer is not running".into()));
+        }
+
+        // newest segments that cover the buffer length (+1 because the newest segment is still being written)
+        let mut segments: Vec<(std::time::SystemTime, PathBuf)> = fs::read_dir(&replay_buffer.dir)
+            .map_err(|e| BackendError(format!("unable to read replay buffer: {e}")))?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "ts"))
+            .filter_map(|path| Some((path.metadata().ok()?.modified().ok()?, path)))
+            .collect();
+        segments.sort();
+        let segment_count = replay_buffer.length.div_ceil(SEGMENT_LENGTH) as usize + 1;
+        let segments = &segments[segments.len().saturating_sub(segment_count)..];
+        if segments.is_empty() {
+            return Err(BackendError("replay buffer is empty".into()));
+        }
+
+        // copy the segments so ffmpeg can't overwrite them while they get concatenated
+        let clip_dir = replay_buffer.dir.with_file_name(format!(
+            "{}_clip_{}",
+            replay_buffer.dir.file_name().unwrap_or_default().to_string_lossy(),
+            chrono::Local::now().timestamp_millis()
+        ));
+        fs::create_dir_all(&clip_dir).map_err(|e| BackendError(format!("unable to create clip directory: {e}")))?;
+        let mut concat_list = String::new();
+        for (i, (_, segment)) in segments.iter().enumerate() {
+            let copy = clip_dir.join(format!("{i:03}.ts"));
+            fs::copy(segment, &copy).map_err(|e| BackendError(format!("unable to copy replay segment: {e}")))?;
+            concat_list.push_str(&format!("file '{}'\n", copy.display()));
+        }
+        let concat_list_path = clip_dir.join("segments.txt");
+        fs::write(&concat_list_path, concat_list)
+            .map_err(|e| BackendError(format!("unable to write segment list: {e}")))?;
+
+        let mut concat = Command::new(FFMPEG);
+        concat
+            .args([
+                "-hide_banner",
+                "-loglevel",
+                "warning",
+                "-y",
+                "-f",
+                "concat",
+                "-safe",
+                "0",
+                "-i",
+            ])
+            .arg(&concat_list_path)
+            .args(["-c", "copy", "-movflags", "+faststart"])
+            .arg(path)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(if self.debug_log {
+                Stdio::inherit()
+            } else {
+                Stdio::null()
+            });
+
+        // remuxing takes a moment - don't block the caller (the ingame task) while it runs
+        let path = path.to_path_buf();
+        thread::spawn(move || {
+            let result = concat.status();
+            log::info!("replay clip {path:?} saved: {result:?}");
+            _ = fs::remove_dir_all(clip_dir);
+        });
+        Ok(())
+    }
+}
+
+/// Copies the streams of `source` into a regular (not fragmented) mp4 file at `destination` without re-encoding.
+pub fn remux(source: &Path, destination: &Path) -> Result<(), BackendError> {
+    let status = Command::new(FFMPEG)
+        .args(["-hide_banner", "-loglevel", "warning", "-y", "-i"])
+        .arg(source)
+        .args(["-map", "0", "-c", "copy", "-movflags", "+faststart"])
+        .arg(destination)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_err(|e| BackendError(format!("unable to run {FFMPEG}: {e}")))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(BackendError(format!("remuxing with ffmpeg failed: {status}")))
+    }
+}
+
+/// Joins the segments into a new mp4 file at `destination` by re-encoding them.
+/// For recordings that can't be joined without re-encoding (e.g. different resolutions or encoders).
+/// Every segment gets scaled to `size` and the audio is only kept if all segments have audio.
+/// Fails if `destination` already exists.
+pub fn reencode_concat(segments: &[Segment], size: Size, audio: bool, destination: &Path) -> Result<(), BackendError> {
+    let output = Command::new(FFMPEG)
+        .args(reencode_concat_args(segments, size, audio))
+        .arg(destination)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|e| BackendError(format!("unable to run {FFMPEG}: {e}")))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(BackendError(format!(
+            "re-encoding with ffmpeg failed: {} {}",
+            output.status,
+            stderr.trim()
+        )))
+    }
+}
+
+/// the arguments for `reencode_concat` without the output file
+fn reencode_concat_args(segments: &[Segment], size: Size, audio: bool) -> Vec<String> {
+    // never overwrite the output file
+    let mut args: Vec<String> = ["-hide_banner", "-loglevel", "error", "-n"].map(String::from).into();
+    for segment in segments {
+        let start = segment.start.max(0.0);
+        push_args(
+            &mut args,
+            &[
+                "-ss",
+                &format!("{start:.3}"),
+                "-t",
+                &format!("{:.3}", segment.end - start),
+            ],
+        );
+        args.push("-i".into());
+        args.push(segment.source.to_string_lossy().into_owned());
+    }
+
+    // libx264 needs an even width and height
+    let (width, height) = (size.width() & !1, size.height() & !1);
+    let mut filter = String::new();
+    let mut concat_inputs = String::new();
+    for i in 0..segments.len() {
+        filter.push_str(&format!(
+            "[{i}:v:0]scale={width}:{height}:force_original_aspect_ratio=decrease,\
+             pad={width}:{height}:(ow-iw)/2:(oh-ih)/2,setsar=1[v{i}];"
+        ));
+        concat_inputs.push_str(&format!("[v{i}]"));
+        if audio {
+            filter.push_str(&format!(
+                "[{i}:a:0]aresample=48000,aformat=channel_layouts=stereo[a{i}];"
+            ));
+            concat_inputs.push_str(&format!("[a{i}]"));
+        }
+    }
+    let audio_streams = u8::from(audio);
+    filter.push_str(&format!(
+        "{concat_inputs}concat=n={}:v=1:a={audio_streams}[v]",
+        segments.len()
+    ));
+    if audio {
+        filter.push_str("[a]");
+    }
+
+    push_args(&mut args, &["-filter_complex", &filter, "-map", "[v]"]);
+    if audio {
+        push_args(&mut args, &["-map", "[a]", "-c:a", "aac", "-b:a", "192k"]);
+    }
+    push_args(
+        &mut args,
+        &[
+            "-c:v",
+            "libx264",
+            "-preset",
+            "veryfast",
+            "-crf",
+            "20",
+            "-movflags",
+            "+faststart",
+        ],
+    );
+    args
+}
+
+fn wait_with_timeout(child: &mut Child, timeout: Duration) -> Result<ExitStatus, BackendError> {
+    let start = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) => return Ok(status),
+            Ok(None) if start.elapsed() < timeout => thread::sleep(Duration::from_millis(100)),
+            Ok(None) => {
+                log::warn!("ffmpeg didn't stop after {timeout:?} - killing it");
+                _ = child.kill();
+                return child
+                    .wait()
+                    .map_err(|e| BackendError(format!("unable to wait for ffmpeg: {e}")));
+            }
+            Err(e) => return Err(BackendError(format!("unable to wait for ffmpeg: {e}"))),
+        }
+    }
+}
+
+fn ffmpeg_args(
+    settings: &BackendSettings,
+    source: FfmpegSource,
+    replay_buffer: Option<&ReplayBuffer>,
+) -> Result<Vec<String>, BackendError> {
+    let framerate = framerate_arg(settings.framerate);
+    let input_size = format!("{}x{}", settings.window_size.width(), settings.window_size.height());
+    let output_size = settings.output_resolution.size();
+    let output_path = settings
+        .output_path
+        .to_str()
+        .ok_or_else(|| BackendError("output path is not valid UTF-8".into()))?;
+
+    let mut args = Vec::new();
+    push_args(&mut args, &["-hide_banner", "-loglevel", "warning", "-nostats", "-y"]);
+
+    // --- video input ---
+    let mut video_filter = String::new();
+    match source {
+        FfmpegSource::X11grab => {
+            // only grab the client area of the LoL window instead of the whole screen
+            let display = std::env::var("DISPLAY").unwrap_or_else(|_| String::from(":0.0"));
+            let position = &settings.window_position;
+            let input = format!("{display}+{},{}", position.x(), position.y());
+            push_args(&mut args, &["-f", "x11grab", "-framerate", &framerate]);
+            push_args(&mut args, &["-video_size", &input_size, "-i", &input]);
+        }
+        FfmpegSource::Kmsgrab => {
+            push_args(&mut args, &["-f", "kmsgrab", "-framerate", &framerate, "-i", "-"]);
+            video_filter.push_str("hwdownload,format=bgr0,");
+        }
+        FfmpegSource::Testsrc => {
+            let testsrc = format!("testsrc=size={input_size}:rate={framerate}");
+            push_args(&mut args, &["-f", "lavfi", "-i", &testsrc]);
+        }
+    }
+
+    // --- audio input ---
+    let audio_inputs = match (source, settings.audio_source) {
+        (_, AudioSource::NONE) => 0,
+        (FfmpegSource::Testsrc, _) => {
+            push_args(&mut args, &["-f", "lavfi", "-i", "sine=frequency=440"]);
+            1
+        }
+        (_, AudioSource::APPLICATION | AudioSource::SYSTEM) => {
+            // there is no per-application capture in PulseAudio/PipeWire - record everything that is played back
+            push_args(&mut args, &["-f", "pulse", "-i", "@DEFAULT_MONITOR@"]);
+            1
+        }
+        (_, AudioSource::ALL) => {
+            push_args(&mut args, &["-f", "pulse", "-i", "@DEFAULT_MONITOR@"]);
+            push_args(&mut args, &["-f", "pulse", "-i", "default"]);
+            2
+        }
+    };
+
+    // --- output ---
+    video_filter.push_str(&format!("scale={}:{}", output_size.width(), output_size.height()));
+    push_args(&mut args, &["-map", "0:v", "-vf", &video_filter]);
+    match audio_inputs {
+        0 => {}
+        1 => push_args(&mut args, &["-map", "1:a"]),
+        _ => push_args(
+            &mut args,
+            &[
+                "-filter_complex",
+                "[1:a][2:a]amix=inputs=2:duration=longest[aout]",
+                "-map",
+                "[aout]",
+            ],
+        ),
+    }
+    // CQP 0-50 roughly maps to the x264 CRF scale 0-51
+    let crf = settings.encoding_quality.min(51).to_string();
+    push_args(&mut args, &["-c:v", "libx264", "-preset", "veryfast", "-crf", &crf]);
+    push_args(&mut args, &["-pix_fmt", "yuv420p", "-r", &framerate]);
+    if audio_inputs > 0 {
+        push_args(&mut args, &["-c:a", "aac", "-b:a", "160k"]);
+    }
+
+    match replay_buffer {
+        Some(replay_buffer) => {
+            // keyframe at the start of every segment so each segment can be cut without re-encoding
+            let keyframes = format!("expr:gte(t,n_forced*{SEGMENT_LENGTH})");
+            let segment_wrap = (replay_buffer.length.div_ceil(SEGMENT_LENGTH) + 2).to_string();
+            let segment_pattern = replay_buffer.dir.join("segment%03d.ts");
+            let segment_pattern = segment_pattern
+                .to_str()
+                .ok_or_else(|| BackendError("replay buffer path is not valid UTF-8".into()))?;
+            push_args(&mut args, &["-force_key_frames", &keyframes, "-f", "segment"]);
+            push_args(&mut args, &["-segment_time", &SEGMENT_LENGTH.to_string()]);
+            push_args(
+                &mut args,
+                &["-segment_wrap", &segment_wrap, "-segment_format", "mpegts"],
+            );
+            push_args(&mut args, &["-reset_timestamps", "1", segment_pattern]);
+        }
+        None => {
+            // fragmented so everything up to the last fragment stays playable if the recording gets interrupted
+            // (the extension of the file doesn't tell ffmpeg the format while it is being recorded)
+            push_args(
+                &mut args,
+                &["-f", "mp4", "-movflags", "+frag_keyframe+empty_moov+default_base_moof"],
+            );
+            args.push(output_path.to_owned());
+        }
+    }
+
+    Ok(args)
+}
+
+fn push_args(args: &mut Vec<String>, new_args: &[&str]) {
+    args.extend(new_args.iter().map(|arg| arg.to_string()));
+}
+
+fn framerate_arg(framerate: Framerate) -> String {
+    match (framerate.numerator(), framerate.denominator()) {
+        (num, den) if num > 0 && den > 0 => format!("{num}/{den}"),
+        _ => String::from("30"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recorder::settings::{Position, Resolution, Size};
+
+    fn settings(audio_source: AudioSource) -> BackendSettings {
+        BackendSettings {
+            window_size: Size::new(2560, 1440),
+            window_position: Position::new(100, 50),
+            output_resolution: Resolution::_1080p,
+            framerate: Framerate::new(60, 1),
+            encoding_quality: 25,
+            audio_source,
+            output_path: PathBuf::from("/recordings/.recording/game.mp4"),
+            debug_log: false,
+            ffmpeg_source: FfmpegSource::X11grab,
+            replay_buffer_length: None,
+        }
+    }
+
+    /// the value that follows the first `flag` after `start`
+    fn arg_after<'a>(args: &'a [String], flag: &str, start: usize) -> &'a str {
+        let index = args[start..].iter().position(|arg| arg == flag).expect(flag) + start;
+        &args[index + 1]
+    }
+
+    #[test]
+    fn grabs_the_lol_window() {
+        let args = ffmpeg_args(&settings(AudioSource::NONE), FfmpegSource::X11grab, None).unwrap();
+
+        assert_eq!(arg_after(&args, "-f", 0), "x11grab");
+        assert_eq!(arg_after(&args, "-framerate", 0), "60/1");
+        assert_eq!(arg_after(&args, "-video_size", 0), "2560x1440");
+        assert!(arg_after(&args, "-i", 0).ends_with("+100,50"));
+        assert_eq!(arg_after(&args, "-vf", 0), "scale=1920:1080");
+        assert_eq!(arg_after(&args, "-crf", 0), "25");
+        assert_eq!(arg_after(&args, "-r", 0), "60/1");
+        assert_eq!(args.last().unwrap(), "/recordings/.recording/game.mp4");
+        // no audio
+        assert_eq!(args.iter().filter(|arg| *arg == "-i").count(), 1);
+        assert!(!args.iter().any(|arg| arg == "-c:a"));
+    }
+
+    #[test]
+    fn maps_the_output_resolution() {
+        for (resolution, scale) in [
+            (Resolution::_480p, "scale=854:480"),
+            (Resolution::_1440p, "scale=2560:1440"),
+            (Resolution::_4320p, "scale=7680:4320"),
+            (Resolution::_1280x1024p, "scale=1280:1024"),
+            (Resolution::_3840x1600p, "scale=3840:1600"),
+        ] {
+            let settings = BackendSettings {
+                output_resolution: resolution,
+                ..settings(AudioSource::NONE)
+            };
+            let args = ffmpeg_args(&settings, FfmpegSource::X11grab, None).unwrap();
+            assert_eq!(arg_after(&args, "-vf", 0), scale);
+        }
+    }
+
+    #[test]
+    fn maps_the_framerate() {
+        assert_eq!(framerate_arg(Framerate::new(30, 1)), "30/1");
+        assert_eq!(framerate_arg(Framerate::new(30, 2)), "30/2");
+        // invalid fractions fall back to 30fps
+        assert_eq!(framerate_arg(Framerate::new(0, 1)), "30");
+        assert_eq!(framerate_arg(Framerate::new(30, 0)), "30");
+    }
+
+    #[test]
+    fn maps_the_audio_source() {
+        for audio_source in [AudioSource::APPLICATION, AudioSource::SYSTEM] {
+            let args = ffmpeg_args(&settings(audio_source), FfmpegSource::X11grab, None).unwrap();
+            let pulse = args.iter().position(|arg| arg == "pulse").unwrap();
+            assert_eq!(arg_after(&args, "-i", pulse), "@DEFAULT_MONITOR@");
+            assert_eq!(arg_after(&args, "-map", pulse), "0:v");
+            assert_eq!(arg_after(&args, "-map", pulse + 4), "1:a");
+            assert_eq!(arg_after(&args, "-c:a", 0), "aac");
+        }
+
+        // playback and microphone get mixed into a single audio track
+        let args = ffmpeg_args(&settings(AudioSource::ALL), FfmpegSource::X11grab, None).unwrap();
+        assert_eq!(args.iter().filter(|arg| *arg == "pulse").count(), 2);
+        assert!(arg_after(&args, "-filter_complex", 0).starts_with("[1:a][2:a]amix=inputs=2"));
+        assert_eq!(arg_after(&args, "-c:a", 0), "aac");
+    }
+
+    #[test]
+    fn maps_the_other_sources() {
+        let args = ffmpeg_args(&settings(AudioSource::APPLICATION), FfmpegSource::Testsrc, None).unwrap();
+        let testsrc = args.iter().position(|arg| arg.starts_with("testsrc")).unwrap();
+        assert_eq!(args[testsrc], "testsrc=size=2560x1440:rate=60/1");
+        assert_eq!(arg_after(&args, "-i", testsrc), "sine=frequency=440");
+        assert!(!args.iter().any(|arg| arg == "x11grab" || arg == "pulse"));
+
+        let args = ffmpeg_args(&settings(AudioSource::NONE), FfmpegSource::Kmsgrab, None).unwrap();
+        assert_eq!(arg_after(&args, "-f", 0), "kmsgrab");
+        assert_eq!(arg_after(&args, "-vf", 0), "hwdownload,format=bgr0,scale=1920:1080");
+    }
+
+    #[test]
+    fn records_fragmented_or_into_the_replay_buffer() {
+        let args = ffmpeg_args(&settings(AudioSource::NONE), FfmpegSource::X11grab, None).unwrap();
+        assert!(arg_after(&args, "-movflags", 0).contains("frag_keyframe"));
+
+        let replay_buffer = ReplayBuffer {
+            dir: PathBuf::from("/tmp/replay_buffer"),
+            length: 30,
+        };
+        let args = ffmpeg_args(
+            &settings(AudioSource::NONE),
+            FfmpegSource::X11grab,
+            Some(&replay_buffer),
+        )
+        .unwrap();
+        assert_eq!(arg_after(&args, "-segment_time", 0), SEGMENT_LENGTH.to_string());
+        // enough segments for the whole buffer + the one that is currently being written
+        assert_eq!(
+            arg_after(&args, "-segment_wrap", 0),
+            (30 / SEGMENT_LENGTH + 2).to_string()
+        );
+        assert_eq!(args.last().unwrap(), "/tmp/replay_buffer/segment%03d.ts");
+        assert!(!args.iter().any(|arg| arg == "-movflags"));
+    }
+
+    #[test]
+    fn reencodes_every_segment_to_the_same_size() {
+        let segment = |source: &str, start: f64, end: f64| Segment {
+            source: PathBuf::from(source),
+            start,
+            end,
+        };
+        let segments = [
+            segment("/recordings/a.mp4", -1.0, 5.5),
+            segment("/recordings/b.mp4", 60.0, 90.0),
+        ];
+
+        let args = reencode_concat_args(&segments, Size::new(1921, 1081), true);
+        assert!(args.iter().any(|arg| arg == "-n"));
+        let first = args.iter().position(|arg| arg == "/recordings/a.mp4").unwrap();
+        assert_eq!(
+            (arg_after(&args, "-ss", 0), arg_after(&args, "-t", 0)),
+            ("0.000", "5.500")
+        );
+        assert_eq!(
+            (arg_after(&args, "-ss", first), arg_after(&args, "-t", first)),
+            ("60.000", "30.000")
+        );
+        assert_eq!(args[first + 1..].iter().filter(|arg| *arg == "-i").count(), 1);
+
+        let filter = arg_after(&args, "-filter_complex", 0);
+        assert!(filter.starts_with("[0:v:0]scale=1920:1080:force_original_aspect_ratio=decrease,pad=1920:1080:"));
+        assert!(filter.contains("[1:v:0]scale=1920:1080"));
+        assert!(filter.contains("[1:a:0]aresample"));
+        assert!(filter.ends_with("[v0][a0][v1][a1]concat=n=2:v=1:a=1[v][a]"));
+        assert_eq!(arg_after(&args, "-map", 0), "[v]");
+        let video_map = args.iter().position(|arg| arg == "-map").unwrap();
+        assert_eq!(arg_after(&args, "-map", video_map + 1), "[a]");
+
+        let args = reencode_concat_args(&segments, Size::new(1920, 1080), false);
+        let filter = arg_after(&args, "-filter_complex", 0);
+        assert!(!filter.contains(":a:0"));
+        assert!(filter.ends_with("[v0][v1]concat=n=2:v=1:a=0[v]"));
+        assert_eq!(args.iter().filter(|arg| *arg == "-map").count(), 1);
+        assert!(!args.iter().any(|arg| arg == "-c:a"));
+    }
+}
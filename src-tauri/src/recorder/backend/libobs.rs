@@ -0,0 +1,162 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use libobs_recorder::{
+    settings::{self as obs, RateControl, Window},
+    Recorder, RecorderSettings,
+};
+
+use super::{BackendError, BackendSettings, RecordingBackend};
+use crate::recorder::{
+    recovery::FRAGMENTED_EXTENSION,
+    settings::{AudioSource, Framerate, Resolution, Size},
+    WINDOW_CLASS, WINDOW_PROCESS, WINDOW_TITLE,
+};
+
+/// Records via libobs running in the separate `extprocess_recorder.exe` (Windows only)
+pub struct LibobsBackend {
+    recorder: Recorder,
+    /// libobs writes a fragmented mp4 so the recording survives a crash of extprocess_recorder or LeagueRecord
+    fragmented_path: PathBuf,
+    output_path: PathBuf,
+}
+
+impl LibobsBackend {
+    pub fn new(settings: BackendSettings) -> Result<Self, BackendError> {
+        if settings.replay_buffer_length.is_some() {
+            return Err(BackendError("the libobs backend has no replay buffer".into()));
+        }
+
+        let mut recorder_settings = RecorderSettings::new();
+        recorder_settings.set_window(Window::new(
+            WINDOW_TITLE,
+            Some(WINDOW_CLASS.into()),
+            Some(WINDOW_PROCESS.into()),
+        ));
+        recorder_settings.set_input_resolution(obs_size(settings.window_size));
+        recorder_settings.set_output_resolution(obs_resolution(settings.output_resolution));
+        recorder_settings.set_framerate(obs_framerate(settings.framerate));
+        recorder_settings.set_rate_control(RateControl::CQP(settings.encoding_quality));
+        recorder_settings.record_audio(obs_audio_source(settings.audio_source));
+        // libobs picks the muxer by the file extension - .ismv gets ffmpeg's fragmented mp4 muxer
+        let fragmented_path = settings.output_path.with_extension(FRAGMENTED_EXTENSION);
+        recorder_settings.set_output_path(
+            fragmented_path
+                .to_str()
+                .expect("error converting filename path to &str"),
+        );
+
+        // if LeagueRecord gets launched by Windows Autostart the CWD is system32 instead of the installation folder
+        // get directory to current executable so we can locate extprocess_recorder.exe
+        let exe_dir = match std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(|a| a.to_path_buf()))
+        {
+            Some(exe_dir) => {
+                log::info!("executable directory: {:?}", exe_dir);
+                exe_dir
+            }
+            None => {
+                log::warn!("unable to get executable directory - trying relative path instead");
+                PathBuf::from("./")
+            }
+        };
+        let mut recorder = Recorder::new_with_paths(
+            Some(exe_dir.join(Path::new("libobs/extprocess_recorder.exe")).as_path()),
+            None,
+            None,
+            None,
+            settings.debug_log,
+        )
+        .map_err(|e| BackendError(format!("failed to create recorder: {e}")))?;
+
+        let configured = recorder.configure(&recorder_settings);
+        log::info!("recorder configured: {configured:?}");
+        log::info!("Available encoders: {:?}", recorder.available_encoders());
+        log::info!("Selected encoder: {:?}", recorder.selected_encoder());
+        if let Err(e) = configured {
+            return Err(BackendError(format!("failed to configure recorder: {e:?}")));
+        }
+
+        Ok(Self {
+            recorder,
+            fragmented_path,
+            output_path: settings.output_path,
+        })
+    }
+}
+
+impl RecordingBackend for LibobsBackend {
+    fn name(&self) -> &'static str {
+        "libobs"
+    }
+
+    fn start_recording(&mut self) -> Result<(), BackendError> {
+        self.recorder
+            .start_recording()
+            .map(|_| ())
+            .map_err(|e| BackendError(format!("{e:?}")))
+    }
+
+    fn stop_recording(&mut self) -> Result<(), BackendError> {
+        self.recorder
+            .stop_recording()
+            .map(|_| ())
+            .map_err(|e| BackendError(format!("{e:?}")))
+    }
+
+    fn shutdown(self: Box<Self>) -> Result<(), BackendError> {
+        let shutdown = self
+            .recorder
+            .shutdown()
+            .map(|_| ())
+            .map_err(|e| BackendError(format!("{e:?}")));
+
+        // extprocess_recorder is gone so the file isn't in use anymore
+        // if this fails the recording stays in the staging folder and gets recovered on the next start
+        if self.fragmented_path.exists() {
+            fs::rename(&self.fragmented_path, &self.output_path)
+                .map_err(|e| BackendError(format!("unable to rename {:?}: {e}", self.fragmented_path)))?;
+        }
+        shutdown
+    }
+}
+
+fn obs_size(size: Size) -> obs::Size {
+    obs::Size::new(size.width(), size.height())
+}
+
+fn obs_resolution(resolution: Resolution) -> obs::Resolution {
+    match resolution {
+        Resolution::_480p => obs::Resolution::_480p,
+        Resolution::_720p => obs::Resolution::_720p,
+        Resolution::_1080p => obs::Resolution::_1080p,
+        Resolution::_1440p => obs::Resolution::_1440p,
+        Resolution::_2160p => obs::Resolution::_2160p,
+        Resolution::_4320p => obs::Resolution::_4320p,
+        Resolution::_1600x1200p => obs::Resolution::_1600x1200p,
+        Resolution::_1280x1024p => obs::Resolution::_1280x1024p,
+        Resolution::_1920x1080p => obs::Resolution::_1920x1080p,
+        Resolution::_1920x1200p => obs::Resolution::_1920x1200p,
+        Resolution::_2560x1080p => obs::Resolution::_2560x1080p,
+        Resolution::_2580x1080p => obs::Resolution::_2580x1080p,
+        Resolution::_3840x1600p => obs::Resolution::_3840x1600p,
+        Resolution::_3840x1080p => obs::Resolution::_3840x1080p,
+        Resolution::_3840x1200p => obs::Resolution::_3840x1200p,
+    }
+}
+
+fn obs_framerate(framerate: Framerate) -> obs::Framerate {
+    obs::Framerate::new(framerate.numerator(), framerate.denominator())
+}
+
+fn obs_audio_source(audio_source: AudioSource) -> obs::AudioSource {
+    match audio_source {
+        AudioSource::NONE => obs::AudioSource::NONE,
+        AudioSource::APPLICATION => obs::AudioSource::APPLICATION,
+        AudioSource::SYSTEM => obs::AudioSource::SYSTEM,
+        AudioSource::ALL => obs::AudioSource::ALL,
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::recorder::settings::{AudioSource, Framerate, Position, Resolution, Size};
+
+pub use fake::FakeBackend;
+pub use ffmpeg::{reencode_concat, remux, FfmpegBackend, FfmpegSource};
+#[cfg(target_os = "windows")]
+pub use libobs::LibobsBackend;
+
+mod fake;
+mod ffmpeg;
+#[cfg(target_os = "windows")]
+mod libobs;
+
+/// Everything a backend needs to know to record a single game.
+#[derive(Debug)]
+pub struct BackendSettings {
+    pub window_size: Size,
+    /// only used by the ffmpeg backend (libobs captures the window itself)
+    pub window_position: Position,
+    pub output_resolution: Resolution,
+    pub framerate: Framerate,
+    pub encoding_quality: u32,
+    pub audio_source: AudioSource,
+    pub output_path: PathBuf,
+    pub debug_log: bool,
+    /// only used by the ffmpeg backend
+    pub ffmpeg_source: FfmpegSource,
+    /// if set only the last n seconds are kept in a replay buffer instead of recording the whole game
+    pub replay_buffer_length: Option<u32>,
+}
+
+#[derive(Debug)]
+pub struct BackendError(pub String);
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for BackendError {}
+
+/// The recorder loop and `collect_ingame_data` only talk to the recording through this trait,
+/// so the actual implementation (libobs, a fake for testing, ...) can be swapped out.
+pub trait RecordingBackend: Send {
+    fn name(&self) -> &'static str;
+    fn start_recording(&mut self) -> Result<(), BackendError>;
+    fn stop_recording(&mut self) -> Result<(), BackendError>;
+    fn shutdown(self: Box<Self>) -> Result<(), BackendError>;
+
+    /// true if the backend records into a replay buffer instead of the output file
+    fn replay_buffer_active(&self) -> bool {
+        false
+    }
+
+    /// write the current content of the replay buffer to a new file at `path`
+    fn save_replay_buffer(&mut self, _path: &Path) -> Result<(), BackendError> {
+        Err(BackendError(format!(
+            "the {} backend has no replay buffer",
+            self.name()
+        )))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    #[cfg_attr(target_os = "windows", default)]
+    Libobs,
+    /// runs an ffmpeg child process (Linux / Wine)
+    #[cfg_attr(not(target_os = "windows"), default)]
+    Ffmpeg,
+    /// doesn't record anything, only writes a placeholder file (useful for testing without LoL or libobs)
+    Fake,
+}
+
+impl BackendKind {
+    /// libobs-recorder can only record the whole game
+    pub fn supports_replay_buffer(self) -> bool {
+        !matches!(self, BackendKind::Libobs)
+    }
+}
+
+pub fn create_backend(kind: BackendKind, settings: BackendSettings) -> Result<Box<dyn RecordingBackend>, BackendError> {
+    let backend: Box<dyn RecordingBackend> = match kind {
+        #[cfg(target_os = "windows")]
+        BackendKind::Libobs => Box::new(LibobsBackend::new(settings)?),
+        #[cfg(not(target_os = "windows"))]
+        BackendKind::Libobs => return Err(BackendError("the libobs backend is only available on Windows".into())),
+        BackendKind::Ffmpeg => {
+            let source = settings.ffmpeg_source;
+            Box::new(FfmpegBackend::new(settings, source)?)
+        }
+        BackendKind::Fake => Box::new(FakeBackend::new(settings.output_path, settings.replay_buffer_length)),
+    };
+    log::info!("created recording backend: {}", backend.name());
+    Ok(backend)
+}
@@ -1,48 +1,214 @@
-use serde::{Deserialize, Serialize};
-
-#[derive(Debug, Serialize, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct GameData {
-    pub win: Option<bool>,
-    pub game_info: GameInfo,
-    pub stats: Stats,
-    pub events: Vec<GameEvent>,
-}
-
-#[derive(Debug, Serialize, Default)]
-#[serde(rename_all = "camelCase")]
-pub struct GameInfo {
-    pub game_mode: String,
-    pub summoner_name: String,
-    pub champion_name: String,
-}
-
-#[derive(Debug, Default, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct Stats {
-    #[serde(alias = "CHAMPIONS_KILLED")]
-    pub kills: u64,
-    #[serde(alias = "NUM_DEATHS")]
-    pub deaths: u64,
-    #[serde(alias = "ASSISTS")]
-    pub assists: u64,
-    /// lane minons killed
-    #[serde(default)]
-    #[serde(alias = "MINIONS_KILLED")]
-    pub minions_killed: u64,
-    /// neutral objectives killed
-    #[serde(default)]
-    #[serde(alias = "NEUTRAL_MINIONS_KILLED")]
-    pub neutral_minions_killed: u64,
-    // add default value fallback since there is no ward score in some game modes like ARAM
-    #[serde(default)]
-    #[serde(alias = "VISION_SCORE")]
-    pub ward_score: f64,
-}
-
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct GameEvent {
-    pub name: &'static str,
-    pub time: f64,
-}
+use serde::{Deserialize, Serialize};
+
+/// Version of the metadata format - increase it (and add a migration) whenever the format changes.
+/// Files without a version are version 0.
+pub const SCHEMA_VERSION: u32 = 3;
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct GameData {
+    pub schema_version: u32,
+    pub win: Option<bool>,
+    pub game_info: GameInfo,
+    pub stats: Stats,
+    pub events: Vec<GameEvent>,
+    /// notes of the user (never written by the recorder)
+    pub annotations: Vec<Annotation>,
+    /// user defined tags
+    pub tags: Vec<String>,
+    pub favorite: bool,
+    /// protected recordings can't be deleted
+    pub protected: bool,
+}
+
+impl Default for GameData {
+    fn default() -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            win: None,
+            game_info: GameInfo::default(),
+            stats: Stats::default(),
+            events: Vec::new(),
+            annotations: Vec::new(),
+            tags: Vec::new(),
+            favorite: false,
+            protected: false,
+        }
+    }
+}
+
+impl GameData {
+    /// The post game stats - `None` if they never arrived (e.g. the game got interrupted or the recording recovered).
+    pub fn collected_stats(&self) -> Option<&Stats> {
+        let stats = &self.stats;
+        let has_stats =
+            stats.kills + stats.deaths + stats.assists + stats.minions_killed + stats.neutral_minions_killed > 0
+                || stats.ward_score > 0.0;
+        has_stats.then_some(stats)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct GameInfo {
+    pub game_mode: String,
+    pub summoner_name: String,
+    pub champion_name: String,
+}
+
+/// Deserializes from the LCU end of game stats as well as from the metadata files.
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Stats {
+    #[serde(default)]
+    #[serde(alias = "CHAMPIONS_KILLED")]
+    pub kills: u64,
+    #[serde(default)]
+    #[serde(alias = "NUM_DEATHS")]
+    pub deaths: u64,
+    #[serde(default)]
+    #[serde(alias = "ASSISTS")]
+    pub assists: u64,
+    /// lane minons killed
+    #[serde(default)]
+    #[serde(alias = "MINIONS_KILLED")]
+    pub minions_killed: u64,
+    /// neutral objectives killed
+    #[serde(default)]
+    #[serde(alias = "NEUTRAL_MINIONS_KILLED")]
+    pub neutral_minions_killed: u64,
+    // add default value fallback since there is no ward score in some game modes like ARAM
+    #[serde(default)]
+    #[serde(alias = "VISION_SCORE")]
+    pub ward_score: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GameEvent {
+    pub name: String,
+    pub time: f64,
+}
+
+/// A note of the user at a specific time (or time range) of the recording.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Annotation {
+    /// unique in the recording
+    pub id: u64,
+    /// in seconds
+    pub time: f64,
+    /// end of the annotated time range in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<f64>,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+}
+
+/// Metadata of a clip (saved from the replay buffer or cut from a recording).
+/// The event times in `game_data` are relative to the start of the clip.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClipData {
+    #[serde(flatten)]
+    pub game_data: GameData,
+    /// the event that triggered the clip (time relative to the start of the game recording)
+    pub trigger: GameEvent,
+    /// position of the triggering event in the clip in seconds
+    pub offset: f64,
+    /// the recording the clip was cut from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<ClipSource>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClipSource {
+    /// filename of the recording
+    pub file: String,
+    /// start of the clip in the recording in seconds
+    pub start: f64,
+    /// end of the clip in the recording in seconds
+    pub end: f64,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn round_trips_the_game_data() {
+        let metadata = json!({
+            "schemaVersion": SCHEMA_VERSION,
+            "win": true,
+            "gameInfo": { "gameMode": "CLASSIC", "summonerName": "Faker", "championName": "Ahri" },
+            "stats": {
+                "kills": 5,
+                "deaths": 2,
+                "assists": 7,
+                "minionsKilled": 180,
+                "neutralMinionsKilled": 12,
+                "wardScore": 23.5
+            },
+            "events": [{ "name": "Kill", "time": 312.5 }, { "name": "Dragon", "time": 600.0 }],
+            "annotations": [
+                { "id": 1, "time": 300.0, "text": "gank" },
+                { "id": 2, "time": 590.0, "end": 610.0, "text": "dragon fight", "category": "teamfight" }
+            ],
+            "tags": ["ranked", "main"],
+            "favorite": true,
+            "protected": false
+        });
+        let game_data: GameData = serde_json::from_value(metadata.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&game_data).unwrap(), metadata);
+    }
+
+    #[test]
+    fn fills_in_missing_fields() {
+        let game_data: GameData = serde_json::from_value(json!({ "stats": { "kills": 3 } })).unwrap();
+        assert_eq!(game_data.schema_version, SCHEMA_VERSION);
+        assert_eq!(
+            (game_data.stats.kills, game_data.stats.deaths, game_data.stats.assists),
+            (3, 0, 0)
+        );
+        assert!(game_data.win.is_none() && game_data.events.is_empty() && game_data.tags.is_empty());
+    }
+
+    #[test]
+    fn reads_the_end_of_game_stats() {
+        // shortened lol-end-of-game/v1/eog-stats-block
+        let mut eog_stats = json!({
+            "gameId": 6543210987u64,
+            "gameMode": "CLASSIC",
+            "localPlayer": {
+                "championName": "Ahri",
+                "stats": {
+                    "ASSISTS": 7,
+                    "CHAMPIONS_KILLED": 5,
+                    "GOLD_EARNED": 12345,
+                    "LEVEL": 16,
+                    "MINIONS_KILLED": 180,
+                    "NEUTRAL_MINIONS_KILLED": 12,
+                    "NUM_DEATHS": 2,
+                    "VISION_SCORE": 23,
+                    "WIN": 1
+                }
+            }
+        });
+        let stats: Stats = serde_json::from_value(eog_stats["localPlayer"]["stats"].take()).unwrap();
+        assert_eq!(
+            serde_json::to_value(stats).unwrap(),
+            json!({
+                "kills": 5,
+                "deaths": 2,
+                "assists": 7,
+                "minionsKilled": 180,
+                "neutralMinionsKilled": 12,
+                "wardScore": 23.0
+            })
+        );
+    }
+}
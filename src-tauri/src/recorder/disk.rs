@@ -0,0 +1,115 @@
+/*
+    Keeps recordings from filling up the drive of the recordings folder.
+    A recording doesn't start below the configured amount of free space and a running recording gets stopped
+    (and finalized like at the end of a game) as soon as the free space drops below it.
+*/
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+/// how often the free space gets checked while recording
+pub const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+const BYTES_PER_GB: f64 = 1024.0 * 1024.0 * 1024.0;
+
+/// payload of the "low_disk_space" event for the UI
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LowDiskSpace {
+    pub free_gb: f64,
+    pub min_free_gb: f64,
+    /// `true` if a running recording got stopped, `false` if the recording didn't start at all
+    pub recording_stopped: bool,
+}
+
+pub struct DiskSpaceGuard {
+    app_handle: AppHandle,
+    folder: PathBuf,
+    min_free_gb: f64,
+}
+
+impl DiskSpaceGuard {
+    pub fn new(app_handle: AppHandle, folder: PathBuf, min_free_gb: f64) -> Self {
+        Self {
+            app_handle,
+            folder,
+            min_free_gb,
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.min_free_gb > 0.0
+    }
+
+    /// Returns `true` if the free space is below the threshold and tells the UI and the tray why the recording
+    /// didn't start or got stopped. If the free space can't be determined the recording goes on as usual.
+    pub fn is_low(&self, recording: bool) -> bool {
+        if !self.enabled() {
+            return false;
+        }
+
+        let free_gb = match free_space(&self.folder) {
+            Ok(bytes) => bytes as f64 / BYTES_PER_GB,
+            Err(e) => {
+                log::warn!("unable to check free space of {:?}: {e}", self.folder);
+                return false;
+            }
+        };
+        if free_gb >= self.min_free_gb {
+            return false;
+        }
+
+        let low_disk_space = LowDiskSpace {
+            free_gb,
+            min_free_gb: self.min_free_gb,
+            recording_stopped: recording,
+        };
+        log::warn!("low disk space: {low_disk_space:?}");
+        _ = self.app_handle.emit_all("low_disk_space", &low_disk_space);
+        _ = self
+            .app_handle
+            .tray_handle()
+            .get_item("rec")
+            .set_title("Not Recording (low disk space)");
+        true
+    }
+}
+
+/// free space (in bytes) the current user can use on the drive that contains `path`
+#[cfg(target_os = "windows")]
+pub fn free_space(path: &Path) -> io::Result<u64> {
+    use windows::{core::HSTRING, Win32::Storage::FileSystem::GetDiskFreeSpaceExW};
+
+    let mut free_bytes = 0u64;
+    unsafe { GetDiskFreeSpaceExW(&HSTRING::from(path), Some(&mut free_bytes), None, None) }?;
+    Ok(free_bytes)
+}
+
+/// free space (in bytes) the current user can use on the drive that contains `path`
+#[cfg(target_os = "linux")]
+pub fn free_space(path: &Path) -> io::Result<u64> {
+    use std::{ffi::CString, mem::MaybeUninit, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let stat = unsafe { stat.assume_init() };
+    #[allow(clippy::unnecessary_cast)] // the field types differ between targets
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+pub fn free_space(_path: &Path) -> io::Result<u64> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "checking the free space is not supported on this platform",
+    ))
+}
@@ -0,0 +1,192 @@
+/*
+    Placeholders for the filename format that get filled in with the data of the game ({champion}, {kda}, ...).
+    Most of the data is only known once the game is over, so a recording keeps the placeholders in its name while it
+    is recorded and gets renamed (together with its metadata file) as soon as the metadata is complete.
+*/
+
+use std::path::{Path, PathBuf};
+
+use super::{data::GameData, recovery::unique_path};
+use crate::helpers::move_recording;
+
+/// used for data that is missing (e.g. no post game stats because the game got interrupted)
+const UNKNOWN: &str = "Unknown";
+/// max length (in characters) of a single value so the whole path stays within the limits of the filesystem
+const MAX_VALUE_LEN: usize = 100;
+
+/// Replaces the placeholders in the filename with the (sanitized) data of the game.
+pub fn fill_placeholders(filename: &str, game_data: &GameData) -> String {
+    let game_info = &game_data.game_info;
+
+    let result = match game_data.win {
+        Some(true) => "Win",
+        Some(false) => "Loss",
+        None => UNKNOWN,
+    };
+    let kda = match game_data.collected_stats() {
+        Some(stats) => format!("{}-{}-{}", stats.kills, stats.deaths, stats.assists),
+        None => String::from(UNKNOWN),
+    };
+
+    let values = [
+        ("{champion}", game_info.champion_name.as_str()),
+        ("{mode}", game_info.game_mode.as_str()),
+        ("{summoner}", game_info.summoner_name.as_str()),
+        ("{result}", result),
+        ("{kda}", kda.as_str()),
+    ];
+
+    // a single pass so placeholders inside the values (e.g. a summoner called "{kda}") stay as they are
+    let mut filled = String::with_capacity(filename.len());
+    let mut rest = filename;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let placeholder = values.iter().find(|(placeholder, _)| rest.starts_with(placeholder));
+        match placeholder {
+            Some((placeholder, value)) => {
+                filled.push_str(&sanitize(value));
+                rest = &rest[placeholder.len()..];
+            }
+            None => {
+                filled.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+/// Renames the recording and its metadata file to the filename with the placeholders filled in (or the next free
+/// name if that one is already taken). Returns the new path of the recording.
+pub fn rename_recording(recording: &Path, game_data: &GameData) -> PathBuf {
+    let Some(filename) = recording.file_name().and_then(|filename| filename.to_str()) else {
+        return recording.to_path_buf();
+    };
+    let filled = fill_placeholders(filename, game_data);
+    if filled == filename {
+        return recording.to_path_buf();
+    }
+
+    let renamed = unique_path(&recording.with_file_name(filled), Path::exists);
+    if let Err(e) = move_recording(recording, &renamed) {
+        log::warn!("unable to rename {recording:?} to {renamed:?}: {e}");
+        return recording.to_path_buf();
+    }
+    log::info!("renamed {recording:?} to {renamed:?}");
+    renamed
+}
+
+/// Replaces the characters that aren't allowed in filenames (on Windows, which is the strictest), shortens overly
+/// long values and makes sure the value isn't a reserved device name on Windows (CON, NUL, COM1, ...).
+pub fn sanitize(value: &str) -> String {
+    let sanitized: String = value
+        .chars()
+        .map(|c| {
+            if c.is_control() || matches!(c, '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*') {
+                '_'
+            } else {
+                c
+            }
+        })
+        .take(MAX_VALUE_LEN)
+        .collect();
+    let sanitized = sanitized.trim().trim_end_matches('.');
+    if sanitized.is_empty() {
+        String::from(UNKNOWN)
+    } else if is_reserved_name(sanitized) {
+        // has to go right after the device name since Windows ignores the extension
+        let (stem, extension) = sanitized.split_at(reserved_name_len(sanitized));
+        format!("{stem}_{extension}")
+    } else {
+        sanitized.to_string()
+    }
+}
+
+/// Windows doesn't allow these names - not even with an extension (e.g. "nul.mp4")
+fn is_reserved_name(name: &str) -> bool {
+    let stem = name[..reserved_name_len(name)].to_ascii_uppercase();
+    if matches!(stem.as_str(), "CON" | "PRN" | "AUX" | "NUL") {
+        return true;
+    }
+    // the superscript digits count as well
+    let number = stem.strip_prefix("COM").or_else(|| stem.strip_prefix("LPT"));
+    matches!(
+        number,
+        Some("1" | "2" | "3" | "4" | "5" | "6" | "7" | "8" | "9" | "¹" | "²" | "³")
+    )
+}
+
+/// length of the part of the name that Windows compares against the device names
+fn reserved_name_len(name: &str) -> usize {
+    let stem = name.split('.').next().unwrap_or_default();
+    stem.trim_end().len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recorder::data::{GameInfo, Stats};
+
+    fn game_data(champion: &str, summoner: &str) -> GameData {
+        GameData {
+            game_info: GameInfo {
+                champion_name: champion.into(),
+                game_mode: "CLASSIC".into(),
+                summoner_name: summoner.into(),
+            },
+            win: Some(true),
+            stats: Stats {
+                kills: 5,
+                deaths: 2,
+                assists: 7,
+                ..Stats::default()
+            },
+            ..GameData::default()
+        }
+    }
+
+    #[test]
+    fn fills_in_the_placeholders() {
+        let filled = fill_placeholders(
+            "{champion}_{mode}_{result}_{kda}_{summoner}.mp4",
+            &game_data("Ahri", "Faker"),
+        );
+        assert_eq!(filled, "Ahri_CLASSIC_Win_5-2-7_Faker.mp4");
+
+        // unknown placeholders and lonely braces stay as they are
+        let filled = fill_placeholders("{date} {champion{champion}}.mp4", &game_data("Ahri", "Faker"));
+        assert_eq!(filled, "{date} {championAhri}.mp4");
+
+        let filled = fill_placeholders("{result}_{kda}", &GameData::default());
+        assert_eq!(filled, "Unknown_Unknown");
+    }
+
+    #[test]
+    fn doesnt_fill_in_placeholders_inside_of_values() {
+        let filled = fill_placeholders("{summoner}_{champion}.mp4", &game_data("Ahri", "{champion}{kda}"));
+        assert_eq!(filled, "{champion}{kda}_Ahri.mp4");
+    }
+
+    #[test]
+    fn sanitizes_values() {
+        assert_eq!(sanitize("Kai'Sa"), "Kai'Sa");
+        assert_eq!(sanitize("a/b\\c:d*e?f\"g<h>i|j\n"), "a_b_c_d_e_f_g_h_i_j_");
+        assert_eq!(sanitize("  name...  "), "name");
+        assert_eq!(sanitize(" . "), UNKNOWN);
+        assert_eq!(sanitize(&"ß".repeat(300)), "ß".repeat(MAX_VALUE_LEN));
+    }
+
+    #[test]
+    fn avoids_reserved_names() {
+        for name in ["CON", "nul", "Aux", "com1", "LPT9", "COM¹"] {
+            assert_eq!(sanitize(name), format!("{name}_"), "{name}");
+        }
+        assert_eq!(sanitize("con.txt"), "con_.txt");
+        assert_eq!(sanitize("NUL .mp4"), "NUL_ .mp4");
+        for name in ["CONSOLE", "NULL", "COM0", "COM10", "LPT", "éé", "Conqueror"] {
+            assert_eq!(sanitize(name), name, "{name}");
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    data::{ClipData, ClipSource, GameData, GameEvent},
+    recovery::unique_path,
+};
+use crate::{helpers::write_metadata, mp4, state::HighlightSettings};
+
+pub const CLIPS_FOLDER: &str = "clips";
+
+/// time range in the recording and the events that happen in it
+struct Highlight {
+    start: f64,
+    end: f64,
+    events: Vec<GameEvent>,
+}
+
+/// Cuts a short clip around every marker of the game and saves it in the `clips` folder next to the recording.
+/// Markers that are close together end up in the same clip.
+pub fn extract_highlights(recording: &Path, game_data: &GameData, settings: &HighlightSettings) -> Vec<PathBuf> {
+    let highlights = collect_highlights(&game_data.events, settings);
+    if highlights.is_empty() {
+        log::info!("no highlights to extract from {recording:?}");
+        return vec![];
+    }
+
+    let (Some(folder), Some(stem)) = (recording.parent(), recording.file_stem()) else {
+        return vec![];
+    };
+    let clips_folder = folder.join(CLIPS_FOLDER);
+    if let Err(e) = fs::create_dir_all(&clips_folder) {
+        log::error!("unable to create clips folder {clips_folder:?}: {e}");
+        return vec![];
+    }
+    let source_file = recording
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    let mut clips = Vec::new();
+    for (i, highlight) in highlights.into_iter().enumerate() {
+        let trigger = highlight.events[0].clone();
+        let clip = clips_folder.join(format!("{}_{:02}_{}.mp4", stem.to_string_lossy(), i + 1, trigger.name));
+        let clip = unique_path(&clip, Path::exists);
+
+        // the clip starts and ends at keyframes so the actual range is a bit larger
+        let range = match mp4::trim(recording, &clip, highlight.start, highlight.end) {
+            Ok(range) => range,
+            Err(e) => {
+                log::error!("failed to cut highlight {clip:?}: {e}");
+                continue;
+            }
+        };
+
+        let clip_data = ClipData {
+            game_data: GameData {
+                win: game_data.win,
+                game_info: game_data.game_info.clone(),
+                stats: game_data.stats.clone(),
+                events: highlight
+                    .events
+                    .iter()
+                    .map(|event| GameEvent {
+                        name: event.name.clone(),
+                        time: event.time - range.start,
+                    })
+                    .collect(),
+                ..Default::default()
+            },
+            offset: trigger.time - range.start,
+            trigger,
+            source: Some(ClipSource {
+                file: source_file.clone(),
+                start: range.start,
+                end: range.end,
+            }),
+        };
+        let result = write_metadata(&clip, &clip_data);
+        log::info!("highlight {clip:?} saved: {result:?}");
+
+        clips.push(clip);
+    }
+    clips
+}
+
+fn collect_highlights(events: &[GameEvent], settings: &HighlightSettings) -> Vec<Highlight> {
+    let before = f64::from(settings.seconds_before);
+    let after = f64::from(settings.seconds_after);
+
+    let mut events: Vec<GameEvent> = events
+        .iter()
+        .filter(|event| settings.events.is_empty() || settings.events.contains(&event.name))
+        .cloned()
+        .collect();
+    events.sort_by(|a, b| a.time.total_cmp(&b.time));
+
+    let mut highlights: Vec<Highlight> = Vec::new();
+    for event in events {
+        let start = (event.time - before).max(0.0);
+        let end = event.time + after;
+        match highlights.last_mut() {
+            // overlapping windows get merged into one clip
+            Some(last) if start <= last.end => {
+                last.end = last.end.max(end);
+                last.events.push(event);
+            }
+            _ => highlights.push(Highlight {
+                start,
+                end,
+                events: vec![event],
+            }),
+        }
+    }
+    highlights
+}
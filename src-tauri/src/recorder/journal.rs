@@ -0,0 +1,183 @@
+/*
+    Everything `collect_ingame_data` learns about a game gets appended to a JSON Lines journal next to the staged
+    recording as soon as it happens, so a cancel, abort or crash doesn't lose the events of the game.
+    At the end of the game the journal gets consolidated into the metadata file of the recording.
+    A journal that is left over from an interrupted game gets consolidated on the next start (see `recovery`).
+*/
+
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::data::{GameData, GameEvent, GameInfo, Stats};
+use crate::mp4;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum JournalEntry {
+    GameInfo(GameInfo),
+    Event(GameEvent),
+    Result { win: bool },
+    Stats(Stats),
+}
+
+pub struct Journal {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl Journal {
+    /// Starts a new journal (replaces an old one with the same path).
+    /// If the file can't be created the entries only get logged and the game data is kept in memory as before.
+    pub fn create(path: PathBuf) -> Self {
+        let file = match File::create(&path) {
+            Ok(file) => Some(file),
+            Err(e) => {
+                log::warn!("unable to create metadata journal {path:?}: {e}");
+                None
+            }
+        };
+        Self { path, file }
+    }
+
+    pub fn append(&mut self, entry: &JournalEntry) {
+        let Some(file) = &mut self.file else {
+            return;
+        };
+
+        let result = serde_json::to_vec(entry).map_err(io::Error::from).and_then(|mut line| {
+            line.push(b'\n');
+            // a single write so a crash can only cut off the last line
+            file.write_all(&line)
+        });
+        if let Err(e) = result {
+            log::warn!("unable to append to metadata journal {:?}: {e}", self.path);
+        }
+    }
+}
+
+/// the journal of the staged recording
+pub fn journal_path(staged: &Path) -> PathBuf {
+    staged.with_extension("jsonl")
+}
+
+/// Replays the entries of the journal. Stops at the first line that can't be read (the line that was being written
+/// when the game got interrupted).
+pub fn consolidate(path: &Path) -> io::Result<GameData> {
+    let journal = fs::read_to_string(path)?;
+
+    let mut game_data = GameData::default();
+    for line in journal.lines() {
+        let entry = match serde_json::from_str(line) {
+            Ok(entry) => entry,
+            Err(e) => {
+                log::warn!("metadata journal {path:?} ends with an unreadable line: {e}");
+                break;
+            }
+        };
+        match entry {
+            JournalEntry::GameInfo(game_info) => game_data.game_info = game_info,
+            JournalEntry::Event(event) => game_data.events.push(event),
+            JournalEntry::Result { win } => game_data.win = Some(win),
+            JournalEntry::Stats(stats) => game_data.stats = stats,
+        }
+    }
+    Ok(game_data)
+}
+
+/// Writes the consolidated journal as the metadata of `recording` and removes the journal.
+/// The journal is only kept if the metadata file couldn't be written.
+pub fn write_consolidated(journal: &Path, recording: &Path) -> Result<GameData, String> {
+    let game_data = consolidate(journal).map_err(|e| format!("unable to read metadata journal: {e}"))?;
+    let json = serde_json::to_vec(&game_data).map_err(|e| e.to_string())?;
+    fs::write(recording.with_extension("json"), &json).map_err(|e| e.to_string())?;
+    _ = fs::remove_file(journal);
+
+    // recordings that couldn't be remuxed (fragmented mp4) can't embed the metadata - the json file is enough
+    if let Err(e) = mp4::write_game_data(recording, &json) {
+        log::warn!("unable to embed metadata into {recording:?}: {e}");
+    }
+    Ok(game_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs::OpenOptions};
+
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("league_record_test_{name}_{}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn event(name: &str, time: f64) -> GameEvent {
+        GameEvent { name: name.into(), time }
+    }
+
+    #[test]
+    fn keeps_every_complete_entry_of_an_interrupted_journal() {
+        let dir = test_dir("journal");
+        let recording = dir.join("game.mp4");
+        fs::write(&recording, "not an mp4").unwrap();
+        let path = journal_path(&recording);
+
+        let mut journal = Journal::create(path.clone());
+        journal.append(&JournalEntry::GameInfo(GameInfo {
+            game_mode: "CLASSIC".into(),
+            summoner_name: "Faker".into(),
+            champion_name: "Ahri".into(),
+        }));
+        journal.append(&JournalEntry::Event(event("Kill", 312.5)));
+        journal.append(&JournalEntry::Event(event("Dragon", 600.0)));
+        journal.append(&JournalEntry::Result { win: true });
+        journal.append(&JournalEntry::Stats(Stats {
+            kills: 5,
+            deaths: 2,
+            assists: 7,
+            ..Stats::default()
+        }));
+        drop(journal);
+
+        // the app crashed while it was writing the next entry
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"kind":"event","name":"Ba"#).unwrap();
+        drop(file);
+
+        let game_data = write_consolidated(&path, &recording).unwrap();
+        let written: GameData = serde_json::from_slice(&fs::read(recording.with_extension("json")).unwrap()).unwrap();
+        for game_data in [game_data, written] {
+            assert_eq!(game_data.game_info.champion_name, "Ahri");
+            assert_eq!(game_data.game_info.summoner_name, "Faker");
+            let events: Vec<(&str, f64)> = game_data.events.iter().map(|e| (e.name.as_str(), e.time)).collect();
+            assert_eq!(events, [("Kill", 312.5), ("Dragon", 600.0)]);
+            assert_eq!(game_data.win, Some(true));
+            assert_eq!(
+                (game_data.stats.kills, game_data.stats.deaths, game_data.stats.assists),
+                (5, 2, 7)
+            );
+        }
+        assert!(!path.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_the_journal_if_the_metadata_cant_be_written() {
+        let dir = test_dir("journal_unwritable");
+        let path = dir.join("game.jsonl");
+        let mut journal = Journal::create(path.clone());
+        journal.append(&JournalEntry::Event(event("Kill", 1.0)));
+        drop(journal);
+
+        // the metadata file would end up in a folder that doesn't exist
+        assert!(write_consolidated(&path, &dir.join("missing/game.mp4")).is_err());
+        assert_eq!(consolidate(&path).unwrap().events.len(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
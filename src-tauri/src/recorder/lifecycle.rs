@@ -0,0 +1,393 @@
+/*
+    The recorder lifecycle as a pure state machine.
+    It doesn't touch windows, recorders, tasks or clocks itself - the recorder thread feeds it events
+    and executes the commands it returns. That way every transition can be driven step by step.
+*/
+
+use std::time::Duration;
+
+/// how long to wait for the EOG lobby (post game stats) after the LoL window closed before canceling the task
+pub const END_OF_GAME_TIMEOUT: Duration = Duration::from_secs(90);
+/// how long a canceled task gets to finish by itself before it is aborted
+pub const CANCEL_TIMEOUT: Duration = Duration::from_secs(5);
+/// how long the task gets to complete a fs::write or sth when LeagueRecord shuts down
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Timer {
+    EndOfGame,
+    Cancel,
+    Shutdown,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecorderEvent {
+    /// the LoL window is currently open (sent on every poll)
+    WindowFound,
+    /// the LoL window is currently closed (sent on every poll)
+    WindowGone,
+    /// the recording session (recorder + ingame task) couldn't be created
+    SessionFailed,
+    /// the game started and the recorder is running
+    GameStarted,
+    /// the recorder was stopped by the ingame task
+    RecordingStopped,
+    /// the ingame task has finished by itself
+    TaskFinished,
+    TimerFired(Timer),
+    ShutdownRequested,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecorderCommand {
+    /// create the recorder for the current window and spawn the ingame task
+    StartSession,
+    SetRecordingTray(bool),
+    /// (re)start the timer - a timer that is already running gets replaced
+    StartTimer(Timer, Duration),
+    ClearTimer(Timer),
+    /// ask the ingame task to stop via its cancellation token
+    CancelTask,
+    /// forcefully abort the ingame task
+    AbortTask,
+    /// stop tracking the ingame task and clean it up in the background
+    /// (wait `END_OF_GAME_TIMEOUT` for it, then cancel it and abort it after `CANCEL_TIMEOUT`)
+    DetachTask,
+    /// stop the recorder thread
+    Exit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PostGamePhase {
+    /// the task is done but the LoL window is still open
+    TaskDone,
+    /// the LoL window is closed, the task is still waiting for the post game stats
+    WaitingForStats,
+    /// the task got canceled and has `CANCEL_TIMEOUT` to finish
+    Cancelling,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecorderState {
+    Idle,
+    WaitingForGame,
+    Recording,
+    PostGame(PostGamePhase),
+    ShuttingDown,
+}
+
+#[derive(Debug)]
+pub struct RecorderStateMachine {
+    state: RecorderState,
+    tray_recording: bool,
+}
+
+impl Default for RecorderStateMachine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RecorderStateMachine {
+    pub fn new() -> Self {
+        Self {
+            state: RecorderState::Idle,
+            tray_recording: false,
+        }
+    }
+
+    pub fn state(&self) -> RecorderState {
+        self.state
+    }
+
+    pub fn handle(&mut self, event: RecorderEvent) -> Vec<RecorderCommand> {
+        use PostGamePhase::*;
+        use RecorderCommand::*;
+        use RecorderEvent::*;
+        use RecorderState::*;
+
+        let mut commands = Vec::new();
+
+        // the tray only depends on the recorder and not on the state
+        if event == RecordingStopped {
+            self.set_tray(&mut commands, false);
+            return commands;
+        }
+
+        let next_state = match (self.state, event) {
+            (ShuttingDown, TaskFinished) => {
+                commands.extend([ClearTimer(Timer::Shutdown), Exit]);
+                self.set_tray(&mut commands, false);
+                ShuttingDown
+            }
+            (ShuttingDown, TimerFired(Timer::Shutdown)) => {
+                commands.extend([AbortTask, Exit]);
+                self.set_tray(&mut commands, false);
+                ShuttingDown
+            }
+            (ShuttingDown, _) => ShuttingDown,
+
+            (state, ShutdownRequested) => {
+                commands.extend([ClearTimer(Timer::EndOfGame), ClearTimer(Timer::Cancel)]);
+                if matches!(state, Idle | PostGame(TaskDone)) {
+                    commands.push(Exit);
+                } else {
+                    commands.extend([CancelTask, StartTimer(Timer::Shutdown, SHUTDOWN_TIMEOUT)]);
+                }
+                ShuttingDown
+            }
+
+            (Idle, WindowFound) => {
+                commands.push(StartSession);
+                WaitingForGame
+            }
+            (Idle, _) => Idle,
+
+            (WaitingForGame, SessionFailed) => Idle,
+            (WaitingForGame, GameStarted) => {
+                self.set_tray(&mut commands, true);
+                Recording
+            }
+            (WaitingForGame | Recording, WindowGone) => {
+                commands.push(StartTimer(Timer::EndOfGame, END_OF_GAME_TIMEOUT));
+                PostGame(WaitingForStats)
+            }
+            (WaitingForGame | Recording, TaskFinished) => {
+                self.set_tray(&mut commands, false);
+                PostGame(TaskDone)
+            }
+            (state @ (WaitingForGame | Recording), _) => state,
+
+            (PostGame(TaskDone), WindowGone) => Idle,
+            (PostGame(WaitingForStats), TaskFinished) => {
+                commands.push(ClearTimer(Timer::EndOfGame));
+                self.set_tray(&mut commands, false);
+                Idle
+            }
+            (PostGame(WaitingForStats), TimerFired(Timer::EndOfGame)) => {
+                commands.extend([CancelTask, StartTimer(Timer::Cancel, CANCEL_TIMEOUT)]);
+                PostGame(Cancelling)
+            }
+            (PostGame(Cancelling), TaskFinished) => {
+                commands.push(ClearTimer(Timer::Cancel));
+                self.set_tray(&mut commands, false);
+                Idle
+            }
+            (PostGame(Cancelling), TimerFired(Timer::Cancel)) => {
+                commands.push(AbortTask);
+                self.set_tray(&mut commands, false);
+                Idle
+            }
+            // the next game started before the task of the last one finished (e.g. the EOG lobby got skipped)
+            // => the new game must not wait for the old task so it gets cleaned up in the background
+            (PostGame(WaitingForStats | Cancelling), WindowFound) => {
+                commands.extend([ClearTimer(Timer::EndOfGame), ClearTimer(Timer::Cancel), DetachTask]);
+                self.set_tray(&mut commands, false);
+                commands.push(StartSession);
+                WaitingForGame
+            }
+            (state @ PostGame(_), _) => state,
+        };
+
+        if next_state != self.state {
+            log::info!("recorder state: {:?} -> {:?} ({event:?})", self.state, next_state);
+        }
+        self.state = next_state;
+        commands
+    }
+
+    fn set_tray(&mut self, commands: &mut Vec<RecorderCommand>, recording: bool) {
+        if self.tray_recording != recording {
+            self.tray_recording = recording;
+            commands.push(RecorderCommand::SetRecordingTray(recording));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use PostGamePhase::*;
+    use RecorderCommand::*;
+    use RecorderEvent::*;
+    use RecorderState::*;
+
+    const ALL_EVENTS: [RecorderEvent; 10] = [
+        WindowFound,
+        WindowGone,
+        SessionFailed,
+        GameStarted,
+        RecordingStopped,
+        TaskFinished,
+        TimerFired(Timer::EndOfGame),
+        TimerFired(Timer::Cancel),
+        TimerFired(Timer::Shutdown),
+        ShutdownRequested,
+    ];
+
+    const SHUTDOWN_NOW: &[RecorderCommand] = &[ClearTimer(Timer::EndOfGame), ClearTimer(Timer::Cancel), Exit];
+    const SHUTDOWN_TASK: &[RecorderCommand] = &[
+        ClearTimer(Timer::EndOfGame),
+        ClearTimer(Timer::Cancel),
+        CancelTask,
+        StartTimer(Timer::Shutdown, SHUTDOWN_TIMEOUT),
+    ];
+    const NEXT_GAME: &[RecorderCommand] = &[
+        ClearTimer(Timer::EndOfGame),
+        ClearTimer(Timer::Cancel),
+        DetachTask,
+        SetRecordingTray(false),
+        StartSession,
+    ];
+
+    /// every transition that changes the state or returns commands
+    /// all other events leave the state unchanged and only turn off the tray (RecordingStopped) if it is on
+    const TRANSITIONS: &[(RecorderState, RecorderEvent, RecorderState, &[RecorderCommand])] = &[
+        (Idle, WindowFound, WaitingForGame, &[StartSession]),
+        (Idle, ShutdownRequested, ShuttingDown, SHUTDOWN_NOW),
+        (WaitingForGame, SessionFailed, Idle, &[]),
+        (WaitingForGame, GameStarted, Recording, &[SetRecordingTray(true)]),
+        (
+            WaitingForGame,
+            WindowGone,
+            PostGame(WaitingForStats),
+            &[StartTimer(Timer::EndOfGame, END_OF_GAME_TIMEOUT)],
+        ),
+        (WaitingForGame, TaskFinished, PostGame(TaskDone), &[]),
+        (WaitingForGame, ShutdownRequested, ShuttingDown, SHUTDOWN_TASK),
+        (
+            Recording,
+            WindowGone,
+            PostGame(WaitingForStats),
+            &[StartTimer(Timer::EndOfGame, END_OF_GAME_TIMEOUT)],
+        ),
+        (Recording, TaskFinished, PostGame(TaskDone), &[SetRecordingTray(false)]),
+        (Recording, ShutdownRequested, ShuttingDown, SHUTDOWN_TASK),
+        (PostGame(TaskDone), WindowGone, Idle, &[]),
+        (PostGame(TaskDone), ShutdownRequested, ShuttingDown, SHUTDOWN_NOW),
+        (
+            PostGame(WaitingForStats),
+            TaskFinished,
+            Idle,
+            &[ClearTimer(Timer::EndOfGame), SetRecordingTray(false)],
+        ),
+        (
+            PostGame(WaitingForStats),
+            TimerFired(Timer::EndOfGame),
+            PostGame(Cancelling),
+            &[CancelTask, StartTimer(Timer::Cancel, CANCEL_TIMEOUT)],
+        ),
+        (PostGame(WaitingForStats), WindowFound, WaitingForGame, NEXT_GAME),
+        (
+            PostGame(WaitingForStats),
+            ShutdownRequested,
+            ShuttingDown,
+            SHUTDOWN_TASK,
+        ),
+        (
+            PostGame(Cancelling),
+            TaskFinished,
+            Idle,
+            &[ClearTimer(Timer::Cancel), SetRecordingTray(false)],
+        ),
+        (
+            PostGame(Cancelling),
+            TimerFired(Timer::Cancel),
+            Idle,
+            &[AbortTask, SetRecordingTray(false)],
+        ),
+        (PostGame(Cancelling), WindowFound, WaitingForGame, NEXT_GAME),
+        (PostGame(Cancelling), ShutdownRequested, ShuttingDown, SHUTDOWN_TASK),
+        (
+            ShuttingDown,
+            TaskFinished,
+            ShuttingDown,
+            &[ClearTimer(Timer::Shutdown), Exit, SetRecordingTray(false)],
+        ),
+        (
+            ShuttingDown,
+            TimerFired(Timer::Shutdown),
+            ShuttingDown,
+            &[AbortTask, Exit, SetRecordingTray(false)],
+        ),
+    ];
+
+    /// a state machine in `state` reached the usual way (with the tray on while a game is being recorded)
+    fn machine_in(state: RecorderState) -> RecorderStateMachine {
+        let events: &[RecorderEvent] = match state {
+            Idle => &[],
+            WaitingForGame => &[WindowFound],
+            Recording => &[WindowFound, GameStarted],
+            PostGame(TaskDone) => &[WindowFound, GameStarted, TaskFinished],
+            PostGame(WaitingForStats) => &[WindowFound, GameStarted, WindowGone],
+            PostGame(Cancelling) => &[WindowFound, GameStarted, WindowGone, TimerFired(Timer::EndOfGame)],
+            ShuttingDown => &[WindowFound, GameStarted, ShutdownRequested],
+        };
+
+        let mut machine = RecorderStateMachine::new();
+        for event in events {
+            machine.handle(*event);
+        }
+        assert_eq!(machine.state(), state);
+        machine
+    }
+
+    #[test]
+    fn every_event_in_every_state() {
+        let states = [
+            Idle,
+            WaitingForGame,
+            Recording,
+            PostGame(TaskDone),
+            PostGame(WaitingForStats),
+            PostGame(Cancelling),
+            ShuttingDown,
+        ];
+
+        for state in states {
+            for event in ALL_EVENTS {
+                let mut machine = machine_in(state);
+                let tray_recording = machine.tray_recording;
+                let commands = machine.handle(event);
+
+                let (expected_state, expected_commands) = TRANSITIONS
+                    .iter()
+                    .find(|(from, on, _, _)| *from == state && *on == event)
+                    .map(|(_, _, to, commands)| (*to, commands.to_vec()))
+                    .unwrap_or_else(|| match event {
+                        RecordingStopped if tray_recording => (state, vec![SetRecordingTray(false)]),
+                        _ => (state, vec![]),
+                    });
+                assert_eq!(machine.state(), expected_state, "{state:?} + {event:?}");
+                assert_eq!(commands, expected_commands, "{state:?} + {event:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn next_game_while_waiting_for_the_last_one() {
+        let mut machine = machine_in(PostGame(WaitingForStats));
+
+        // no EOG lobby - the next game starts right away and gets recorded while the old task is cleaned up
+        assert_eq!(machine.handle(WindowFound), NEXT_GAME);
+        assert_eq!(machine.handle(GameStarted), [SetRecordingTray(true)]);
+        assert_eq!(machine.state(), Recording);
+
+        assert_eq!(machine.handle(TaskFinished), [SetRecordingTray(false)]);
+        assert_eq!(machine.handle(WindowGone), []);
+        assert_eq!(machine.state(), Idle);
+    }
+
+    #[test]
+    fn next_game_that_fails_to_start() {
+        let mut machine = machine_in(PostGame(Cancelling));
+
+        assert_eq!(machine.handle(WindowFound), NEXT_GAME);
+        assert_eq!(machine.handle(SessionFailed), []);
+        assert_eq!(machine.state(), Idle);
+
+        // the old task doesn't belong to the machine anymore => its timers can't do anything
+        assert_eq!(machine.handle(TimerFired(Timer::Cancel)), []);
+        assert_eq!(machine.handle(WindowFound), [StartSession]);
+    }
+}
@@ -0,0 +1,161 @@
+/*
+    Upgrades metadata json files from older versions of LeagueRecord to the current `SCHEMA_VERSION`.
+    `MIGRATIONS[n]` turns version n into version n + 1, so a file runs through every migration after its own version.
+*/
+
+use std::{
+    error::Error,
+    fmt::{self, Display},
+};
+
+use serde_json::{Map, Value};
+
+use super::data::SCHEMA_VERSION;
+
+type Migration = fn(&mut Map<String, Value>);
+
+const MIGRATIONS: [Migration; SCHEMA_VERSION as usize] = [unversioned_to_v1, add_annotations, add_tags_and_flags];
+
+#[derive(Debug)]
+pub enum MigrationError {
+    NotAnObject,
+    /// the file was written by a newer version of LeagueRecord
+    NewerVersion(u64),
+}
+
+impl Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::NotAnObject => write!(f, "metadata is not a json object"),
+            MigrationError::NewerVersion(version) => {
+                write!(
+                    f,
+                    "metadata version {version} is newer than the supported version {SCHEMA_VERSION}"
+                )
+            }
+        }
+    }
+}
+
+impl Error for MigrationError {}
+
+/// the version of the metadata - files from before there was a version are version 0
+pub fn schema_version(metadata: &Value) -> u64 {
+    metadata.get("schemaVersion").and_then(Value::as_u64).unwrap_or(0)
+}
+
+/// Upgrades the metadata to the current version in place. Returns if anything had to be upgraded.
+pub fn migrate(metadata: &mut Value) -> Result<bool, MigrationError> {
+    let version = schema_version(metadata);
+    let Value::Object(metadata) = metadata else {
+        return Err(MigrationError::NotAnObject);
+    };
+    if version > u64::from(SCHEMA_VERSION) {
+        return Err(MigrationError::NewerVersion(version));
+    }
+
+    for (from, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(metadata);
+        metadata.insert("schemaVersion".into(), Value::from(from + 1));
+    }
+    Ok(version < u64::from(SCHEMA_VERSION))
+}
+
+/// The files from before the schema version existed have the same layout as version 1 but not every tool that
+/// wrote them included all fields.
+fn unversioned_to_v1(metadata: &mut Map<String, Value>) {
+    metadata.entry("win").or_insert(Value::Null);
+    metadata.entry("events").or_insert_with(|| Value::Array(Vec::new()));
+}
+
+/// version 2 added the user annotations
+fn add_annotations(metadata: &mut Map<String, Value>) {
+    metadata
+        .entry("annotations")
+        .or_insert_with(|| Value::Array(Vec::new()));
+}
+
+/// version 3 added the user defined tags and the favorite and protected flags
+fn add_tags_and_flags(metadata: &mut Map<String, Value>) {
+    metadata.entry("tags").or_insert_with(|| Value::Array(Vec::new()));
+    metadata.entry("favorite").or_insert(Value::Bool(false));
+    metadata.entry("protected").or_insert(Value::Bool(false));
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn migrates_unversioned_metadata() {
+        let mut metadata = json!({
+            "gameInfo": { "gameMode": "CLASSIC", "summonerName": "Faker", "championName": "Ahri" },
+            "stats": { "kills": 5, "deaths": 2, "assists": 7 }
+        });
+        assert!(migrate(&mut metadata).unwrap());
+        assert_eq!(
+            metadata,
+            json!({
+                "schemaVersion": 3,
+                "gameInfo": { "gameMode": "CLASSIC", "summonerName": "Faker", "championName": "Ahri" },
+                "stats": { "kills": 5, "deaths": 2, "assists": 7 },
+                "win": null,
+                "events": [],
+                "annotations": [],
+                "tags": [],
+                "favorite": false,
+                "protected": false
+            })
+        );
+    }
+
+    #[test]
+    fn migrates_from_an_older_version() {
+        let mut metadata = json!({
+            "schemaVersion": 1,
+            "win": true,
+            "events": [{ "name": "Kill", "time": 100.0 }]
+        });
+        assert!(migrate(&mut metadata).unwrap());
+        assert_eq!(
+            metadata,
+            json!({
+                "schemaVersion": 3,
+                "win": true,
+                "events": [{ "name": "Kill", "time": 100.0 }],
+                "annotations": [],
+                "tags": [],
+                "favorite": false,
+                "protected": false
+            })
+        );
+    }
+
+    #[test]
+    fn keeps_current_metadata_as_it_is() {
+        let current = json!({
+            "schemaVersion": SCHEMA_VERSION,
+            "win": false,
+            "events": [],
+            "annotations": [{ "id": 1, "time": 10.0, "text": "nice" }],
+            "tags": ["ranked"],
+            "favorite": true,
+            "protected": true
+        });
+        let mut metadata = current.clone();
+        assert!(!migrate(&mut metadata).unwrap());
+        assert_eq!(metadata, current);
+    }
+
+    #[test]
+    fn rejects_newer_versions_and_non_objects() {
+        let mut metadata = json!({ "schemaVersion": 4 });
+        assert!(matches!(migrate(&mut metadata), Err(MigrationError::NewerVersion(4))));
+        assert_eq!(metadata, json!({ "schemaVersion": 4 }));
+
+        let mut metadata = json!([{ "schemaVersion": 1 }]);
+        assert!(matches!(migrate(&mut metadata), Err(MigrationError::NotAnObject)));
+    }
+}
@@ -1,16 +1,14 @@
 use std::{
     cmp::Ordering,
+    collections::{HashMap, VecDeque},
     path::{Path, PathBuf},
-    sync::mpsc::{channel, RecvTimeoutError},
+    sync::mpsc::{channel, RecvTimeoutError, Sender},
     thread,
     time::{Duration, Instant},
 };
 
+use self::settings::{Resolution, Size};
 use futures_util::StreamExt;
-use libobs_recorder::{
-    settings::{RateControl, Resolution, Size, Window},
-    Recorder, RecorderSettings,
-};
 use shaco::{
     ingame::{EventStream, IngameClient},
     model::{
@@ -24,24 +22,46 @@ use tauri::{
     async_runtime::{self, JoinHandle},
     AppHandle, Manager,
 };
-use tokio::time::timeout;
+use tokio::{
+    sync::mpsc::{unbounded_channel, UnboundedReceiver},
+    time::timeout,
+};
 use tokio_util::sync::CancellationToken;
+#[cfg(target_os = "windows")]
 use windows::Win32::UI::HiDpi::{
     GetAwarenessFromDpiAwarenessContext, GetDpiFromDpiAwarenessContext, GetThreadDpiAwarenessContext,
     SetThreadDpiAwarenessContext, DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE,
 };
-#[cfg(target_os = "windows")]
-use windows::{
-    core::PCSTR,
-    Win32::{
-        Foundation::{HWND, RECT},
-        UI::WindowsAndMessaging::{FindWindowA, GetClientRect},
-    },
-};
 
-use crate::{helpers::set_recording_tray_item, state::Settings};
+use self::{
+    backend::{create_backend, BackendSettings, RecordingBackend},
+    disk::{DiskSpaceGuard, DISK_CHECK_INTERVAL},
+    highlights::extract_highlights,
+    journal::{journal_path, Journal, JournalEntry},
+    lifecycle::{RecorderCommand, RecorderEvent, RecorderStateMachine, Timer, CANCEL_TIMEOUT, END_OF_GAME_TIMEOUT},
+    recovery::{BrokenRecordings, FinalizeError},
+    replay::ReplayClipper,
+    window::{GameWindowLocator, WindowHandle},
+};
+use crate::{
+    helpers::{self, set_clip_tray_item, set_recording_tray_item, write_metadata},
+    layout,
+    mp4::{self, Chapter},
+    state::{HighlightSettings, ReplayBufferSettings, Settings},
+};
 
-mod data;
+pub mod backend;
+pub mod data;
+pub mod disk;
+pub mod filename;
+pub mod highlights;
+pub mod journal;
+pub mod lifecycle;
+pub mod migrations;
+pub mod recovery;
+mod replay;
+pub mod settings;
+pub mod window;
 
 const WINDOW_TITLE: &str = "League of Legends (TM) Client";
 const WINDOW_CLASS: &str = "RiotWindowClass";
@@ -68,13 +88,303 @@ fn closest_resolution_to_size(window_size: &Size) -> Resolution {
     aspect_ratios.first().unwrap().0
 }
 
+const WINDOW_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+enum RecorderMessage {
+    Event(RecorderEvent),
+    /// events from an ingame task are tagged with the id of the session they belong to
+    /// so a late event from an old session can't mess with the current one
+    Session(u64, RecorderEvent),
+}
+
+/// handed to the ingame task to report back to the recorder thread
+struct SessionEvents {
+    id: u64,
+    tx: Sender<RecorderMessage>,
+}
+
+impl SessionEvents {
+    fn send(&self, event: RecorderEvent) {
+        _ = self.tx.send(RecorderMessage::Session(self.id, event));
+    }
+}
+
+/// the settings the ingame task needs - read once when the session gets created
+struct SessionSettings {
+    replay_buffer: ReplayBufferSettings,
+    highlights: HighlightSettings,
+    mp4_chapters: bool,
+    folder_layout: String,
+    disk_guard: DiskSpaceGuard,
+}
+
+struct Session {
+    id: u64,
+    handle: JoinHandle<()>,
+    cancel_token: CancellationToken,
+    /// clips can be saved while the game is being recorded
+    replay_buffer: bool,
+}
+
+/// wait for the task to finish by itself, cancel it after `END_OF_GAME_TIMEOUT` and abort it after `CANCEL_TIMEOUT`
+async fn clean_up_task(mut session: Session) {
+    if timeout(END_OF_GAME_TIMEOUT, &mut session.handle).await.is_ok() {
+        return;
+    }
+    session.cancel_token.cancel();
+    if timeout(CANCEL_TIMEOUT, &mut session.handle).await.is_err() {
+        log::warn!("aborting ingame task of session {}", session.id);
+        session.handle.abort();
+    }
+}
+
+/// Executes the commands of the `RecorderStateMachine` and keeps track of everything it doesn't own itself
+/// (the ingame task, timers, the LoL window)
+struct RecorderDriver {
+    app_handle: AppHandle,
+    machine: RecorderStateMachine,
+    window_locator: Box<dyn GameWindowLocator>,
+    /// the LoL window found by the last poll
+    window: Option<WindowHandle>,
+    tx: Sender<RecorderMessage>,
+    timers: HashMap<Timer, Instant>,
+    session: Option<Session>,
+    next_session_id: u64,
+    exit: bool,
+}
+
+impl RecorderDriver {
+    fn dispatch(&mut self, event: RecorderEvent) {
+        let mut events = VecDeque::from([event]);
+        while let Some(event) = events.pop_front() {
+            for command in self.machine.handle(event) {
+                match command {
+                    RecorderCommand::StartSession => {
+                        if !self.start_session() {
+                            events.push_back(RecorderEvent::SessionFailed);
+                        }
+                    }
+                    RecorderCommand::SetRecordingTray(recording) => {
+                        set_recording_tray_item(&self.app_handle, recording);
+                        let replay_buffer = self.session.as_ref().is_some_and(|session| session.replay_buffer);
+                        set_clip_tray_item(&self.app_handle, recording && replay_buffer);
+                    }
+                    RecorderCommand::StartTimer(timer, duration) => {
+                        self.timers.insert(timer, Instant::now() + duration);
+                    }
+                    RecorderCommand::ClearTimer(timer) => {
+                        self.timers.remove(&timer);
+                    }
+                    RecorderCommand::CancelTask => {
+                        if let Some(session) = &self.session {
+                            session.cancel_token.cancel();
+                        }
+                    }
+                    RecorderCommand::AbortTask => {
+                        if let Some(session) = self.session.take() {
+                            log::warn!("aborting ingame task");
+                            session.handle.abort();
+                        }
+                    }
+                    RecorderCommand::DetachTask => {
+                        if let Some(session) = self.session.take() {
+                            log::info!("cleaning up ingame task of session {} in the background", session.id);
+                            async_runtime::spawn(clean_up_task(session));
+                        }
+                    }
+                    RecorderCommand::Exit => self.exit = true,
+                }
+            }
+        }
+    }
+
+    fn receive(&mut self, message: RecorderMessage) {
+        match message {
+            RecorderMessage::Event(event) => self.dispatch(event),
+            RecorderMessage::Session(id, event) => {
+                if !self.session.as_ref().is_some_and(|session| session.id == id) {
+                    log::info!("ignoring event from old session {id}: {event:?}");
+                    return;
+                }
+                if event == RecorderEvent::TaskFinished {
+                    self.session = None;
+                }
+                self.dispatch(event);
+            }
+        }
+    }
+
+    fn fire_expired_timers(&mut self) {
+        let now = Instant::now();
+        let expired: Vec<Timer> = self
+            .timers
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(timer, _)| *timer)
+            .collect();
+        for timer in expired {
+            self.timers.remove(&timer);
+            self.dispatch(RecorderEvent::TimerFired(timer));
+        }
+    }
+
+    /// wait for the next message until either the next window poll or the next timer is due
+    fn next_timeout(&self) -> Duration {
+        let now = Instant::now();
+        self.timers
+            .values()
+            .map(|deadline| deadline.saturating_duration_since(now))
+            .fold(WINDOW_POLL_INTERVAL, Duration::min)
+    }
+
+    fn poll_window(&mut self) {
+        self.window = self.window_locator.find_window();
+        let event = if self.window.is_some() {
+            RecorderEvent::WindowFound
+        } else {
+            RecorderEvent::WindowGone
+        };
+        self.dispatch(event);
+    }
+
+    /// create the recorder for the LoL window and spawn the ingame data task
+    fn start_session(&mut self) -> bool {
+        let Some(window_handle) = self.window else {
+            return false;
+        };
+
+        log::info!("LoL Window found");
+
+        let Ok(window_size) = self.window_locator.client_size(window_handle) else {
+            log::error!("unable to get window size of League of Legends.exe");
+            return false;
+        };
+        let Ok(window_position) = self.window_locator.client_position(window_handle) else {
+            log::error!("unable to get window position of League of Legends.exe");
+            return false;
+        };
+
+        let settings_state = self.app_handle.state::<Settings>();
+
+        // either get the explicitly set resolution or choose the default resolution for the LoL window aspect ratio
+        let output_resolution = settings_state
+            .get_output_resolution()
+            .unwrap_or_else(|| closest_resolution_to_size(&window_size));
+
+        log::info!("Using resolution ({output_resolution:?}) for window ({window_size:?})");
+
+        let backend_kind = settings_state.get_recording_backend();
+        let mut replay_buffer = settings_state.get_replay_buffer();
+        if replay_buffer.enabled && !backend_kind.supports_replay_buffer() {
+            log::warn!("the {backend_kind:?} backend has no replay buffer - recording the whole game instead");
+            replay_buffer.enabled = false;
+        }
+        let replay_buffer_length = replay_buffer.enabled.then_some(replay_buffer.length);
+
+        let mut filename_path = settings_state.get_recordings_path();
+        filename_path.push(format!(
+            "{}",
+            chrono::Local::now().format(&settings_state.get_filename_format())
+        ));
+        // never overwrite an existing recording (e.g. two games within the same minute)
+        let filename_path = recovery::unique_recording_path(&filename_path);
+
+        // the recording only gets its final name (filename_path) once it is finished
+        let staged_path = match recovery::prepare_staging(&filename_path) {
+            Ok(staged_path) => staged_path,
+            Err(e) => {
+                log::error!("unable to create staging folder for {filename_path:?}: {e}");
+                return false;
+            }
+        };
+
+        let backend_settings = BackendSettings {
+            window_size,
+            window_position,
+            output_resolution,
+            framerate: settings_state.get_framerate(),
+            encoding_quality: settings_state.get_encoding_quality(),
+            audio_source: settings_state.get_audio_source(),
+            output_path: staged_path,
+            debug_log: settings_state.debug_log(),
+            ffmpeg_source: settings_state.get_ffmpeg_source(),
+            replay_buffer_length,
+        };
+        let recorder = match create_backend(backend_kind, backend_settings) {
+            Ok(recorder) => recorder,
+            Err(e) => {
+                log::error!("failed to create recording backend: {e}");
+                return false;
+            }
+        };
+
+        // --- ingame data collection ---
+        let session_settings = SessionSettings {
+            replay_buffer,
+            highlights: settings_state.get_highlights(),
+            mp4_chapters: settings_state.mp4_chapters(),
+            folder_layout: settings_state.get_folder_layout(),
+            disk_guard: DiskSpaceGuard::new(
+                self.app_handle.clone(),
+                settings_state.get_recordings_path(),
+                settings_state.min_free_space_gb(),
+            ),
+        };
+
+        let id = self.next_session_id;
+        self.next_session_id += 1;
+
+        // manual clip requests (e.g. from the tray menu) for the replay buffer
+        let (clip_tx, clip_rx) = unbounded_channel();
+        let clip_listener = self
+            .app_handle
+            .listen_global("save_replay_clip", move |_| _ = clip_tx.send(()));
+
+        let cancel_token = CancellationToken::new();
+        let handle = async_runtime::spawn({
+            // preparation for task
+            let app_handle = self.app_handle.clone();
+            let events = SessionEvents { id, tx: self.tx.clone() };
+            let cancel_subtoken = cancel_token.child_token();
+            let mut outfile = settings_state.get_recordings_path().join(filename_path);
+            outfile.set_extension("json");
+
+            // actual task
+            async move {
+                collect_ingame_data(
+                    &app_handle,
+                    &events,
+                    cancel_subtoken,
+                    recorder,
+                    outfile,
+                    session_settings,
+                    clip_rx,
+                )
+                .await;
+                app_handle.unlisten(clip_listener);
+                app_handle.trigger_global("recording_finished", None);
+                events.send(RecorderEvent::TaskFinished);
+            }
+        });
+        log::info!("ingame task spawned: {handle:?}");
+
+        self.session = Some(Session {
+            id,
+            handle,
+            cancel_token,
+            replay_buffer: replay_buffer_length.is_some(),
+        });
+        true
+    }
+}
+
 pub fn start(app_handle: &AppHandle) {
     let app_handle = app_handle.clone();
 
     thread::spawn(move || {
         #[cfg(target_os = "windows")]
         unsafe {
-            // Get correct window size from get_lol_window() / GetClientRect
+            // Get correct window size from GetClientRect
             let result = SetThreadDpiAwarenessContext(DPI_AWARENESS_CONTEXT_PER_MONITOR_AWARE);
             let dpi_awareness_context = GetThreadDpiAwarenessContext();
             log::info!(
@@ -87,165 +397,39 @@ pub fn start(app_handle: &AppHandle) {
         };
 
         // send stop to channel on "shutdown" event
-        let (tx, rx) = channel::<_>();
-        app_handle.once_global("shutdown_recorder", move |_| _ = tx.send(()));
-
-        enum State {
-            Idle,
-            Recording((JoinHandle<()>, CancellationToken)),
-        }
-
-        let mut state = State::Idle;
-
-        'recorder: loop {
-            match state {
-                State::Idle => 'inner: {
-                    // --- initialize recorder if LoL window is found ---
-                    let Some(window_handle) = get_lol_window() else {
-                        break 'inner;
-                    };
-
-                    log::info!("LoL Window found");
-
-                    let Ok(window_size) = get_window_size(window_handle) else {
-                        log::error!("unable to get window size of League of Legends.exe");
-                        break 'inner;
-                    };
-
-                    let settings_state = app_handle.state::<Settings>();
-
-                    // either get the explicitly set resolution or choose the default resolution for the LoL window aspect ratio
-                    let output_resolution = settings_state
-                        .get_output_resolution()
-                        .unwrap_or_else(|| closest_resolution_to_size(&window_size));
-
-                    log::info!("Using resolution ({output_resolution:?}) for window ({window_size:?})");
-
-                    let mut filename_path = settings_state.get_recordings_path();
-                    filename_path.push(format!(
-                        "{}",
-                        chrono::Local::now().format(&settings_state.get_filename_format())
-                    ));
-
-                    let mut settings = RecorderSettings::new();
-                    settings.set_window(Window::new(
-                        WINDOW_TITLE,
-                        Some(WINDOW_CLASS.into()),
-                        Some(WINDOW_PROCESS.into()),
-                    ));
-                    settings.set_input_resolution(window_size);
-                    settings.set_output_resolution(output_resolution);
-                    settings.set_framerate(settings_state.get_framerate());
-                    settings.set_rate_control(RateControl::CQP(settings_state.get_encoding_quality()));
-                    settings.record_audio(settings_state.get_audio_source());
-                    settings.set_output_path(filename_path.to_str().expect("error converting filename path to &str"));
-
-                    // if LeagueRecord gets launched by Windows Autostart the CWD is system32 instead of the installation folder
-                    // get directory to current executable so we can locate extprocess_recorder.exe
-                    let exe_dir = match std::env::current_exe()
-                        .ok()
-                        .and_then(|exe| exe.parent().map(|a| a.to_path_buf()))
-                    {
-                        Some(exe_dir) => {
-                            log::info!("executable directory: {:?}", exe_dir);
-                            exe_dir
-                        }
-                        None => {
-                            log::warn!("unable to get executable directory - trying relative path instead");
-                            PathBuf::from("./")
-                        }
-                    };
-                    let mut recorder = match Recorder::new_with_paths(
-                        Some(exe_dir.join(Path::new("libobs/extprocess_recorder.exe")).as_path()),
-                        None,
-                        None,
-                        None,
-                        settings_state.debug_log(),
-                    ) {
-                        Ok(rec) => rec,
-                        Err(e) => {
-                            log::error!("failed to create recorder: {e}");
-                            break 'inner;
-                        }
-                    };
-
-                    let configured = recorder.configure(&settings);
-                    log::info!("recorder configured: {configured:?}");
-                    log::info!("Available encoders: {:?}", recorder.available_encoders());
-                    log::info!("Selected encoder: {:?}", recorder.selected_encoder());
-                    if configured.is_err() {
-                        break 'inner;
-                    }
-
-                    // --- ingame data collection ---
-                    let cancel_token = CancellationToken::new();
-                    let handle = async_runtime::spawn({
-                        // preparation for task
-                        let app_handle = app_handle.clone();
-                        let cancel_subtoken = cancel_token.child_token();
-                        let mut outfile = settings_state.get_recordings_path().join(filename_path);
-                        outfile.set_extension("json");
-
-                        // actual task
-                        async move { collect_ingame_data(app_handle, cancel_subtoken, recorder, outfile).await }
-                    });
-                    log::info!("ingame task spawned: {handle:?}");
-
-                    state = State::Recording((handle, cancel_token));
-                }
-                State::Recording((mut handle, cancel_token)) => 'inner: {
-                    // don't stop while LoL window is open
-                    if get_lol_window().is_some() {
-                        state = State::Recording((handle, cancel_token));
-                        break 'inner;
-                    } else {
-                        state = State::Idle;
-                    }
-
-                    // if task already finished -> done
-                    if handle.inner().is_finished() {
-                        break 'inner;
-                    }
+        let (tx, rx) = channel::<RecorderMessage>();
+        app_handle.once_global("shutdown_recorder", {
+            let tx = tx.clone();
+            move |_| _ = tx.send(RecorderMessage::Event(RecorderEvent::ShutdownRequested))
+        });
 
-                    // spawn async thread to cleanup the gamedata task
-                    async_runtime::spawn(async move {
-                        // wait for 90s for EOG lobby before trying to cancel the task
-                        if timeout(Duration::from_secs(90), &mut handle).await.is_ok() {
-                            return;
-                        } else {
-                            cancel_token.cancel();
-                        }
+        let mut driver = RecorderDriver {
+            app_handle: app_handle.clone(),
+            machine: RecorderStateMachine::new(),
+            window_locator: window::default_locator(),
+            window: None,
+            tx,
+            timers: HashMap::new(),
+            session: None,
+            next_session_id: 0,
+            exit: false,
+        };
 
-                        // abort task if the cancel_token didn't stop the it (after 5s)
-                        if timeout(Duration::from_secs(5), &mut handle).await.is_err() {
-                            handle.abort();
-                        }
-                    });
-                }
+        let mut last_window_poll: Option<Instant> = None;
+        while !driver.exit {
+            if !last_window_poll.is_some_and(|last_poll| last_poll.elapsed() < WINDOW_POLL_INTERVAL) {
+                last_window_poll = Some(Instant::now());
+                driver.poll_window();
             }
 
-            // break if stop event received or sender disconnected
-            match rx.recv_timeout(Duration::from_secs(1)) {
-                Ok(_) | Err(RecvTimeoutError::Disconnected) => {
-                    // cleanup the gamedata task if it doesn't exit by itself
-                    if let State::Recording((handle, cancel_token)) = state {
-                        if !handle.inner().is_finished() {
-                            cancel_token.cancel();
-
-                            // give the task a little bit of time to complete a fs::write or sth
-                            std::thread::sleep(Duration::from_millis(500));
-
-                            // if it is still not finished -> abort
-                            if !handle.inner().is_finished() {
-                                handle.abort();
-                            }
-                        }
-                    }
-
-                    break 'recorder;
-                }
+            match rx.recv_timeout(driver.next_timeout()) {
+                Ok(message) => driver.receive(message),
                 Err(RecvTimeoutError::Timeout) => {}
+                // can't happen since the driver holds a sender itself
+                Err(RecvTimeoutError::Disconnected) => driver.dispatch(RecorderEvent::ShutdownRequested),
             }
+
+            driver.fire_expired_timers();
         }
 
         app_handle.trigger_global("recorder_shutdown", None);
@@ -253,12 +437,31 @@ pub fn start(app_handle: &AppHandle) {
     });
 }
 
+/// Remembers a recording that got moved to the broken folder until the UI shows it and tells the UI about it.
+pub fn report_broken_recording(app_handle: &AppHandle, broken: &Path) {
+    let rec_folder = app_handle.state::<Settings>().get_recordings_path();
+    let name = helpers::recording_name(&rec_folder, broken).unwrap_or_else(|| broken.to_string_lossy().into_owned());
+    app_handle.state::<BrokenRecordings>().push(name);
+    _ = app_handle.emit_all("recording_broken", ());
+}
+
 async fn collect_ingame_data(
-    app_handle: AppHandle,
+    app_handle: &AppHandle,
+    events: &SessionEvents,
     cancel_subtoken: CancellationToken,
-    mut recorder: Recorder,
+    mut recorder: Box<dyn RecordingBackend>,
     outfile: PathBuf,
+    settings: SessionSettings,
+    mut clip_requests: UnboundedReceiver<()>,
 ) {
+    let SessionSettings {
+        replay_buffer,
+        highlights,
+        mp4_chapters,
+        folder_layout,
+        disk_guard,
+    } = settings;
+
     // IngameClient::new() never actually returns Err()
     let ingame_client = IngameClient::new().unwrap();
 
@@ -321,6 +524,13 @@ async fn collect_ingame_data(
 
     log::info!("initial data parsed: {game_data:?}");
 
+    if disk_guard.is_low(false) {
+        log::warn!("not enough free disk space - not recording this game");
+        let shutdown = recorder.shutdown();
+        log::info!("recorder shutdown: {shutdown:?}");
+        return;
+    }
+
     // if initial game_data is successful => start recording
     let start_recording = recorder.start_recording();
     log::info!("start recording: {start_recording:?}");
@@ -331,12 +541,11 @@ async fn collect_ingame_data(
         let shutdown = recorder.shutdown();
         log::error!("recording start failed - stop recording: {stop_recording:?}");
         log::info!("recorder shutdown: {shutdown:?}");
-        set_recording_tray_item(&app_handle, false);
         return;
     }
 
     let recording_start = Instant::now();
-    set_recording_tray_item(&app_handle, true);
+    events.send(RecorderEvent::GameStarted);
 
     // prepare LcuWebsocketClient subscription for post game stats
     // if we do this after the ingame window closes we could technically miss the event
@@ -358,10 +567,57 @@ async fn collect_ingame_data(
 
     log::info!("Starting EventStream - listening to ingame events");
 
+    // in replay buffer mode clips get saved during the game instead of recording the whole game
+    let mut replay_clipper = recorder
+        .replay_buffer_active()
+        .then(|| ReplayClipper::new(replay_buffer, &outfile, recording_start));
+    let replay_mode = replay_clipper.is_some();
+
+    let recording = outfile.with_extension("mp4");
+    let staged = recovery::staging_path(&recording);
+    // in replay buffer mode there is no recording the metadata could belong to
+    let mut journal = (!replay_mode).then(|| Journal::create(journal_path(&staged)));
+    if let Some(journal) = &mut journal {
+        journal.append(&JournalEntry::GameInfo(game_data.game_info.clone()));
+    }
+
+    // the free space was just checked so the first check is due after one interval
+    let mut disk_check = tokio::time::interval_at((Instant::now() + DISK_CHECK_INTERVAL).into(), DISK_CHECK_INTERVAL);
+
     let mut ingame_events = EventStream::from_ingame_client(ingame_client, None);
-    while let Some(event) =
-        tokio::select! { event = ingame_events.next() => event, _ = cancel_subtoken.cancelled() => None }
-    {
+    loop {
+        let next_clip_save = replay_clipper.as_ref().and_then(ReplayClipper::next_save);
+        let event = tokio::select! {
+            event = ingame_events.next() => event,
+            _ = cancel_subtoken.cancelled() => None,
+            Some(()) = clip_requests.recv() => {
+                match &mut replay_clipper {
+                    Some(clipper) => {
+                        clipper.on_manual_trigger();
+                        clipper.save_pending_clip(recorder.as_mut(), &game_data.game_info);
+                    }
+                    None => log::info!("replay buffer is disabled - ignoring clip request"),
+                }
+                continue;
+            }
+            _ = tokio::time::sleep_until(next_clip_save.unwrap_or_else(Instant::now).into()), if next_clip_save.is_some() => {
+                if let Some(clipper) = &mut replay_clipper {
+                    clipper.save_pending_clip(recorder.as_mut(), &game_data.game_info);
+                }
+                continue;
+            }
+            _ = disk_check.tick(), if disk_guard.enabled() => {
+                if disk_guard.is_low(true) {
+                    log::warn!("not enough free disk space - stopping the recording early");
+                    break;
+                }
+                continue;
+            }
+        };
+        let Some(event) = event else {
+            break;
+        };
+
         let time = recording_start.elapsed().as_secs_f64();
         log::info!("[{}]: {:?}", time, event);
 
@@ -392,10 +648,11 @@ async fn collect_ingame_data(
                 Some(dragon)
             }
             GameEvent::GameEnd(e) => {
-                game_data.win = match e.result {
-                    GameResult::Win => Some(true),
-                    GameResult::Lose => Some(false),
-                };
+                let win = matches!(e.result, GameResult::Win);
+                game_data.win = Some(win);
+                if let Some(journal) = &mut journal {
+                    journal.append(&JournalEntry::Result { win });
+                }
                 None
             }
             GameEvent::HordeKill(_) => Some("Voidgrub"),
@@ -406,17 +663,43 @@ async fn collect_ingame_data(
         };
 
         if let Some(name) = event_name {
-            game_data.events.push(data::GameEvent { name, time })
+            let event = data::GameEvent { name: name.to_string(), time };
+            if let Some(clipper) = &mut replay_clipper {
+                clipper.on_event(event.clone());
+            }
+            if let Some(journal) = &mut journal {
+                journal.append(&JournalEntry::Event(event.clone()));
+            }
+            game_data.events.push(event);
         }
     }
 
-    log::info!("Ingame window has closed");
+    log::info!("Ingame window has closed or recording stopped early");
+
+    // don't lose a clip that was triggered right before the end of the game
+    if let Some(clipper) = &mut replay_clipper {
+        clipper.save_pending_clip(recorder.as_mut(), &game_data.game_info);
+    }
 
     let stopped = recorder.stop_recording();
     let shutdown = recorder.shutdown();
     log::info!("recorder shutdown: {shutdown:?}");
     log::info!("recorder stopped: {stopped:?}");
-    set_recording_tray_item(&app_handle, false);
+    events.send(RecorderEvent::RecordingStopped);
+
+    // where the recording ended up (there is none in replay buffer mode)
+    let finalized = if replay_mode {
+        None
+    } else {
+        let finalized = async_runtime::spawn_blocking(move || recovery::finalize(&staged, &recording))
+            .await
+            .unwrap_or_else(|e| Err(FinalizeError::Failed(e.to_string())));
+        log::info!("recording finalized: {finalized:?}");
+        if let Err(FinalizeError::Broken(broken)) = &finalized {
+            report_broken_recording(app_handle, broken);
+        }
+        Some(finalized)
+    };
 
     log::info!("waiting for post game stats");
 
@@ -439,10 +722,16 @@ async fn collect_ingame_data(
                         } else if !json_stats["LOSE"].is_null() {
                             game_data.win = Some(false);
                         }
+                        if let (Some(journal), Some(win)) = (&mut journal, game_data.win) {
+                            journal.append(&JournalEntry::Result { win });
+                        }
                     }
 
-                    match serde_json::from_value(json_stats) {
+                    match serde_json::from_value::<data::Stats>(json_stats) {
                         Ok(stats) => {
+                            if let Some(journal) = &mut journal {
+                                journal.append(&JournalEntry::Stats(stats.clone()));
+                            }
                             game_data.stats = stats;
                             log::info!("collected post game stats successfully");
                         }
@@ -455,42 +744,79 @@ async fn collect_ingame_data(
         }
     }
 
-    async_runtime::spawn_blocking(move || {
-        log::info!("writing game metadata to file: {outfile:?}");
-
-        // serde_json requires a std::fs::File
-        if let Ok(file) = std::fs::File::create(&outfile) {
-            let result = serde_json::to_writer(file, &game_data);
-            log::info!("metadata saved: {result:?}");
+    // there is no recording of the whole game the metadata could belong to
+    let (Some(journal), Some(finalized)) = (journal, finalized) else {
+        log::info!("replay buffer mode - not writing game metadata");
+        return;
+    };
+    let recording = match finalized {
+        Ok(recording) => recording,
+        Err(FinalizeError::Broken(broken)) => {
+            // the metadata can still help to find out what happened
+            drop(journal);
+            let written = async_runtime::spawn_blocking(move || recovery::write_broken_metadata(&broken)).await;
+            if let Err(e) = written {
+                log::error!("writing the game metadata failed: {e}");
+            }
+            return;
         }
-    });
-}
+        Err(e) => {
+            // a failed recording gets another try on the next start - together with its journal
+            log::warn!("not writing game metadata - the recording couldn't be finalized: {e}");
+            return;
+        }
+    };
 
-#[cfg(target_os = "windows")]
-fn get_lol_window() -> Option<HWND> {
-    let mut window_title = WINDOW_TITLE.to_owned();
-    window_title.push('\0'); // null terminate
-    let mut window_class = WINDOW_CLASS.to_owned();
-    window_class.push('\0'); // null terminate
+    // the recording only counts as finished (e.g. for the retention settings) once its metadata is written
+    let finished = async_runtime::spawn_blocking(move || {
+        log::info!("writing game metadata to file: {:?}", recording.with_extension("json"));
+        drop(journal);
+        let journal_file = journal_path(&recovery::staging_path(&recording));
+        let game_data = match journal::write_consolidated(&journal_file, &recording) {
+            Ok(game_data) => game_data,
+            Err(e) => {
+                // the journal couldn't be written or read - the data in memory is just as complete
+                log::warn!("unable to consolidate metadata journal {journal_file:?}: {e}");
+                let result = write_metadata(&recording, &game_data);
+                log::info!("metadata saved: {result:?}");
+                game_data
+            }
+        };
+        let recording = filename::rename_recording(&recording, &game_data);
+        // the finished recording is still directly in the recordings folder
+        let recording = match recording.parent() {
+            Some(rec_folder) => layout::place(rec_folder, &recording, &folder_layout, &game_data),
+            None => recording,
+        };
 
-    let title = PCSTR(window_title.as_ptr());
-    let class = PCSTR(window_class.as_ptr());
+        if mp4_chapters {
+            let result = mp4::write_chapters(&recording, &game_chapters(&game_data));
+            log::info!("chapters written to recording: {result:?}");
+        }
 
-    let hwnd = unsafe { FindWindowA(class, title) };
-    if hwnd.0 == 0 {
-        None
-    } else {
-        Some(hwnd)
+        if highlights.enabled {
+            let clips = extract_highlights(&recording, &game_data, &highlights);
+            log::info!("extracted {} highlight clips", clips.len());
+        }
+    })
+    .await;
+    if let Err(e) = finished {
+        log::error!("writing the game metadata failed: {e}");
     }
 }
 
-#[cfg(target_os = "windows")]
-fn get_window_size(hwnd: HWND) -> Result<Size, ()> {
-    let mut rect = RECT::default();
-    unsafe { GetClientRect(hwnd, &mut rect as _) }.map_err(|_| ())?;
-    if rect.right > 0 && rect.bottom > 0 {
-        Ok(Size::new(rect.right as u32, rect.bottom as u32))
-    } else {
-        Err(())
+/// a chapter for every event so external players show the markers as well
+fn game_chapters(game_data: &data::GameData) -> Vec<Chapter> {
+    let mut chapters = Vec::with_capacity(game_data.events.len() + 1);
+    if game_data.events.iter().all(|event| event.time > 0.0) {
+        chapters.push(Chapter {
+            start: 0.0,
+            title: String::from("Game Start"),
+        });
     }
+    chapters.extend(game_data.events.iter().map(|event| Chapter {
+        start: event.time,
+        title: event.name.to_string(),
+    }));
+    chapters
 }
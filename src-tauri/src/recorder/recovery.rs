@@ -0,0 +1,412 @@
+/*
+    Recordings are written into the hidden `.recording` folder inside the recordings folder and only get moved next
+    to the other recordings once they are finalized.
+    Both backends write fragmented mp4 while recording, so everything up to the last complete fragment stays playable
+    when the recording gets interrupted. libobs picks the muxer by the file extension, which is why its recordings are
+    staged as .ismv (ffmpeg's fragmented mp4 muxer) and only get their .mp4 extension when libobs is done with them.
+    Finalizing turns the fragmented file into a regular mp4 file (see `mp4::defragment`).
+    Everything that is still in that folder at startup belongs to a recording that got interrupted (LeagueRecord or
+    extprocess_recorder crashed, the ingame task got aborted, ...) and gets finalized with whatever was captured
+    (the video and the metadata journal).
+    Recordings that can't be repaired (a regular mp4 that never got its index) get moved to the visible `broken`
+    folder so they don't get retried on every start and the user can decide what to do with them.
+*/
+
+use std::{
+    fmt::{self, Display},
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use super::{
+    backend,
+    data::GameData,
+    disk,
+    filename::rename_recording,
+    journal::{journal_path, write_consolidated},
+};
+use crate::{helpers::write_metadata, layout, mp4};
+
+pub const STAGING_FOLDER: &str = ".recording";
+pub const BROKEN_FOLDER: &str = "broken";
+/// extension of the recordings libobs writes into the staging folder (makes it pick the fragmented mp4 muxer)
+pub const FRAGMENTED_EXTENSION: &str = "ismv";
+/// free space that has to be left over after remuxing a fragmented recording (in bytes)
+const REMUX_RESERVE: u64 = 200 * 1024 * 1024;
+
+#[derive(Debug, PartialEq, Eq)]
+enum Layout {
+    /// nothing got written
+    Empty,
+    /// not an mp4 file (e.g. the placeholder of the fake backend) - gets moved as is
+    NotMp4,
+    /// regular mp4 with a moov box
+    Complete,
+    /// fragmented mp4 - everything up to `valid_len` is playable
+    Fragmented { valid_len: u64 },
+    /// regular mp4 that never got its moov box - can't be repaired without an index
+    Unfinished,
+}
+
+#[derive(Debug)]
+pub enum FinalizeError {
+    /// nothing got recorded - the staged files got removed
+    Empty,
+    /// the recording can't be repaired and got moved to the broken folder (with its metadata journal)
+    Broken(PathBuf),
+    /// the staged recording is still there and gets another try on the next start
+    Failed(String),
+}
+
+impl Display for FinalizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FinalizeError::Empty => write!(f, "nothing was recorded"),
+            FinalizeError::Broken(broken) => write!(f, "the recording can't be repaired - moved it to {broken:?}"),
+            FinalizeError::Failed(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+/// The recordings that got moved to the broken folder since the UI last asked for them.
+#[derive(Debug, Default)]
+pub struct BrokenRecordings(Mutex<Vec<String>>);
+
+impl BrokenRecordings {
+    pub fn push(&self, recording: String) {
+        self.0.lock().unwrap().push(recording);
+    }
+
+    pub fn take(&self) -> Vec<String> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+/// Where a recording gets written to while it is recorded. Creates the staging folder if it doesn't exist yet.
+pub fn prepare_staging(recording: &Path) -> io::Result<PathBuf> {
+    let staged = staging_path(recording);
+    if let Some(folder) = staged.parent() {
+        fs::create_dir_all(folder)?;
+    }
+    Ok(staged)
+}
+
+pub fn staging_path(recording: &Path) -> PathBuf {
+    recording
+        .with_file_name(STAGING_FOLDER)
+        .join(recording.file_name().unwrap_or_default())
+}
+
+/// `recording` or - if a finished or staged recording already has that name - the first free `{name}_{n}.mp4`
+pub fn unique_recording_path(recording: &Path) -> PathBuf {
+    unique_path(recording, |path| {
+        let staged = staging_path(path);
+        path.exists() || staged.exists() || staged.with_extension(FRAGMENTED_EXTENSION).exists()
+    })
+}
+
+/// Moves the staged recording to `recording` (or the next free name if another recording got that name in the
+/// meantime) and returns where it ended up. Fragmented recordings get turned into a regular mp4 file on the way.
+pub fn finalize(staged: &Path, recording: &Path) -> Result<PathBuf, FinalizeError> {
+    let failed = |e: io::Error| FinalizeError::Failed(e.to_string());
+    let layout = layout(staged).map_err(|e| FinalizeError::Failed(format!("unable to read {staged:?}: {e}")))?;
+    let recording = unique_path(recording, Path::exists);
+    match layout {
+        Layout::Empty => {
+            _ = fs::remove_file(staged);
+            _ = fs::remove_file(journal_path(staged));
+            return Err(FinalizeError::Empty);
+        }
+        Layout::Unfinished => {
+            // the mp4 has no moov box (the index of the samples) which only gets written when the recording stops
+            let broken = move_to_broken_folder(staged, &recording).map_err(failed)?;
+            return Err(FinalizeError::Broken(broken));
+        }
+        Layout::NotMp4 | Layout::Complete => fs::rename(staged, &recording).map_err(failed)?,
+        Layout::Fragmented { valid_len } => {
+            // drop the fragment that was only partially written when the recording got interrupted
+            let file = File::options().write(true).open(staged).map_err(failed)?;
+            if file.metadata().map_err(failed)?.len() > valid_len {
+                log::info!("cutting off incomplete fragment of {staged:?} at {valid_len}");
+                file.set_len(valid_len).map_err(failed)?;
+            }
+            drop(file);
+
+            // defragmenting writes a complete copy of the recording before the fragmented one gets removed
+            let remuxed = match staging_free_space(staged) {
+                Some(free) if free < valid_len + REMUX_RESERVE => Err(format!(
+                    "only {free} bytes of free space for a copy of {valid_len} bytes"
+                )),
+                _ => mp4::defragment(staged, &recording).or_else(|e| {
+                    log::warn!("unable to defragment {staged:?} - remuxing it with ffmpeg instead: {e}");
+                    backend::remux(staged, &recording).map_err(|e| e.to_string())
+                }),
+            };
+            match remuxed {
+                Ok(()) => _ = fs::remove_file(staged),
+                Err(e) => {
+                    // a fragmented mp4 still plays fine - it just can't be edited (chapters, clips, ...)
+                    log::warn!("unable to remux {staged:?} - keeping the fragmented recording: {e}");
+                    _ = fs::remove_file(&recording);
+                    fs::rename(staged, &recording).map_err(failed)?;
+                }
+            }
+        }
+    }
+
+    // the journal has to keep the name of the recording it belongs to
+    let journal = journal_path(staged);
+    let renamed_journal = journal_path(&staging_path(&recording));
+    if journal != renamed_journal && journal.exists() {
+        if let Err(e) = fs::rename(&journal, &renamed_journal) {
+            log::warn!("unable to rename metadata journal {journal:?}: {e}");
+        }
+    }
+    Ok(recording)
+}
+
+/// Moves a recording that can't be repaired and its metadata journal into the broken folder next to `recording`.
+fn move_to_broken_folder(staged: &Path, recording: &Path) -> io::Result<PathBuf> {
+    let folder = recording.with_file_name(BROKEN_FOLDER);
+    fs::create_dir_all(&folder)?;
+    let broken = unique_path(&folder.join(recording.file_name().unwrap_or_default()), |path| {
+        path.exists() || journal_path(path).exists()
+    });
+    fs::rename(staged, &broken)?;
+
+    let journal = journal_path(staged);
+    if journal.exists() {
+        if let Err(e) = fs::rename(&journal, journal_path(&broken)) {
+            log::warn!("unable to move metadata journal {journal:?} to the broken folder: {e}");
+        }
+    }
+    log::warn!("{staged:?} can't be repaired - moved it to {broken:?}");
+    Ok(broken)
+}
+
+/// Turns the metadata journal of a recording in the broken folder into its metadata file (if there is a journal).
+pub fn write_broken_metadata(broken: &Path) {
+    let journal = journal_path(broken);
+    if !journal.exists() {
+        return;
+    }
+    if let Err(e) = write_consolidated(&journal, broken) {
+        log::warn!("unable to write the metadata of {broken:?}: {e}");
+    }
+}
+
+/// free space on the drive of the staging folder - `None` if it can't be determined
+fn staging_free_space(staged: &Path) -> Option<u64> {
+    let folder = staged.parent()?;
+    match disk::free_space(folder) {
+        Ok(free) => Some(free),
+        Err(e) => {
+            log::warn!("unable to check the free space in {folder:?}: {e}");
+            None
+        }
+    }
+}
+
+/// Finalizes the recordings and metadata journals that are left in the staging folder from an earlier run and moves
+/// them into the folder layout. Blocks until everything is recovered - the recorder must only be started afterwards
+/// so this doesn't pick up the recording of a new game. `on_broken` gets called for every recording that got moved to
+/// the broken folder.
+pub fn recover_interrupted(rec_folder: &Path, folder_layout: &str, on_broken: impl Fn(&Path)) {
+    let staging = rec_folder.join(STAGING_FOLDER);
+    let Ok(entries) = fs::read_dir(&staging) else {
+        return;
+    };
+    let (staged, journals): (Vec<PathBuf>, Vec<PathBuf>) = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|ext| ext == "mp4" || ext == FRAGMENTED_EXTENSION || ext == "jsonl")
+        })
+        .partition(|path| path.extension().is_some_and(|ext| ext != "jsonl"));
+    if staged.is_empty() && journals.is_empty() {
+        return;
+    }
+
+    for staged in staged {
+        log::info!("recovering interrupted recording {staged:?}");
+        let recording = rec_folder
+            .join(staged.file_name().unwrap_or_default())
+            .with_extension("mp4");
+        match finalize(&staged, &recording) {
+            Ok(recording) => {
+                let journal = journal_path(&staging_path(&recording));
+                let result = if journal.exists() {
+                    write_consolidated(&journal, &recording)
+                } else {
+                    // at least an empty metadata file like for a finished game
+                    let game_data = GameData::default();
+                    write_metadata(&recording, &game_data).map(|_| game_data)
+                };
+                match result {
+                    Ok(game_data) => {
+                        let recording = rename_recording(&recording, &game_data);
+                        let recording = layout::place(rec_folder, &recording, folder_layout, &game_data);
+                        log::info!("recovered {recording:?}");
+                    }
+                    Err(e) => log::warn!("recovered {recording:?} without metadata: {e}"),
+                }
+            }
+            Err(FinalizeError::Broken(broken)) => {
+                write_broken_metadata(&broken);
+                on_broken(&broken);
+            }
+            Err(e) => log::error!("unable to recover {staged:?}: {e}"),
+        }
+    }
+
+    // journals of games that got interrupted after the recording was already finalized
+    // (e.g. while waiting for the post game stats)
+    for journal in journals {
+        // already consolidated above or the recording couldn't be recovered
+        if !journal.exists() || journal.with_extension("mp4").exists() {
+            continue;
+        }
+        let recording = rec_folder
+            .join(journal.file_name().unwrap_or_default())
+            .with_extension("mp4");
+        if !recording.exists() {
+            log::warn!("not recovering metadata journal {journal:?} - {recording:?} doesn't exist");
+            continue;
+        }
+        match write_consolidated(&journal, &recording) {
+            Ok(game_data) => {
+                let recording = rename_recording(&recording, &game_data);
+                let recording = layout::place(rec_folder, &recording, folder_layout, &game_data);
+                log::info!("recovered metadata journal {journal:?} of {recording:?}");
+            }
+            Err(e) => log::warn!("unable to recover metadata journal {journal:?}: {e}"),
+        }
+    }
+
+    // only succeeds if everything got recovered
+    _ = fs::remove_dir(&staging);
+}
+
+/// `path` or the first `{name}_{n}.{extension}` (starting at 2) that isn't `taken`
+pub fn unique_path(path: &Path, taken: impl Fn(&Path) -> bool) -> PathBuf {
+    if !taken(path) {
+        return path.to_path_buf();
+    }
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    let mut n = 2;
+    loop {
+        let candidate = path.with_file_name(format!("{stem}_{n}{extension}"));
+        if !taken(&candidate) {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+fn layout(path: &Path) -> Result<Layout, mp4::Mp4Error> {
+    let mut file = File::open(path)?;
+    if file.metadata()?.len() == 0 {
+        return Ok(Layout::Empty);
+    }
+
+    let (boxes, cut_off) = mp4::scan_top_level_boxes(&mut file)?;
+    let first = boxes.first().or(cut_off.as_ref());
+    if first.is_none_or(|location| &location.kind != b"ftyp") {
+        return Ok(Layout::NotMp4);
+    }
+
+    if let Some(first_moof) = boxes.iter().find(|location| &location.kind == b"moof") {
+        // a fragment is only complete with its media data
+        let valid_len = boxes
+            .iter()
+            .rev()
+            .find(|location| &location.kind == b"mdat")
+            .map_or(first_moof.offset, |mdat| mdat.offset + mdat.size);
+        Ok(Layout::Fragmented { valid_len })
+    } else if boxes.iter().any(|location| &location.kind == b"moov") {
+        Ok(Layout::Complete)
+    } else {
+        Ok(Layout::Unfinished)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::recorder::journal::{Journal, JournalEntry};
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("league_record_test_{name}_{}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn mp4_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    #[test]
+    fn moves_unfinished_recordings_to_the_broken_folder() {
+        let rec_folder = test_dir("recovery_broken");
+        let recording = rec_folder.join("game.mp4");
+        let staged = prepare_staging(&recording).unwrap();
+        // a regular mp4 only gets its moov box when the recording stops
+        fs::write(
+            &staged,
+            [mp4_box(b"ftyp", b"isom"), mp4_box(b"mdat", &[0; 64])].concat(),
+        )
+        .unwrap();
+        let mut journal = Journal::create(journal_path(&staged));
+        journal.append(&JournalEntry::Result { win: true });
+
+        // a broken recording with the same name from an earlier game
+        fs::create_dir_all(rec_folder.join(BROKEN_FOLDER)).unwrap();
+        fs::write(rec_folder.join(BROKEN_FOLDER).join("game.mp4"), "").unwrap();
+
+        let broken = match finalize(&staged, &recording) {
+            Err(FinalizeError::Broken(broken)) => broken,
+            result => panic!("unexpected result {result:?}"),
+        };
+        assert_eq!(broken, rec_folder.join(BROKEN_FOLDER).join("game_2.mp4"));
+        assert!(broken.exists() && !staged.exists() && !recording.exists());
+
+        // entries that get appended after the journal got moved still end up in the metadata
+        journal.append(&JournalEntry::Result { win: false });
+        drop(journal);
+        write_broken_metadata(&broken);
+        let metadata: GameData = serde_json::from_slice(&fs::read(broken.with_extension("json")).unwrap()).unwrap();
+        assert_eq!(metadata.win, Some(false));
+        assert!(!journal_path(&broken).exists());
+
+        fs::remove_dir_all(rec_folder).unwrap();
+    }
+
+    #[test]
+    fn removes_empty_recordings() {
+        let rec_folder = test_dir("recovery_empty");
+        let recording = rec_folder.join("game.mp4");
+        let staged = prepare_staging(&recording).unwrap();
+        fs::write(&staged, "").unwrap();
+        drop(Journal::create(journal_path(&staged)));
+
+        assert!(matches!(finalize(&staged, &recording), Err(FinalizeError::Empty)));
+        assert!(!staged.exists() && !journal_path(&staged).exists() && !recording.exists());
+        assert!(!rec_folder.join(BROKEN_FOLDER).exists());
+
+        fs::remove_dir_all(rec_folder).unwrap();
+    }
+}
@@ -0,0 +1,211 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use super::{
+    backend::RecordingBackend,
+    data::{ClipData, GameData, GameEvent, GameInfo},
+    filename::fill_placeholders,
+};
+use crate::state::ReplayBufferSettings;
+
+/// LoL counts kills as a multikill if they are at most 10s apart
+const MULTIKILL_WINDOW: f64 = 10.0;
+
+struct PendingClip {
+    save_at: Instant,
+    /// all events that happened since the clip got triggered (times relative to the recording start)
+    events: Vec<GameEvent>,
+}
+
+/// Decides when the replay buffer gets saved to a clip and writes the metadata for each clip
+pub struct ReplayClipper {
+    settings: ReplayBufferSettings,
+    /// path of the recording without extension - clips get named `{base_path}_clip{n}.mp4`
+    base_path: PathBuf,
+    recording_start: Instant,
+    pending: Option<PendingClip>,
+    clip_count: u32,
+    last_kill: Option<f64>,
+}
+
+impl ReplayClipper {
+    pub fn new(settings: ReplayBufferSettings, recording_path: &Path, recording_start: Instant) -> Self {
+        Self {
+            settings,
+            base_path: recording_path.with_extension(""),
+            recording_start,
+            pending: None,
+            clip_count: 0,
+            last_kill: None,
+        }
+    }
+
+    /// register an event from the ingame API and schedule a clip if it is one of the trigger events
+    pub fn on_event(&mut self, event: GameEvent) {
+        let time = event.time;
+        let is_kill = event.name == "Kill";
+        let mut events = vec![event];
+        if is_kill {
+            if self
+                .last_kill
+                .is_some_and(|last_kill| time - last_kill <= MULTIKILL_WINDOW)
+            {
+                events.push(GameEvent { name: "Multikill".into(), time });
+            }
+            self.last_kill = Some(time);
+        }
+
+        for event in events {
+            if self.settings.trigger_events.contains(&event.name) {
+                // waiting longer than the buffer is long would push the trigger event out of the clip
+                let delay = self.settings.seconds_after_event.min(self.settings.length);
+                self.trigger(event, Duration::from_secs(delay.into()));
+            } else if let Some(pending) = &mut self.pending {
+                // not a trigger but it still is part of the clip
+                pending.events.push(event);
+            }
+        }
+    }
+
+    /// save the clip right away (e.g. from the tray menu)
+    pub fn on_manual_trigger(&mut self) {
+        let event = GameEvent {
+            name: "Manual".into(),
+            time: self.recording_start.elapsed().as_secs_f64(),
+        };
+        self.trigger(event, Duration::ZERO);
+    }
+
+    fn trigger(&mut self, event: GameEvent, delay: Duration) {
+        match &mut self.pending {
+            // multiple events in quick succession end up in the same clip
+            Some(pending) => pending.events.push(event),
+            None => {
+                log::info!("replay clip triggered by {event:?}");
+                self.pending = Some(PendingClip {
+                    save_at: Instant::now() + delay,
+                    events: vec![event],
+                });
+            }
+        }
+    }
+
+    pub fn next_save(&self) -> Option<Instant> {
+        self.pending.as_ref().map(|pending| pending.save_at)
+    }
+
+    pub fn save_pending_clip(&mut self, recorder: &mut dyn RecordingBackend, game_info: &GameInfo) {
+        let Some(pending) = self.pending.take() else {
+            return;
+        };
+
+        // the clip has its own metadata so the placeholders of the filename format get filled in right away
+        let game_data = GameData {
+            game_info: game_info.clone(),
+            ..Default::default()
+        };
+        self.clip_count += 1;
+        let mut clip_path = self.base_path.clone().into_os_string();
+        clip_path.push(format!("_clip{}.mp4", self.clip_count));
+        let clip_path = PathBuf::from(clip_path);
+        let clip_path = match clip_path.file_name().and_then(|filename| filename.to_str()) {
+            Some(filename) => clip_path.with_file_name(fill_placeholders(filename, &game_data)),
+            None => clip_path,
+        };
+
+        if let Err(e) = recorder.save_replay_buffer(&clip_path) {
+            log::error!("failed to save replay buffer to {clip_path:?}: {e}");
+            return;
+        }
+
+        // the clip contains (roughly) the last `length` seconds before it got saved
+        let save_time = self.recording_start.elapsed().as_secs_f64();
+        let clip_start = (save_time - f64::from(self.settings.length)).max(0.0);
+        let trigger = pending.events[0].clone();
+
+        let clip_data = ClipData {
+            game_data: GameData {
+                events: pending
+                    .events
+                    .iter()
+                    .filter(|event| event.time >= clip_start)
+                    .map(|event| GameEvent {
+                        name: event.name.clone(),
+                        time: event.time - clip_start,
+                    })
+                    .collect(),
+                ..game_data
+            },
+            // the trigger can still be slightly before the start of the buffer (e.g. the save took a moment)
+            offset: (trigger.time - clip_start).max(0.0),
+            trigger,
+            source: None,
+        };
+
+        let metadata_path = clip_path.with_extension("json");
+        log::info!("writing clip metadata to file: {metadata_path:?}");
+        let result = serde_json::to_string(&clip_data)
+            .map_err(|e| e.to_string())
+            .and_then(|json| std::fs::write(&metadata_path, json).map_err(|e| e.to_string()));
+        log::info!("clip metadata saved: {result:?}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+    use crate::recorder::{backend::FakeBackend, data::ClipData};
+
+    fn settings(length: u32, seconds_after_event: u32) -> ReplayBufferSettings {
+        ReplayBufferSettings {
+            enabled: true,
+            length,
+            seconds_after_event,
+            trigger_events: vec!["Kill".into()],
+        }
+    }
+
+    fn kill(time: f64) -> GameEvent {
+        GameEvent { name: "Kill".into(), time }
+    }
+
+    #[test]
+    fn waits_at_most_the_length_of_the_buffer() {
+        let before = Instant::now();
+        let mut clipper = ReplayClipper::new(settings(10, 30), Path::new("game.mp4"), before);
+        clipper.on_event(kill(0.0));
+
+        let save_at = clipper.next_save().unwrap();
+        assert!(save_at >= before + Duration::from_secs(10));
+        assert!(save_at <= Instant::now() + Duration::from_secs(10));
+    }
+
+    #[test]
+    fn offset_is_never_negative() {
+        let dir = env::temp_dir().join(format!("league_record_test_replay_{}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let recording = dir.join("game.mp4");
+
+        // the game has been recorded for 100s and the buffer only holds the last 10s
+        let recording_start = Instant::now() - Duration::from_secs(100);
+        let mut backend = FakeBackend::new(recording.clone(), Some(10));
+        backend.start_recording().unwrap();
+        let mut clipper = ReplayClipper::new(settings(10, 5), &recording, recording_start);
+
+        // the kill is older than the buffer
+        clipper.on_event(kill(80.0));
+        clipper.save_pending_clip(&mut backend, &GameInfo::default());
+
+        let clip: ClipData = serde_json::from_str(&fs::read_to_string(dir.join("game_clip1.json")).unwrap()).unwrap();
+        assert_eq!(clip.trigger.time, 80.0);
+        assert_eq!(clip.offset, 0.0);
+        assert!(clip.game_data.events.is_empty());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,138 @@
+/*
+    The capture settings of a recording (window size, output resolution, framerate, audio).
+    libobs-recorder only builds on Windows, so the rest of the app uses these types and the libobs backend converts
+    them into the libobs types. They (de)serialize the same way as the libobs types so the settings file stays the same.
+*/
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Size {
+    width: u32,
+    height: u32,
+}
+
+impl Size {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+/// position of the top left corner on the screen
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Position {
+    x: i32,
+    y: i32,
+}
+
+impl Position {
+    pub fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    pub fn x(&self) -> i32 {
+        self.x
+    }
+
+    pub fn y(&self) -> i32 {
+        self.y
+    }
+}
+
+#[allow(non_camel_case_types)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    // 16:9
+    #[serde(rename = "480p")]
+    _480p,
+    #[serde(rename = "720p")]
+    _720p,
+    #[serde(rename = "1080p")]
+    _1080p,
+    #[serde(rename = "1440p")]
+    _1440p,
+    #[serde(rename = "2160p")]
+    _2160p,
+    #[serde(rename = "4320p")]
+    _4320p,
+    // the default resolutions for other aspect ratios of the LoL window
+    #[serde(rename = "1600x1200p")]
+    _1600x1200p,
+    #[serde(rename = "1280x1024p")]
+    _1280x1024p,
+    #[serde(rename = "1920x1080p")]
+    _1920x1080p,
+    #[serde(rename = "1920x1200p")]
+    _1920x1200p,
+    #[serde(rename = "2560x1080p")]
+    _2560x1080p,
+    #[serde(rename = "2580x1080p")]
+    _2580x1080p,
+    #[serde(rename = "3840x1600p")]
+    _3840x1600p,
+    #[serde(rename = "3840x1080p")]
+    _3840x1080p,
+    #[serde(rename = "3840x1200p")]
+    _3840x1200p,
+}
+
+impl Resolution {
+    pub fn size(self) -> Size {
+        let (width, height) = match self {
+            Resolution::_480p => (854, 480),
+            Resolution::_720p => (1280, 720),
+            Resolution::_1080p => (1920, 1080),
+            Resolution::_1440p => (2560, 1440),
+            Resolution::_2160p => (3840, 2160),
+            Resolution::_4320p => (7680, 4320),
+            Resolution::_1600x1200p => (1600, 1200),
+            Resolution::_1280x1024p => (1280, 1024),
+            Resolution::_1920x1080p => (1920, 1080),
+            Resolution::_1920x1200p => (1920, 1200),
+            Resolution::_2560x1080p => (2560, 1080),
+            Resolution::_2580x1080p => (2580, 1080),
+            Resolution::_3840x1600p => (3840, 1600),
+            Resolution::_3840x1080p => (3840, 1080),
+            Resolution::_3840x1200p => (3840, 1200),
+        };
+        Size::new(width, height)
+    }
+}
+
+/// frames per second as a fraction - (de)serializes to `[numerator, denominator]`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Framerate(u32, u32);
+
+impl Framerate {
+    pub fn new(numerator: u32, denominator: u32) -> Self {
+        Self(numerator, denominator)
+    }
+
+    pub fn numerator(&self) -> u32 {
+        self.0
+    }
+
+    pub fn denominator(&self) -> u32 {
+        self.1
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioSource {
+    NONE,
+    /// only the LoL game
+    APPLICATION,
+    /// everything that is played back
+    SYSTEM,
+    /// everything that is played back and the default microphone
+    ALL,
+}
@@ -0,0 +1,170 @@
+use std::collections::VecDeque;
+
+use crate::recorder::settings::{Position, Size};
+
+use super::{GameWindowLocator, WindowHandle};
+
+/// What the scripted locator reports for one call to `find_window`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowStep {
+    NoWindow,
+    /// the window exists and has the given client size (at the top left corner of the screen)
+    Window {
+        width: u32,
+        height: u32,
+    },
+    /// the window exists but its size can't be determined
+    InvalidSize,
+}
+
+/// Plays back a fixed sequence of window states, one step per `find_window` call.
+/// After the script ran out the last step is repeated.
+#[derive(Debug, Default)]
+pub struct ScriptedWindowLocator {
+    steps: VecDeque<WindowStep>,
+    current: Option<WindowStep>,
+}
+
+impl ScriptedWindowLocator {
+    pub fn new(steps: impl IntoIterator<Item = WindowStep>) -> Self {
+        Self {
+            steps: steps.into_iter().collect(),
+            current: None,
+        }
+    }
+
+    pub fn push(&mut self, step: WindowStep) {
+        self.steps.push_back(step);
+    }
+}
+
+impl GameWindowLocator for ScriptedWindowLocator {
+    fn find_window(&mut self) -> Option<WindowHandle> {
+        if let Some(step) = self.steps.pop_front() {
+            self.current = Some(step);
+        }
+
+        match self.current? {
+            WindowStep::NoWindow => None,
+            WindowStep::Window { .. } | WindowStep::InvalidSize => Some(WindowHandle(1)),
+        }
+    }
+
+    fn client_size(&mut self, _handle: WindowHandle) -> Result<Size, ()> {
+        match self.current {
+            Some(WindowStep::Window { width, height }) => Ok(Size::new(width, height)),
+            _ => Err(()),
+        }
+    }
+
+    fn client_position(&mut self, _handle: WindowHandle) -> Result<Position, ()> {
+        match self.current {
+            Some(WindowStep::Window { .. }) => Ok(Position::default()),
+            _ => Err(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::recorder::{
+        closest_resolution_to_size,
+        lifecycle::{PostGamePhase::*, RecorderCommand, RecorderEvent, RecorderState, RecorderStateMachine},
+        settings::Resolution,
+    };
+
+    /// Polls the locator and starts sessions like the recorder thread does.
+    /// Returns the state after every poll and the output resolution of every session that got started.
+    fn run(locator: &mut ScriptedWindowLocator, polls: usize) -> (Vec<RecorderState>, Vec<Resolution>) {
+        let mut machine = RecorderStateMachine::new();
+        let mut states = Vec::new();
+        let mut sessions = Vec::new();
+
+        for _ in 0..polls {
+            let window = locator.find_window();
+            let mut events = vec![match window {
+                Some(_) => RecorderEvent::WindowFound,
+                None => RecorderEvent::WindowGone,
+            }];
+            while let Some(event) = events.pop() {
+                for command in machine.handle(event) {
+                    if command == RecorderCommand::StartSession {
+                        match window.map(|handle| locator.client_size(handle)) {
+                            Some(Ok(size)) => sessions.push(closest_resolution_to_size(&size)),
+                            _ => events.push(RecorderEvent::SessionFailed),
+                        }
+                    }
+                }
+            }
+            states.push(machine.state());
+        }
+
+        (states, sessions)
+    }
+
+    #[test]
+    fn repeats_the_last_step() {
+        let mut locator =
+            ScriptedWindowLocator::new([WindowStep::NoWindow, WindowStep::Window { width: 800, height: 600 }]);
+        assert_eq!(locator.find_window(), None);
+        for _ in 0..3 {
+            let handle = locator.find_window().unwrap();
+            assert_eq!(locator.client_size(handle), Ok(Size::new(800, 600)));
+        }
+
+        locator.push(WindowStep::InvalidSize);
+        let handle = locator.find_window().unwrap();
+        assert_eq!(locator.client_size(handle), Err(()));
+    }
+
+    #[test]
+    fn no_script_means_no_window() {
+        let mut locator = ScriptedWindowLocator::default();
+        assert_eq!(locator.find_window(), None);
+        assert_eq!(locator.client_size(WindowHandle(1)), Err(()));
+    }
+
+    #[test]
+    fn starts_a_session_once_the_window_appears() {
+        let mut locator = ScriptedWindowLocator::new([
+            WindowStep::NoWindow,
+            WindowStep::NoWindow,
+            WindowStep::Window { width: 3840, height: 1600 },
+            WindowStep::Window { width: 3840, height: 1600 },
+        ]);
+        let (states, sessions) = run(&mut locator, 5);
+
+        use RecorderState::*;
+        assert_eq!(states, [Idle, Idle, WaitingForGame, WaitingForGame, WaitingForGame]);
+        assert_eq!(sessions, [Resolution::_3840x1600p]);
+    }
+
+    #[test]
+    fn retries_when_the_window_size_is_unknown() {
+        let mut locator = ScriptedWindowLocator::new([
+            WindowStep::InvalidSize,
+            WindowStep::InvalidSize,
+            WindowStep::Window { width: 1600, height: 1200 },
+        ]);
+        let (states, sessions) = run(&mut locator, 4);
+
+        use RecorderState::*;
+        assert_eq!(states, [Idle, Idle, WaitingForGame, WaitingForGame]);
+        assert_eq!(sessions, [Resolution::_1600x1200p]);
+    }
+
+    #[test]
+    fn waits_for_the_task_after_the_window_closed() {
+        let mut locator =
+            ScriptedWindowLocator::new([WindowStep::Window { width: 1920, height: 1080 }, WindowStep::NoWindow]);
+        let (states, sessions) = run(&mut locator, 3);
+
+        use RecorderState::*;
+        assert_eq!(
+            states,
+            [WaitingForGame, PostGame(WaitingForStats), PostGame(WaitingForStats)]
+        );
+        assert_eq!(sessions, [Resolution::_1920x1080p]);
+    }
+}
@@ -0,0 +1,43 @@
+use crate::recorder::settings::{Position, Size};
+
+#[cfg(target_os = "windows")]
+pub use win32::Win32WindowLocator;
+#[cfg(target_os = "linux")]
+pub use x11::X11WindowLocator;
+
+/// plays back a scripted sequence of windows - for tests and platforms without window detection
+#[cfg(any(test, not(any(target_os = "windows", target_os = "linux"))))]
+#[cfg_attr(not(test), allow(dead_code))]
+mod mock;
+#[cfg(target_os = "windows")]
+mod win32;
+#[cfg(target_os = "linux")]
+mod x11;
+
+/// Platform specific window identifier (HWND on Windows, XID on X11)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WindowHandle(pub isize);
+
+/// Finds the LoL ingame window and its client area
+pub trait GameWindowLocator: Send {
+    fn find_window(&mut self) -> Option<WindowHandle>;
+    fn client_size(&mut self, handle: WindowHandle) -> Result<Size, ()>;
+    /// screen position of the top left corner of the client area
+    fn client_position(&mut self, handle: WindowHandle) -> Result<Position, ()>;
+}
+
+#[cfg(target_os = "windows")]
+pub fn default_locator() -> Box<dyn GameWindowLocator> {
+    Box::new(Win32WindowLocator)
+}
+
+#[cfg(target_os = "linux")]
+pub fn default_locator() -> Box<dyn GameWindowLocator> {
+    Box::new(X11WindowLocator::default())
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+pub fn default_locator() -> Box<dyn GameWindowLocator> {
+    log::warn!("no game window detection available for this platform");
+    Box::new(mock::ScriptedWindowLocator::default())
+}
@@ -0,0 +1,54 @@
+use windows::{
+    core::PCSTR,
+    Win32::{
+        Foundation::{HWND, POINT, RECT},
+        Graphics::Gdi::ClientToScreen,
+        UI::WindowsAndMessaging::{FindWindowA, GetClientRect},
+    },
+};
+
+use super::{GameWindowLocator, WindowHandle};
+use crate::recorder::{
+    settings::{Position, Size},
+    WINDOW_CLASS, WINDOW_TITLE,
+};
+
+pub struct Win32WindowLocator;
+
+impl GameWindowLocator for Win32WindowLocator {
+    fn find_window(&mut self) -> Option<WindowHandle> {
+        let mut window_title = WINDOW_TITLE.to_owned();
+        window_title.push('\0'); // null terminate
+        let mut window_class = WINDOW_CLASS.to_owned();
+        window_class.push('\0'); // null terminate
+
+        let title = PCSTR(window_title.as_ptr());
+        let class = PCSTR(window_class.as_ptr());
+
+        let hwnd = unsafe { FindWindowA(class, title) };
+        if hwnd.0 == 0 {
+            None
+        } else {
+            Some(WindowHandle(hwnd.0))
+        }
+    }
+
+    fn client_size(&mut self, handle: WindowHandle) -> Result<Size, ()> {
+        let mut rect = RECT::default();
+        unsafe { GetClientRect(HWND(handle.0), &mut rect as _) }.map_err(|_| ())?;
+        if rect.right > 0 && rect.bottom > 0 {
+            Ok(Size::new(rect.right as u32, rect.bottom as u32))
+        } else {
+            Err(())
+        }
+    }
+
+    fn client_position(&mut self, handle: WindowHandle) -> Result<Position, ()> {
+        let mut point = POINT::default();
+        if unsafe { ClientToScreen(HWND(handle.0), &mut point as _) }.as_bool() {
+            Ok(Position::new(point.x, point.y))
+        } else {
+            Err(())
+        }
+    }
+}
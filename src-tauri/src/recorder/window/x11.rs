@@ -0,0 +1,330 @@
+use x11rb::{
+    connection::Connection,
+    protocol::xproto::{Atom, AtomEnum, ConnectionExt, Window},
+    rust_connection::RustConnection,
+};
+
+use super::{GameWindowLocator, WindowHandle};
+use crate::recorder::{
+    settings::{Position, Size},
+    WINDOW_CLASS, WINDOW_PROCESS, WINDOW_TITLE,
+};
+
+/// Finds the LoL window on an X11 display (e.g. when running through Wine/Proton or under Xvfb).
+/// The connection to the X server is opened lazily and re-established after errors.
+#[derive(Default)]
+pub struct X11WindowLocator {
+    /// the display to connect to (e.g. ":99") - `None` uses $DISPLAY
+    display: Option<String>,
+    connection: Option<X11Connection>,
+    connect_failed: bool,
+}
+
+struct X11Connection {
+    conn: RustConnection,
+    root: Window,
+    net_client_list: Atom,
+    net_wm_name: Atom,
+    utf8_string: Atom,
+}
+
+type X11Result<T> = Result<T, Box<dyn std::error::Error>>;
+
+impl X11Connection {
+    fn connect(display: Option<&str>) -> X11Result<Self> {
+        let (conn, screen_num) = x11rb::connect(display)?;
+        let root = conn.setup().roots[screen_num].root;
+        let net_client_list = conn.intern_atom(false, b"_NET_CLIENT_LIST")?.reply()?.atom;
+        let net_wm_name = conn.intern_atom(false, b"_NET_WM_NAME")?.reply()?.atom;
+        let utf8_string = conn.intern_atom(false, b"UTF8_STRING")?.reply()?.atom;
+        Ok(Self {
+            conn,
+            root,
+            net_client_list,
+            net_wm_name,
+            utf8_string,
+        })
+    }
+
+    fn find_lol_window(&self) -> X11Result<Option<Window>> {
+        // prefer the list of managed windows from the window manager
+        // fall back to walking the whole window tree if there is no WM (e.g. plain Xvfb)
+        let client_list = self
+            .conn
+            .get_property(false, self.root, self.net_client_list, AtomEnum::WINDOW, 0, u32::MAX)?
+            .reply()?;
+        let candidates: Vec<Window> = match client_list.value32() {
+            Some(windows) => windows.collect(),
+            None => self.all_windows()?,
+        };
+
+        // windows can disappear while we look at them => treat errors for single windows as "not LoL"
+        Ok(candidates
+            .into_iter()
+            .find(|window| self.is_lol_window(*window).unwrap_or(false)))
+    }
+
+    fn all_windows(&self) -> X11Result<Vec<Window>> {
+        let mut windows = Vec::new();
+        let mut stack = vec![self.root];
+        while let Some(window) = stack.pop() {
+            let Ok(tree) = self.conn.query_tree(window)?.reply() else {
+                continue;
+            };
+            windows.extend_from_slice(&tree.children);
+            stack.extend(tree.children);
+        }
+        Ok(windows)
+    }
+
+    fn is_lol_window(&self, window: Window) -> X11Result<bool> {
+        if self.window_title(window)?.as_deref() != Some(WINDOW_TITLE) {
+            return Ok(false);
+        }
+
+        // WM_CLASS contains two null terminated strings: instance and class name
+        // Wine sets these to the name of the executable instead of the Win32 window class
+        let wm_class = self
+            .conn
+            .get_property(false, window, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 1024)?
+            .reply()?;
+        let matches_class = wm_class
+            .value
+            .split(|b| *b == 0)
+            .filter_map(|part| std::str::from_utf8(part).ok())
+            .any(|part| part.eq_ignore_ascii_case(WINDOW_CLASS) || part.eq_ignore_ascii_case(WINDOW_PROCESS));
+
+        // some setups don't set WM_CLASS at all - the title alone is specific enough in that case
+        Ok(matches_class || wm_class.value.is_empty())
+    }
+
+    fn window_title(&self, window: Window) -> X11Result<Option<String>> {
+        let net_wm_name = self
+            .conn
+            .get_property(false, window, self.net_wm_name, self.utf8_string, 0, 1024)?
+            .reply()?;
+        if !net_wm_name.value.is_empty() {
+            return Ok(String::from_utf8(net_wm_name.value).ok());
+        }
+
+        let wm_name = self
+            .conn
+            .get_property(false, window, AtomEnum::WM_NAME, AtomEnum::STRING, 0, 1024)?
+            .reply()?;
+        if wm_name.value.is_empty() {
+            Ok(None)
+        } else {
+            Ok(String::from_utf8(wm_name.value).ok())
+        }
+    }
+}
+
+impl X11WindowLocator {
+    pub fn with_display(display: impl Into<String>) -> Self {
+        Self {
+            display: Some(display.into()),
+            ..Self::default()
+        }
+    }
+
+    fn connection(&mut self) -> Option<&X11Connection> {
+        if self.connection.is_none() {
+            match X11Connection::connect(self.display.as_deref()) {
+                Ok(connection) => {
+                    self.connection = Some(connection);
+                    self.connect_failed = false;
+                }
+                Err(e) => {
+                    // this gets polled every second - only log the first failed attempt
+                    if !self.connect_failed {
+                        log::warn!("unable to connect to X server: {e}");
+                    }
+                    self.connect_failed = true;
+                }
+            }
+        }
+        self.connection.as_ref()
+    }
+}
+
+impl GameWindowLocator for X11WindowLocator {
+    fn find_window(&mut self) -> Option<WindowHandle> {
+        let result = self.connection()?.find_lol_window();
+        match result {
+            Ok(window) => window.map(|w| WindowHandle(w as isize)),
+            Err(e) => {
+                log::warn!("X11 error while searching for the LoL window: {e}");
+                // reconnect on the next call
+                self.connection = None;
+                None
+            }
+        }
+    }
+
+    fn client_size(&mut self, handle: WindowHandle) -> Result<Size, ()> {
+        let connection = self.connection().ok_or(())?;
+        let geometry = connection
+            .conn
+            .get_geometry(handle.0 as Window)
+            .map_err(|_| ())?
+            .reply()
+            .map_err(|_| ())?;
+        if geometry.width > 0 && geometry.height > 0 {
+            Ok(Size::new(u32::from(geometry.width), u32::from(geometry.height)))
+        } else {
+            Err(())
+        }
+    }
+
+    fn client_position(&mut self, handle: WindowHandle) -> Result<Position, ()> {
+        let connection = self.connection().ok_or(())?;
+        // the window position is relative to its parent (e.g. the frame of the WM) => translate to root coordinates
+        let translated = connection
+            .conn
+            .translate_coordinates(handle.0 as Window, connection.root, 0, 0)
+            .map_err(|_| ())?
+            .reply()
+            .map_err(|_| ())?;
+        Ok(Position::new(i32::from(translated.dst_x), i32::from(translated.dst_y)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Child, Command, Stdio},
+    };
+
+    use x11rb::{
+        protocol::xproto::{CreateWindowAux, PropMode, WindowClass},
+        wrapper::ConnectionExt as _,
+        COPY_DEPTH_FROM_PARENT,
+    };
+
+    use super::*;
+
+    /// kills the X server when the test is done
+    struct Xvfb(Child);
+
+    impl Drop for Xvfb {
+        fn drop(&mut self) {
+            _ = self.0.kill();
+            _ = self.0.wait();
+        }
+    }
+
+    /// Starts Xvfb on a free display and returns it together with its display name.
+    /// Returns `None` if Xvfb isn't installed so the test can be skipped.
+    fn start_xvfb() -> Option<(Xvfb, String)> {
+        // -displayfd makes Xvfb pick a free display and write its number to stdout once it is ready
+        let child = Command::new("Xvfb")
+            .args(["-displayfd", "1", "-screen", "0", "1920x1080x24", "-nolisten", "tcp"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn();
+        let mut xvfb = match child {
+            Ok(child) => Xvfb(child),
+            Err(e) => {
+                eprintln!("skipping test - unable to start Xvfb: {e}");
+                return None;
+            }
+        };
+
+        let mut display = String::new();
+        BufReader::new(xvfb.0.stdout.take()?).read_line(&mut display).ok()?;
+        let display = format!(":{}", display.trim());
+        Some((xvfb, display))
+    }
+
+    /// creates and maps a top level window with the given title and WM_CLASS
+    fn create_window(
+        conn: &RustConnection,
+        title: &str,
+        class: &[u8],
+        position: (i16, i16),
+        size: (u16, u16),
+    ) -> Window {
+        let root = conn.setup().roots[0].root;
+        let window = conn.generate_id().unwrap();
+        conn.create_window(
+            COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            position.0,
+            position.1,
+            size.0,
+            size.1,
+            0,
+            WindowClass::INPUT_OUTPUT,
+            0,
+            &CreateWindowAux::new(),
+        )
+        .unwrap();
+        conn.change_property8(
+            PropMode::REPLACE,
+            window,
+            AtomEnum::WM_NAME,
+            AtomEnum::STRING,
+            title.as_bytes(),
+        )
+        .unwrap();
+        conn.change_property8(PropMode::REPLACE, window, AtomEnum::WM_CLASS, AtomEnum::STRING, class)
+            .unwrap();
+        conn.map_window(window).unwrap();
+        conn.sync().unwrap();
+        window
+    }
+
+    #[test]
+    fn finds_the_lol_window() {
+        let Some((_xvfb, display)) = start_xvfb() else {
+            return;
+        };
+        let (conn, _) = x11rb::connect(Some(&display)).unwrap();
+        let mut locator = X11WindowLocator::with_display(&display);
+
+        assert_eq!(locator.find_window(), None);
+
+        // same title but a different program or only a similar title => not LoL
+        create_window(&conn, WINDOW_TITLE, b"firefox\0Firefox\0", (0, 0), (800, 600));
+        let client = b"LeagueClientUx.exe\0LeagueClientUx.exe\0";
+        create_window(&conn, "League of Legends", client, (0, 0), (1280, 720));
+        assert_eq!(locator.find_window(), None);
+
+        // Wine sets WM_CLASS to the name of the executable
+        let class = b"league of legends.exe\0League of Legends.exe\0";
+        let lol = create_window(&conn, WINDOW_TITLE, class, (100, 50), (1600, 900));
+        let handle = locator.find_window().unwrap();
+        assert_eq!(handle, WindowHandle(lol as isize));
+        assert_eq!(locator.client_size(handle), Ok(Size::new(1600, 900)));
+        assert_eq!(locator.client_position(handle), Ok(Position::new(100, 50)));
+
+        conn.destroy_window(lol).unwrap();
+        conn.sync().unwrap();
+        assert_eq!(locator.find_window(), None);
+    }
+
+    #[test]
+    fn finds_the_lol_window_without_wm_class() {
+        let Some((_xvfb, display)) = start_xvfb() else {
+            return;
+        };
+        let (conn, _) = x11rb::connect(Some(&display)).unwrap();
+        let mut locator = X11WindowLocator::with_display(&display);
+
+        let lol = create_window(&conn, WINDOW_TITLE, b"", (0, 0), (1920, 1080));
+        let handle = locator.find_window().unwrap();
+        assert_eq!(handle, WindowHandle(lol as isize));
+        assert_eq!(locator.client_size(handle), Ok(Size::new(1920, 1080)));
+    }
+
+    #[test]
+    fn no_window_without_x_server() {
+        let mut locator = X11WindowLocator::with_display(":nonexistent");
+        assert_eq!(locator.find_window(), None);
+        assert_eq!(locator.client_size(WindowHandle(1)), Err(()));
+        assert_eq!(locator.client_position(WindowHandle(1)), Err(()));
+    }
+}
@@ -0,0 +1,321 @@
+/*
+    Keeps the recordings folder within the limits of the retention settings by deleting the oldest recordings.
+    Protected recordings and the newest recording (which might still be recording) are never deleted.
+*/
+
+use std::{
+    cmp::Reverse,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+use tauri::{api::path::app_config_dir, AppHandle, Manager};
+
+use crate::{
+    helpers::{self, unix_millis},
+    state::{RetentionSettings, Settings},
+};
+
+const RETENTION_LOG: &str = "retention.log";
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Deletion {
+    pub filename: String,
+    /// in bytes
+    pub size: u64,
+    /// unix timestamp in milliseconds
+    pub created: i64,
+    pub reason: DeletionReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DeletionReason {
+    /// more recordings than `keep_last`
+    Count,
+    /// older than `max_age_days`
+    Age,
+    /// all recordings together are larger than `max_size_gb`
+    Size,
+}
+
+struct Recording {
+    path: PathBuf,
+    size: u64,
+    created: SystemTime,
+}
+
+/// Enforces the retention settings at startup and after every game.
+pub fn start(app_handle: &AppHandle) {
+    enforce_in_background(app_handle);
+    app_handle.listen_global("recording_finished", {
+        let app_handle = app_handle.clone();
+        move |_| enforce_in_background(&app_handle)
+    });
+}
+
+fn enforce_in_background(app_handle: &AppHandle) {
+    let app_handle = app_handle.clone();
+    thread::spawn(move || enforce(&app_handle));
+}
+
+fn enforce(app_handle: &AppHandle) {
+    let settings = app_handle.state::<Settings>();
+    let retention = settings.get_retention();
+    if !retention.is_enabled() {
+        return;
+    }
+
+    let rec_folder = settings.get_recordings_path();
+    let mut removed = String::new();
+    for deletion in plan(&rec_folder, &retention) {
+        let recording = rec_folder.join(&deletion.filename);
+        match helpers::delete_recording(&recording) {
+            Ok(_) => {
+                if let Some(folder) = recording.parent() {
+                    helpers::remove_empty_folders(&rec_folder, folder);
+                }
+                log::info!("retention: deleted {} ({:?})", deletion.filename, deletion.reason);
+                let now = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
+                removed.push_str(&format!(
+                    "{now} deleted {} ({} bytes, {:?})\n",
+                    deletion.filename, deletion.size, deletion.reason
+                ));
+            }
+            Err(e) => log::warn!("retention: unable to delete {}: {e}", deletion.filename),
+        }
+    }
+
+    if removed.is_empty() {
+        return;
+    }
+    let Some(log_path) = app_config_dir(app_handle.config().as_ref()).map(|dir| dir.join(RETENTION_LOG)) else {
+        return;
+    };
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .and_then(|mut file| file.write_all(removed.as_bytes()));
+    if let Err(e) = result {
+        log::warn!("unable to write {log_path:?}: {e}");
+    }
+}
+
+/// The recordings that have to be deleted to meet the retention settings (oldest first).
+pub fn plan(rec_folder: &Path, retention: &RetentionSettings) -> Vec<Deletion> {
+    let mut recordings: Vec<Recording> = helpers::get_recordings(rec_folder)
+        .into_iter()
+        .filter_map(|path| {
+            let metadata = fs::metadata(&path).ok()?;
+            // not every platform / filesystem records the creation time
+            let created = metadata.created().or_else(|_| metadata.modified()).ok()?;
+            Some(Recording {
+                path,
+                size: metadata.len(),
+                created,
+            })
+        })
+        .collect();
+    let reasons = select(&mut recordings, retention, SystemTime::now(), helpers::is_protected);
+
+    recordings
+        .into_iter()
+        .zip(reasons)
+        .rev()
+        .filter_map(|(recording, reason)| {
+            Some(Deletion {
+                filename: helpers::recording_name(rec_folder, &recording.path)?,
+                size: recording.size,
+                created: unix_millis(recording.created),
+                reason: reason?,
+            })
+        })
+        .collect()
+}
+
+/// Sorts the recordings (newest first) and returns why each of them has to be deleted (if at all).
+fn select(
+    recordings: &mut [Recording],
+    retention: &RetentionSettings,
+    now: SystemTime,
+    is_protected: impl Fn(&Path) -> bool,
+) -> Vec<Option<DeletionReason>> {
+    // newest first
+    recordings.sort_by_key(|recording| Reverse(recording.created));
+
+    let max_age = retention
+        .max_age_days
+        .map(|days| Duration::from_secs(u64::from(days) * 24 * 60 * 60));
+    let max_size = retention.max_size_gb.map(|gb| (gb * 1_000_000_000.0) as u64);
+    let mut total_size: u64 = recordings.iter().map(|recording| recording.size).sum();
+
+    let mut reasons = vec![None; recordings.len()];
+    for (index, recording) in recordings.iter().enumerate().skip(1) {
+        if retention.keep_last.is_some_and(|keep_last| index >= keep_last as usize) {
+            reasons[index] = Some(DeletionReason::Count);
+        } else if max_age.is_some_and(|max_age| now.duration_since(recording.created).is_ok_and(|age| age > max_age)) {
+            reasons[index] = Some(DeletionReason::Age);
+        }
+    }
+    // protected recordings stay no matter what
+    for (reason, recording) in reasons.iter_mut().zip(recordings.iter()) {
+        if reason.is_some() && is_protected(&recording.path) {
+            *reason = None;
+        }
+    }
+    for (reason, recording) in reasons.iter().zip(recordings.iter()) {
+        if reason.is_some() {
+            total_size -= recording.size;
+        }
+    }
+
+    // delete the oldest recordings until the rest fits
+    if let Some(max_size) = max_size {
+        for (index, recording) in recordings.iter().enumerate().skip(1).rev() {
+            if total_size <= max_size {
+                break;
+            }
+            if reasons[index].is_none() && !is_protected(&recording.path) {
+                reasons[index] = Some(DeletionReason::Size);
+                total_size -= recording.size;
+            }
+        }
+    }
+
+    reasons
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GB: u64 = 1_000_000_000;
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    fn recording(name: &str, size: u64, age_days: u32, now: SystemTime) -> Recording {
+        Recording {
+            path: PathBuf::from(format!("/recordings/{name}.mp4")),
+            size,
+            created: now - DAY * age_days,
+        }
+    }
+
+    /// the recordings ordered from oldest to newest (which is the last one) and their age in days
+    fn recordings(ages: &[u32], now: SystemTime) -> Vec<Recording> {
+        ages.iter()
+            .enumerate()
+            .map(|(i, age)| recording(&format!("game{i}"), GB, *age, now))
+            .collect()
+    }
+
+    /// the recordings that get deleted (oldest first)
+    fn run(
+        mut recordings: Vec<Recording>,
+        retention: RetentionSettings,
+        protected: &[&str],
+        now: SystemTime,
+    ) -> Vec<(String, DeletionReason)> {
+        let is_protected = |path: &Path| protected.iter().any(|name| path.ends_with(format!("{name}.mp4")));
+        let reasons = select(&mut recordings, &retention, now, is_protected);
+        recordings
+            .into_iter()
+            .zip(reasons)
+            .rev()
+            .filter_map(|(recording, reason)| {
+                let name = recording.path.file_stem()?.to_string_lossy().into_owned();
+                Some((name, reason?))
+            })
+            .collect()
+    }
+
+    fn deleted(names: &[&str], reason: DeletionReason) -> Vec<(String, DeletionReason)> {
+        names.iter().map(|name| (name.to_string(), reason)).collect()
+    }
+
+    #[test]
+    fn keeps_everything_without_limits() {
+        let now = SystemTime::now();
+        let deletions = run(recordings(&[400, 300, 0], now), RetentionSettings::default(), &[], now);
+        assert!(deletions.is_empty());
+    }
+
+    #[test]
+    fn keeps_the_last_recordings() {
+        let now = SystemTime::now();
+        let retention = RetentionSettings {
+            keep_last: Some(2),
+            ..RetentionSettings::default()
+        };
+        // the order on disk doesn't matter - only when the recordings were created
+        let mut unordered = recordings(&[4, 3, 2, 1], now);
+        unordered.swap(0, 3);
+        let deletions = run(unordered, retention, &[], now);
+        assert_eq!(deletions, deleted(&["game0", "game1"], DeletionReason::Count));
+    }
+
+    #[test]
+    fn deletes_old_recordings() {
+        let now = SystemTime::now();
+        let retention = RetentionSettings {
+            max_age_days: Some(7),
+            ..RetentionSettings::default()
+        };
+        let deletions = run(recordings(&[30, 8, 6, 0], now), retention.clone(), &[], now);
+        assert_eq!(deletions, deleted(&["game0", "game1"], DeletionReason::Age));
+
+        // the newest recording stays even if it is too old
+        let deletions = run(recordings(&[30, 8], now), retention, &[], now);
+        assert_eq!(deletions, deleted(&["game0"], DeletionReason::Age));
+    }
+
+    #[test]
+    fn deletes_the_oldest_recordings_until_the_rest_fits() {
+        let now = SystemTime::now();
+        let retention = RetentionSettings {
+            max_size_gb: Some(2.5),
+            ..RetentionSettings::default()
+        };
+        let deletions = run(recordings(&[4, 3, 2, 1], now), retention.clone(), &[], now);
+        assert_eq!(deletions, deleted(&["game0", "game1"], DeletionReason::Size));
+
+        // recordings that get deleted anyway count towards the limit
+        let retention = RetentionSettings {
+            keep_last: Some(3),
+            ..retention
+        };
+        let mut deletions = deleted(&["game0"], DeletionReason::Count);
+        deletions.extend(deleted(&["game1"], DeletionReason::Size));
+        assert_eq!(run(recordings(&[4, 3, 2, 1], now), retention, &[], now), deletions);
+    }
+
+    #[test]
+    fn never_deletes_protected_recordings() {
+        let now = SystemTime::now();
+        let retention = RetentionSettings {
+            keep_last: Some(1),
+            max_age_days: Some(1),
+            max_size_gb: Some(0.5),
+        };
+        let deletions = run(recordings(&[4, 3, 2, 1], now), retention, &["game0", "game2"], now);
+        assert_eq!(deletions, deleted(&["game1"], DeletionReason::Count));
+
+        // protected recordings still count towards the size limit
+        let retention = RetentionSettings {
+            max_size_gb: Some(2.5),
+            ..RetentionSettings::default()
+        };
+        let recordings = vec![
+            recording("big", 3 * GB, 3, now),
+            recording("small", GB / 2, 2, now),
+            recording("newest", GB, 0, now),
+        ];
+        let deletions = run(recordings, retention, &["big"], now);
+        assert_eq!(deletions, deleted(&["small"], DeletionReason::Size));
+    }
+}
@@ -11,7 +11,10 @@ use serde::{
 };
 use tauri::api::path::video_dir;
 
-use libobs_recorder::settings::{AudioSource, Framerate, Resolution};
+use crate::recorder::{
+    backend::{BackendKind, FfmpegSource},
+    settings::{AudioSource, Framerate, Resolution},
+};
 
 pub struct WindowState {
     pub size: Mutex<(f64, f64)>,
@@ -151,6 +154,70 @@ impl Default for MarkerFlags {
 //     }
 // }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ReplayBufferSettings {
+    /// only keep the last `length` seconds and save clips instead of recording the whole game
+    pub enabled: bool,
+    /// length of the replay buffer (and the clips) in seconds
+    pub length: u32,
+    /// how long to keep recording after a trigger event before the clip gets saved
+    pub seconds_after_event: u32,
+    /// names of the events that save a clip (e.g. "Kill", "Baron", "Multikill")
+    pub trigger_events: Vec<String>,
+}
+
+impl Default for ReplayBufferSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            length: 30,
+            seconds_after_event: 5,
+            trigger_events: vec!["Kill".into(), "Baron".into(), "Multikill".into()],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HighlightSettings {
+    /// cut clips around the markers of a game after it ended
+    pub enabled: bool,
+    pub seconds_before: u32,
+    pub seconds_after: u32,
+    /// names of the events to cut clips for - all events if empty
+    pub events: Vec<String>,
+}
+
+impl Default for HighlightSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            seconds_before: 10,
+            seconds_after: 5,
+            events: vec![],
+        }
+    }
+}
+
+/// Limits for the recordings folder - the oldest recordings that aren't protected get deleted to stay within them.
+/// Every limit that is not set is disabled.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RetentionSettings {
+    /// max total size of all recordings in GB
+    pub max_size_gb: Option<f64>,
+    pub max_age_days: Option<u32>,
+    /// max number of recordings
+    pub keep_last: Option<u32>,
+}
+
+impl RetentionSettings {
+    pub fn is_enabled(&self) -> bool {
+        self.max_size_gb.is_some() || self.max_age_days.is_some() || self.keep_last.is_some()
+    }
+}
+
 #[derive(Debug)]
 pub struct Settings(RwLock<SettingsInner>);
 
@@ -189,6 +256,10 @@ impl Settings {
         self.0.read().unwrap().filename_format.clone()
     }
 
+    pub fn get_folder_layout(&self) -> String {
+        self.0.read().unwrap().folder_layout.clone()
+    }
+
     pub fn get_encoding_quality(&self) -> u32 {
         self.0.read().unwrap().encoding_quality
     }
@@ -205,6 +276,34 @@ impl Settings {
         self.0.read().unwrap().record_audio
     }
 
+    pub fn get_recording_backend(&self) -> BackendKind {
+        self.0.read().unwrap().recording_backend
+    }
+
+    pub fn get_ffmpeg_source(&self) -> FfmpegSource {
+        self.0.read().unwrap().ffmpeg_source
+    }
+
+    pub fn get_replay_buffer(&self) -> ReplayBufferSettings {
+        self.0.read().unwrap().replay_buffer.clone()
+    }
+
+    pub fn get_highlights(&self) -> HighlightSettings {
+        self.0.read().unwrap().highlights.clone()
+    }
+
+    pub fn get_retention(&self) -> RetentionSettings {
+        self.0.read().unwrap().retention.clone()
+    }
+
+    pub fn trash_retention_days(&self) -> u32 {
+        self.0.read().unwrap().trash_retention_days
+    }
+
+    pub fn min_free_space_gb(&self) -> f64 {
+        self.0.read().unwrap().min_free_space_gb
+    }
+
     pub fn check_for_updates(&self) -> bool {
         self.0.read().unwrap().check_for_updates
     }
@@ -217,6 +316,10 @@ impl Settings {
         self.0.write().unwrap().marker_flags = marker_flags;
     }
 
+    pub fn mp4_chapters(&self) -> bool {
+        self.0.read().unwrap().mp4_chapters
+    }
+
     pub fn autostart(&self) -> bool {
         self.0.read().unwrap().autostart
     }
@@ -242,11 +345,20 @@ pub struct SettingsInner {
     // these get passed to lol_rec
     recordings_folder: PathBuf,
     filename_format: String,
+    folder_layout: String,
     encoding_quality: u32,
     #[serde(skip_serializing_if = "Option::is_none")]
     output_resolution: Option<Resolution>,
     framerate: Framerate,
     record_audio: AudioSource,
+    recording_backend: BackendKind,
+    ffmpeg_source: FfmpegSource,
+    replay_buffer: ReplayBufferSettings,
+    highlights: HighlightSettings,
+    mp4_chapters: bool,
+    retention: RetentionSettings,
+    trash_retention_days: u32,
+    min_free_space_gb: f64,
     autostart: bool,
 }
 
@@ -254,6 +366,9 @@ const DEFAULT_UPDATE_CHECK: bool = true;
 const DEFAULT_DEBUG_LOG: bool = false;
 const DEFAULT_ENCODING_QUALITY: u32 = 25;
 const DEFAULT_RECORD_AUDIO: AudioSource = AudioSource::APPLICATION;
+const DEFAULT_MP4_CHAPTERS: bool = false;
+const DEFAULT_TRASH_RETENTION_DAYS: u32 = 30;
+const DEFAULT_MIN_FREE_SPACE_GB: f64 = 2.0;
 const DEFAULT_AUTOSTART: bool = false;
 
 #[inline]
@@ -266,6 +381,12 @@ fn default_filename_format() -> String {
     String::from("%Y-%m-%d_%H-%M.mp4")
 }
 
+#[inline]
+fn default_folder_layout() -> String {
+    // all recordings directly in the recordings folder
+    String::new()
+}
+
 #[inline]
 fn default_framerate() -> Framerate {
     Framerate::new(30, 1)
@@ -279,10 +400,19 @@ impl Default for SettingsInner {
             debug_log: DEFAULT_DEBUG_LOG,
             recordings_folder: default_recordings_folder(),
             filename_format: default_filename_format(),
+            folder_layout: default_folder_layout(),
             encoding_quality: DEFAULT_ENCODING_QUALITY,
             output_resolution: None,
             framerate: default_framerate(),
             record_audio: DEFAULT_RECORD_AUDIO,
+            recording_backend: BackendKind::default(),
+            ffmpeg_source: FfmpegSource::default(),
+            replay_buffer: ReplayBufferSettings::default(),
+            highlights: HighlightSettings::default(),
+            mp4_chapters: DEFAULT_MP4_CHAPTERS,
+            retention: RetentionSettings::default(),
+            trash_retention_days: DEFAULT_TRASH_RETENTION_DAYS,
+            min_free_space_gb: DEFAULT_MIN_FREE_SPACE_GB,
             autostart: false,
         }
     }
@@ -326,6 +456,9 @@ impl<'de> Deserialize<'de> for SettingsInner {
                         "filenameFormat" => {
                             settings.filename_format = map.next_value().unwrap_or_else(|_| default_filename_format());
                         }
+                        "folderLayout" => {
+                            settings.folder_layout = map.next_value().unwrap_or_else(|_| default_folder_layout());
+                        }
                         "encodingQuality" => {
                             settings.encoding_quality = map.next_value().unwrap_or(DEFAULT_ENCODING_QUALITY);
                         }
@@ -338,6 +471,30 @@ impl<'de> Deserialize<'de> for SettingsInner {
                         "recordAudio" => {
                             settings.record_audio = map.next_value().unwrap_or(DEFAULT_RECORD_AUDIO);
                         }
+                        "recordingBackend" => {
+                            settings.recording_backend = map.next_value().unwrap_or_default();
+                        }
+                        "ffmpegSource" => {
+                            settings.ffmpeg_source = map.next_value().unwrap_or_default();
+                        }
+                        "replayBuffer" => {
+                            settings.replay_buffer = map.next_value().unwrap_or_default();
+                        }
+                        "highlights" => {
+                            settings.highlights = map.next_value().unwrap_or_default();
+                        }
+                        "mp4Chapters" => {
+                            settings.mp4_chapters = map.next_value().unwrap_or(DEFAULT_MP4_CHAPTERS);
+                        }
+                        "retention" => {
+                            settings.retention = map.next_value().unwrap_or_default();
+                        }
+                        "trashRetentionDays" => {
+                            settings.trash_retention_days = map.next_value().unwrap_or(DEFAULT_TRASH_RETENTION_DAYS);
+                        }
+                        "minFreeSpaceGb" => {
+                            settings.min_free_space_gb = map.next_value().unwrap_or(DEFAULT_MIN_FREE_SPACE_GB);
+                        }
                         "autostart" => {
                             settings.autostart = map.next_value().unwrap_or(DEFAULT_AUTOSTART);
                         }
@@ -0,0 +1,287 @@
+/*
+    Aggregated statistics (winrates, KDA, CS/min and vision score) over the recorded games in the library index.
+*/
+
+use std::{cmp::Reverse, collections::BTreeMap, fs, path::Path};
+
+use chrono::{Local, TimeZone};
+use serde::{Deserialize, Serialize};
+
+use crate::library::{GameRecord, GameStats, Library};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StatisticsQuery {
+    /// unix timestamp in milliseconds
+    pub from: Option<i64>,
+    /// unix timestamp in milliseconds
+    pub to: Option<i64>,
+    /// length of the periods of the trend
+    pub interval: Interval,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Interval {
+    Day,
+    #[default]
+    Week,
+    Month,
+}
+
+impl Interval {
+    /// name of the period (in local time) the timestamp belongs to - sorts the same as the periods
+    fn period(self, timestamp: i64) -> String {
+        let format = match self {
+            Interval::Day => "%Y-%m-%d",
+            Interval::Week => "%G-W%V",
+            Interval::Month => "%Y-%m",
+        };
+        Local
+            .timestamp_millis_opt(timestamp)
+            .single()
+            .map(|time| time.format(format).to_string())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Statistics {
+    pub total: Summary,
+    /// most played first
+    pub champions: Vec<Group>,
+    /// most played first
+    pub game_modes: Vec<Group>,
+    /// one entry for every period that has games (oldest first)
+    pub trend: Vec<Group>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Group {
+    pub name: String,
+    #[serde(flatten)]
+    pub summary: Summary,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Summary {
+    pub games: u64,
+    pub wins: u64,
+    pub losses: u64,
+    /// wins / (wins + losses) - `None` if there is no game with a known result
+    pub winrate: Option<f64>,
+    /// average KDA of the games with post game stats
+    pub kda: Option<f64>,
+    /// only includes games with post game stats and a known duration
+    pub cs_per_minute: Option<f64>,
+    /// average vision score of the games with post game stats
+    pub vision_score: Option<f64>,
+}
+
+#[derive(Default)]
+struct Accumulator {
+    games: u64,
+    wins: u64,
+    losses: u64,
+    /// games with post game stats
+    with_stats: u64,
+    kda: f64,
+    vision_score: f64,
+    creep_score: u64,
+    minutes: f64,
+}
+
+impl Accumulator {
+    fn add(&mut self, game: &GameRecord) {
+        self.games += 1;
+        match game.win {
+            Some(true) => self.wins += 1,
+            Some(false) => self.losses += 1,
+            None => {}
+        }
+        let Some(stats) = &game.stats else {
+            return;
+        };
+        self.with_stats += 1;
+        self.kda += kda(stats);
+        self.vision_score += stats.vision_score;
+        if let Some(duration) = game.duration.filter(|duration| *duration > 0.0) {
+            self.creep_score += stats.creep_score;
+            self.minutes += duration / 60.0;
+        }
+    }
+
+    fn summary(&self) -> Summary {
+        let with_stats = self.with_stats as f64;
+        let with_result = (self.wins + self.losses) as f64;
+        Summary {
+            games: self.games,
+            wins: self.wins,
+            losses: self.losses,
+            winrate: (with_result > 0.0).then(|| self.wins as f64 / with_result),
+            kda: (with_stats > 0.0).then(|| self.kda / with_stats),
+            cs_per_minute: (self.minutes > 0.0).then(|| self.creep_score as f64 / self.minutes),
+            vision_score: (with_stats > 0.0).then(|| self.vision_score / with_stats),
+        }
+    }
+}
+
+pub fn statistics(library: &Library, query: &StatisticsQuery) -> Statistics {
+    let mut total = Accumulator::default();
+    let mut champions = BTreeMap::<String, Accumulator>::new();
+    let mut game_modes = BTreeMap::<String, Accumulator>::new();
+    let mut trend = BTreeMap::<String, Accumulator>::new();
+    for game in library.games(query.from, query.to) {
+        total.add(&game);
+        champions.entry(game.champion.clone()).or_default().add(&game);
+        game_modes.entry(game.game_mode.clone()).or_default().add(&game);
+        trend.entry(query.interval.period(game.created)).or_default().add(&game);
+    }
+
+    let most_played = |groups: BTreeMap<String, Accumulator>| {
+        let mut groups = into_groups(groups);
+        groups.sort_by_key(|group| Reverse(group.summary.games));
+        groups
+    };
+    Statistics {
+        total: total.summary(),
+        champions: most_played(champions),
+        game_modes: most_played(game_modes),
+        trend: into_groups(trend),
+    }
+}
+
+/// Writes the games (one line per game) with the values the statistics are calculated from into a CSV file in the
+/// recordings folder. Returns the filename of the CSV file.
+pub fn export_csv(library: &Library, rec_folder: &Path, query: &StatisticsQuery) -> Result<String, String> {
+    let mut csv = String::from(
+        "date,champion,game mode,result,kills,deaths,assists,kda,creep score,cs per minute,vision score,duration,recording\n",
+    );
+    for game in library.games(query.from, query.to) {
+        let date = Local
+            .timestamp_millis_opt(game.created)
+            .single()
+            .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        let result = match game.win {
+            Some(true) => "win",
+            Some(false) => "loss",
+            None => "",
+        };
+        let duration = game
+            .duration
+            .map(|duration| format!("{duration:.0}"))
+            .unwrap_or_default();
+
+        // the stat fields stay empty if there are no post game stats
+        let stats = match &game.stats {
+            Some(stats) => {
+                let cs_per_minute = game
+                    .duration
+                    .filter(|duration| *duration > 0.0)
+                    .map(|duration| format!("{:.2}", stats.creep_score as f64 / (duration / 60.0)))
+                    .unwrap_or_default();
+                [
+                    stats.kills.to_string(),
+                    stats.deaths.to_string(),
+                    stats.assists.to_string(),
+                    format!("{:.2}", kda(stats)),
+                    stats.creep_score.to_string(),
+                    cs_per_minute,
+                    format!("{:.1}", stats.vision_score),
+                ]
+            }
+            None => Default::default(),
+        };
+
+        let mut fields = vec![
+            date,
+            csv_field(&game.champion),
+            csv_field(&game.game_mode),
+            result.to_string(),
+        ];
+        fields.extend(stats);
+        fields.extend([duration, csv_field(&game.filename)]);
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+
+    let filename = format!("Statistics_{}.csv", Local::now().format("%Y-%m-%d_%H-%M-%S"));
+    fs::write(rec_folder.join(&filename), csv).map_err(|e| e.to_string())?;
+    log::info!("exported statistics to {filename}");
+    Ok(filename)
+}
+
+/// (kills + assists) / deaths - with at least 1 death to avoid dividing by 0
+fn kda(stats: &GameStats) -> f64 {
+    (stats.kills + stats.assists) as f64 / stats.deaths.max(1) as f64
+}
+
+fn into_groups(groups: BTreeMap<String, Accumulator>) -> Vec<Group> {
+    groups
+        .into_iter()
+        .map(|(name, accumulator)| Group {
+            name,
+            summary: accumulator.summary(),
+        })
+        .collect()
+}
+
+/// quote the field if it contains characters that have a special meaning in CSV
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn game(win: Option<bool>, stats: Option<GameStats>) -> GameRecord {
+        GameRecord {
+            filename: String::from("game.mp4"),
+            created: 0,
+            champion: String::from("Ahri"),
+            game_mode: String::from("CLASSIC"),
+            win,
+            stats,
+            duration: Some(600.0),
+        }
+    }
+
+    #[test]
+    fn leaves_games_without_stats_out_of_the_averages() {
+        let stats = GameStats {
+            kills: 5,
+            deaths: 2,
+            assists: 7,
+            creep_score: 80,
+            vision_score: 20.0,
+        };
+        let mut accumulator = Accumulator::default();
+        accumulator.add(&game(Some(true), Some(stats)));
+        accumulator.add(&game(Some(false), None));
+        accumulator.add(&game(None, None));
+
+        let summary = accumulator.summary();
+        assert_eq!((summary.games, summary.wins, summary.losses), (3, 1, 1));
+        assert_eq!(summary.winrate, Some(0.5));
+        assert_eq!(summary.kda, Some(6.0));
+        assert_eq!(summary.cs_per_minute, Some(8.0));
+        assert_eq!(summary.vision_score, Some(20.0));
+
+        let mut accumulator = Accumulator::default();
+        accumulator.add(&game(Some(true), None));
+        let summary = accumulator.summary();
+        assert_eq!(
+            (summary.kda, summary.cs_per_minute, summary.vision_score),
+            (None, None, None)
+        );
+    }
+}
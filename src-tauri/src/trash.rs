@@ -0,0 +1,337 @@
+/*
+    Recycle bin for deleted recordings.
+    Deleted recordings (video and metadata file) get moved into a `.trash` folder inside the recordings folder.
+    The time of the deletion is the prefix of their filename in the trash: `{unix timestamp in ms}_{filename}`.
+    A `.location` file next to them remembers the subfolder they got deleted from, so they can be restored there.
+*/
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+use tauri::{AppHandle, Manager};
+
+use crate::{
+    helpers::{self, unix_millis},
+    recorder::recovery::unique_path,
+    state::Settings,
+};
+
+pub const TRASH_FOLDER: &str = ".trash";
+/// extension of the file that contains the name of the recording before it got deleted (see `helpers::recording_name`)
+const LOCATION_EXTENSION: &str = "location";
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashItem {
+    /// filename of the video in the trash
+    pub id: String,
+    /// name of the recording (relative to the recordings folder) before it got deleted
+    pub filename: String,
+    /// unix timestamp in milliseconds
+    pub deleted: i64,
+    /// in bytes
+    pub size: u64,
+}
+
+/// Purges old recordings from the trash at startup and after every game.
+pub fn start(app_handle: &AppHandle) {
+    purge_in_background(app_handle);
+    app_handle.listen_global("recording_finished", {
+        let app_handle = app_handle.clone();
+        move |_| purge_in_background(&app_handle)
+    });
+}
+
+fn purge_in_background(app_handle: &AppHandle) {
+    let settings = app_handle.state::<Settings>();
+    let rec_folder = settings.get_recordings_path();
+    let days = settings.trash_retention_days();
+    thread::spawn(move || purge(&rec_folder, days));
+}
+
+/// Moves the recording and its metadata file into the trash.
+pub fn move_to_trash(rec_folder: &Path, recording: &Path) -> io::Result<()> {
+    let Some(filename) = recording.file_name().and_then(|f| f.to_str()) else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid recording path"));
+    };
+    let trash = rec_folder.join(TRASH_FOLDER);
+    fs::create_dir_all(&trash)?;
+
+    // recordings with the same name from different folders can be deleted at the same time
+    let trashed = unique_path(
+        &trash.join(format!("{}_{filename}", unix_millis(SystemTime::now()))),
+        |path| path.exists() || path.with_extension(LOCATION_EXTENSION).exists(),
+    );
+    let location = trashed.with_extension(LOCATION_EXTENSION);
+    let name = helpers::recording_name(rec_folder, recording).unwrap_or_else(|| filename.to_string());
+    fs::write(&location, name)?;
+    // if the video can't be moved keep the metadata where it is as well
+    if let Err(e) = fs::rename(recording, &trashed) {
+        _ = fs::remove_file(location);
+        return Err(e);
+    }
+    if let Err(e) = fs::rename(recording.with_extension("json"), trashed.with_extension("json")) {
+        if e.kind() != io::ErrorKind::NotFound {
+            log::warn!("unable to move metadata of {recording:?} to the trash: {e}");
+        }
+    }
+    log::info!("moved {recording:?} to the trash");
+    Ok(())
+}
+
+/// The recordings in the trash (most recently deleted first).
+pub fn list(rec_folder: &Path) -> Vec<TrashItem> {
+    let Ok(entries) = fs::read_dir(rec_folder.join(TRASH_FOLDER)) else {
+        return Vec::new();
+    };
+
+    let mut items: Vec<TrashItem> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "mp4") {
+                return None;
+            }
+            let id = path.file_name()?.to_str()?.to_string();
+            let (deleted, _) = id.split_once('_')?;
+            Some(TrashItem {
+                filename: original_name(&path)?,
+                deleted: deleted.parse().ok()?,
+                size: entry.metadata().ok()?.len(),
+                id,
+            })
+        })
+        .collect();
+    items.sort_by_key(|item| -item.deleted);
+    items
+}
+
+/// Moves a recording from the trash back to where it got deleted from. Returns the path of the restored recording.
+pub fn restore(rec_folder: &Path, id: &str) -> Result<PathBuf, String> {
+    let item = trashed_item(rec_folder, id)?;
+    let name = original_name(&item).ok_or_else(|| format!("invalid trash item '{id}'"))?;
+    let Some(recording) = helpers::recording_path(rec_folder, &name) else {
+        return Err(format!("invalid location '{name}' of trash item '{id}'"));
+    };
+    if recording.exists() {
+        return Err(format!("a recording with the name '{name}' already exists"));
+    }
+    if let Some(folder) = recording.parent() {
+        fs::create_dir_all(folder).map_err(|e| e.to_string())?;
+    }
+
+    // move the metadata first so the recording shows up with its metadata
+    let metadata = item.with_extension("json");
+    if metadata.exists() {
+        fs::rename(&metadata, recording.with_extension("json")).map_err(|e| e.to_string())?;
+    }
+    fs::rename(&item, &recording).map_err(|e| e.to_string())?;
+    _ = fs::remove_file(item.with_extension(LOCATION_EXTENSION));
+    log::info!("restored {recording:?} from the trash");
+    Ok(recording)
+}
+
+/// Deletes everything in the trash for good. Returns the number of deleted recordings.
+pub fn empty(rec_folder: &Path) -> usize {
+    let items = list(rec_folder);
+    items.iter().filter(|item| delete_item(rec_folder, &item.id)).count()
+}
+
+/// Deletes the recordings that have been in the trash for more than `days` days.
+pub fn purge(rec_folder: &Path, days: u32) {
+    let max_age = Duration::from_secs(u64::from(days) * 24 * 60 * 60).as_millis() as i64;
+    let now = unix_millis(SystemTime::now());
+    let purged = list(rec_folder)
+        .iter()
+        .filter(|item| now - item.deleted > max_age)
+        .filter(|item| delete_item(rec_folder, &item.id))
+        .count();
+    if purged > 0 {
+        log::info!("purged {purged} recordings from the trash");
+    }
+}
+
+fn delete_item(rec_folder: &Path, id: &str) -> bool {
+    let Ok(item) = trashed_item(rec_folder, id) else {
+        return false;
+    };
+    match fs::remove_file(&item) {
+        Ok(_) => {
+            _ = fs::remove_file(item.with_extension("json"));
+            _ = fs::remove_file(item.with_extension(LOCATION_EXTENSION));
+            true
+        }
+        Err(e) => {
+            log::warn!("unable to delete {item:?} from the trash: {e}");
+            false
+        }
+    }
+}
+
+/// The name of the recording before it got deleted. Items that were deleted before the location was remembered
+/// go back into the top level of the recordings folder.
+fn original_name(item: &Path) -> Option<String> {
+    match fs::read_to_string(item.with_extension(LOCATION_EXTENSION)) {
+        Ok(name) => Some(name),
+        Err(_) => {
+            let (_, filename) = item.file_name()?.to_str()?.split_once('_')?;
+            Some(filename.to_string())
+        }
+    }
+}
+
+/// path of an item in the trash - the id must not point anywhere outside of the trash
+fn trashed_item(rec_folder: &Path, id: &str) -> Result<PathBuf, String> {
+    let is_filename = Path::new(id).file_name().is_some_and(|filename| filename == id);
+    let has_timestamp = id
+        .split_once('_')
+        .is_some_and(|(deleted, _)| deleted.parse::<i64>().is_ok());
+    if !is_filename || !has_timestamp || !id.ends_with(".mp4") {
+        return Err(format!("invalid trash item '{id}'"));
+    }
+    let item = rec_folder.join(TRASH_FOLDER).join(id);
+    if !item.is_file() {
+        return Err(format!("'{id}' is not in the trash"));
+    }
+    Ok(item)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("league_record_test_{name}_{}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write_recording(recording: &Path, content: &str) {
+        fs::create_dir_all(recording.parent().unwrap()).unwrap();
+        fs::write(recording, content).unwrap();
+        fs::write(recording.with_extension("json"), format!("{{\"video\":\"{content}\"}}")).unwrap();
+    }
+
+    fn trash_files(rec_folder: &Path) -> usize {
+        fs::read_dir(rec_folder.join(TRASH_FOLDER)).unwrap().count()
+    }
+
+    #[test]
+    fn restores_recordings_into_their_folders() {
+        let rec_folder = test_dir("trash_restore");
+        let (first, second) = (rec_folder.join("2024/05/game.mp4"), rec_folder.join("2024/06/game.mp4"));
+        write_recording(&first, "first");
+        write_recording(&second, "second");
+
+        move_to_trash(&rec_folder, &first).unwrap();
+        move_to_trash(&rec_folder, &second).unwrap();
+        assert!(!first.exists() && !first.with_extension("json").exists() && !second.exists());
+
+        let items = list(&rec_folder);
+        let mut filenames: Vec<&str> = items.iter().map(|item| item.filename.as_str()).collect();
+        filenames.sort();
+        assert_eq!(filenames, ["2024/05/game.mp4", "2024/06/game.mp4"]);
+
+        // the folder of a recording can be gone by the time it gets restored
+        fs::remove_dir(first.parent().unwrap()).unwrap();
+        for item in &items {
+            restore(&rec_folder, &item.id).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&first).unwrap(), "first");
+        assert_eq!(
+            fs::read_to_string(first.with_extension("json")).unwrap(),
+            r#"{"video":"first"}"#
+        );
+        assert_eq!(fs::read_to_string(&second).unwrap(), "second");
+        assert_eq!(
+            fs::read_to_string(second.with_extension("json")).unwrap(),
+            r#"{"video":"second"}"#
+        );
+        assert!(list(&rec_folder).is_empty());
+        assert_eq!(trash_files(&rec_folder), 0);
+        fs::remove_dir_all(rec_folder).unwrap();
+    }
+
+    #[test]
+    fn never_restores_over_an_existing_recording() {
+        let rec_folder = test_dir("trash_restore_existing");
+        let recording = rec_folder.join("game.mp4");
+        write_recording(&recording, "deleted");
+        move_to_trash(&rec_folder, &recording).unwrap();
+        write_recording(&recording, "new");
+
+        let id = &list(&rec_folder)[0].id;
+        assert!(restore(&rec_folder, id).is_err());
+        assert_eq!(fs::read_to_string(&recording).unwrap(), "new");
+        assert_eq!(
+            fs::read_to_string(recording.with_extension("json")).unwrap(),
+            r#"{"video":"new"}"#
+        );
+        assert_eq!(list(&rec_folder).len(), 1);
+        assert_eq!(trash_files(&rec_folder), 3);
+        fs::remove_dir_all(rec_folder).unwrap();
+    }
+
+    #[test]
+    fn purges_old_recordings() {
+        let rec_folder = test_dir("trash_purge");
+        let trash = rec_folder.join(TRASH_FOLDER);
+        let day = 24 * 60 * 60 * 1000;
+        let now = unix_millis(SystemTime::now());
+        write_recording(&trash.join(format!("{}_old.mp4", now - 8 * day)), "old");
+        write_recording(&trash.join(format!("{}_new.mp4", now - 6 * day)), "new");
+
+        purge(&rec_folder, 7);
+        let items = list(&rec_folder);
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0].filename, "new.mp4");
+        // the metadata of the purged recording is gone as well
+        assert_eq!(trash_files(&rec_folder), 2);
+
+        assert_eq!(empty(&rec_folder), 1);
+        assert_eq!(trash_files(&rec_folder), 0);
+        fs::remove_dir_all(rec_folder).unwrap();
+    }
+
+    #[test]
+    fn only_accepts_items_in_the_trash() {
+        let rec_folder = test_dir("trash_items");
+        let trash = rec_folder.join(TRASH_FOLDER);
+        write_recording(&rec_folder.join("x.mp4"), "outside");
+        write_recording(&trash.join("a/1_b.mp4"), "nested");
+        write_recording(&trash.join("game.mp4"), "no timestamp");
+        write_recording(&trash.join("1_game.mp4"), "valid");
+
+        for id in [
+            "../x.mp4",
+            "a/1_b.mp4",
+            "a/../1_game.mp4",
+            "game.mp4",
+            "1_game.json",
+            "2_game.mp4",
+            "",
+        ] {
+            assert!(trashed_item(&rec_folder, id).is_err(), "{id}");
+            assert!(restore(&rec_folder, id).is_err(), "{id}");
+        }
+        assert_eq!(
+            trashed_item(&rec_folder, "1_game.mp4").unwrap(),
+            trash.join("1_game.mp4")
+        );
+
+        // a location that points outside of the recordings folder
+        fs::write(trash.join("1_game.location"), "../game.mp4").unwrap();
+        assert!(restore(&rec_folder, "1_game.mp4").is_err());
+        assert!(!rec_folder.parent().unwrap().join("game.mp4").exists());
+        assert!(trash.join("1_game.mp4").exists() && rec_folder.join("x.mp4").exists());
+        fs::remove_dir_all(rec_folder).unwrap();
+    }
+}
@@ -24,6 +24,7 @@ const checkboxBaron = document.getElementById('baron');
 
 let fullscreen = false;
 let currentEvents = [];
+let currentAnnotations = [];
 // ------------------------------
 
 
@@ -132,6 +133,12 @@ addEventListener('focusin', event => {
 __TAURI__.event.listen('reload_recordings', partialReloadFiles);
 __TAURI__.event.listen('new_recording', partialReloadFiles);
 
+// listen for recordings that didn't start or got stopped because the recordings drive is full
+__TAURI__.event.listen('low_disk_space', event => showLowDiskSpaceModal(event.payload));
+
+// listen for recordings that couldn't be finished and got moved to the broken folder
+__TAURI__.event.listen('recording_broken', showBrokenRecordingsModal);
+
 // listen for settings change
 __TAURI__.event.listen('reload_ui', async () => {
     await init();
@@ -171,15 +178,43 @@ function resetPlayer() {
 }
 
 function showDeleteModal(video) {
-    let html = `<p>Do you really want to delete ${video}?</p>`;
+    const el = escape(video);
+    let html = `<p>Do you really want to delete ${el}?</p><p>It can be restored from the trash for a while.</p>`;
     html += '<p>';
-    html += `<button class="btn" onclick="hideModal();deleteVideo('${video}');">Yes</button>`;
+    html += `<button class="btn" onclick="hideModal();deleteVideo('${el}');">Yes</button>`;
     html += `<button class="btn" onclick="hideModal()">No</button>`;
     html += '</p>';
 
     showModal(html);
 }
 
+function showLowDiskSpaceModal({ freeGb, minFreeGb, recordingStopped }) {
+    let html = recordingStopped
+        ? '<p>The recording was stopped early because the drive of the recordings folder is almost full.</p>'
+        : "<p>The game wasn't recorded because the drive of the recordings folder is almost full.</p>";
+    html += `<p>${freeGb.toFixed(1)} GB free (at least ${minFreeGb} GB required).</p>`;
+    html += '<p><button class="btn" onclick="hideModal();">Close</button></p>';
+
+    showModal(html);
+}
+
+async function showBrokenRecordingsModal() {
+    const broken = await __TAURI__.invoke('take_broken_recordings');
+    if (broken.length === 0) return;
+
+    let html = broken.length === 1
+        ? "<p>A recording couldn't be finished"
+        : `<p>${broken.length} recordings couldn't be finished`;
+    html += " (e.g. because LeagueRecord crashed) and got moved to the 'broken' folder in the recordings folder:</p>";
+    html += `<p>${broken.map(escape).join('<br>')}</p>`;
+    html += '<p>';
+    html += '<button class="btn" onclick="hideModal();openRecordingsFolder();">Open Folder</button>';
+    html += '<button class="btn" onclick="hideModal();">Close</button>';
+    html += '</p>';
+
+    showModal(html);
+}
+
 function showModal(content) {
     modalContent.innerHTML = content;
     modal.style.display = 'block';
@@ -191,7 +226,9 @@ function hideModal() {
 
 async function getVideoPath(video) {
     let port = await __TAURI__.invoke('get_asset_port');
-    return `http://127.0.0.1:${port}/${video}`;
+    // recordings in subfolders have '/' in their name
+    const path = video.split('/').map(encodeURIComponent).join('/');
+    return `http://127.0.0.1:${port}/${path}`;
 }
 
 function openRecordingsFolder() {
@@ -200,7 +237,7 @@ function openRecordingsFolder() {
 
 async function getRecordingsNames() {
     console.log('get_recordings_list');
-    return (await __TAURI__.invoke('get_recordings_list')).map(escape);
+    return await __TAURI__.invoke('get_recordings_list');
 }
 
 async function setRecordingsSize() {
@@ -227,6 +264,7 @@ function setCurrentMarkerSettings(markers) {
 function clearData() {
     player.markers.removeAll();
     currentEvents = [];
+    currentAnnotations = [];
     descriptionLeft.innerHTML = '';
     descriptionCenter.innerHTML = 'No Data';
 }
@@ -247,6 +285,7 @@ function setVideo(name) {
         if (md) {
             try {
                 currentEvents = md['events'];
+                currentAnnotations = md['annotations'] ?? [];
 
                 const stats = md['stats'];
                 let descLeft = `<span class="summoner-name">${escape(md['gameInfo']['summonerName'])}</span><br>`;
@@ -265,6 +304,9 @@ function setVideo(name) {
         } else {
             clearData();
         }
+    }).catch(error => {
+        clearData();
+        descriptionCenter.innerHTML = 'No Data<br>' + escape(metadataErrorText(error));
     });
 
     getVideoPath(name).then(path => {
@@ -275,6 +317,23 @@ function setVideo(name) {
     });
 }
 
+function metadataErrorText(error) {
+    switch (error['kind']) {
+        case 'missing':
+            return 'no metadata for this recording';
+        case 'invalidRecording':
+            return 'not a recording in the recordings folder';
+        case 'corrupt':
+            return 'the metadata file is corrupt';
+        case 'unsupportedSchema':
+            return 'the metadata was written by a newer version of LeagueRecord';
+        case 'permissionDenied':
+            return 'no permission to read the metadata file';
+        default:
+            return error['details'] ?? '';
+    }
+}
+
 async function deleteVideo(video) {
     if (video === document.querySelector('.active')?.id) {
         // make sure the video is not in use before deleting it
@@ -284,16 +343,18 @@ async function deleteVideo(video) {
 
     let ok = await __TAURI__.invoke('delete_video', { 'video': video });
     if (!ok) {
-        let content = '<p>Error deleting video!</p>';
+        let content = '<p>Error deleting video!</p><p>Protected recordings can\'t be deleted.</p>';
         content += '<p><button class="btn" onclick="hideModal();">Close</button></p>';
         showModal(content);
     }
 }
 
-function createSidebarElement(el) {
+function createSidebarElement(name) {
+    // the attributes get unescaped again so the id is the name of the recording (which can contain '/')
+    const el = escape(name);
     // call event.stopPropagation(); to stop the onclick event from also effecting the element under the clicked X button
     let deleteBtn = `<span class="delete" onclick="event.stopPropagation();showDeleteModal('${el}')">&times;</span>`;
-    return `<li id="${el}" onclick="setVideo('${el}')">${escape(el.substring(0, el.length - 4))}${deleteBtn}</li>`;
+    return `<li id="${el}" onclick="setVideo('${el}')">${escape(name.substring(0, name.length - 4))}${deleteBtn}</li>`;
 }
 
 function changeMarkers() {
@@ -345,6 +406,15 @@ function changeMarkers() {
             });
         }
     });
+    // annotations of the user are always shown
+    currentAnnotations.forEach(a => {
+        arr.push({
+            'time': a['time'],
+            'text': escape(a['category'] ? `${a['category']}: ${a['text']}` : a['text']),
+            'class': 'annotation',
+            'duration': a['end'] != null ? a['end'] - a['time'] : 4
+        });
+    });
     player.markers.add(arr);
     setCurrentMarkerSettings({
         kill: checkboxKill.checked,
@@ -400,6 +470,7 @@ async function init() {
 
     await sleep(150); // delay so the initial blank screen when creating a window doesn't show
     await __TAURI__.invoke('show_app_window');
+    await showBrokenRecordingsModal();
 }
 
 // ------------------------------